@@ -0,0 +1,177 @@
+#!/usr/bin/env python3
+# Writes the LUKS test images. cryptsetup needs root and a device mapper, so
+# this follows the LUKS1 and LUKS2 on-disk specifications directly, with
+# seeded randomness so that rerunning it reproduces the committed files.
+import base64
+import hashlib
+import json
+import random
+import struct
+
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+from cryptography.hazmat.primitives.kdf.argon2 import Argon2id
+
+SECTOR = 512
+STRIPES = 4000
+rng = random.Random(47)
+
+
+def xts_encrypt(key, data, sector_size, first_iv=0):
+    out = b""
+    for index in range(len(data) // sector_size):
+        tweak = (first_iv + index).to_bytes(16, "little")
+        encryptor = Cipher(algorithms.AES(key), modes.XTS(tweak)).encryptor()
+        out += encryptor.update(data[index * sector_size:(index + 1) * sector_size])
+    return out
+
+
+def diffuse(data, hash_name):
+    size = hashlib.new(hash_name).digest_size
+    out = b""
+    for index in range(0, len(data), size):
+        chunk = data[index:index + size]
+        digest = hashlib.new(hash_name, struct.pack(">I", index // size) + chunk).digest()
+        out += digest[:len(chunk)]
+    return out
+
+
+def af_split(key, hash_name):
+    stripes = [rng.randbytes(len(key)) for _ in range(STRIPES - 1)]
+    d = bytes(len(key))
+    for stripe in stripes:
+        d = diffuse(bytes(a ^ b for a, b in zip(d, stripe)), hash_name)
+    stripes.append(bytes(a ^ b for a, b in zip(d, key)))
+    material = b"".join(stripes)
+    return material + bytes(-len(material) % SECTOR)
+
+
+def padded(text, length):
+    return text.encode().ljust(length, b"\0")
+
+
+def luks1(password, payload):
+    key_bytes, hash_name = 32, "sha1"
+    master = rng.randbytes(key_bytes)
+    digest_salt, digest_iterations = rng.randbytes(32), 1000
+    digest = hashlib.pbkdf2_hmac(hash_name, master, digest_salt, digest_iterations, 20)
+
+    salt, iterations, material_offset = rng.randbytes(32), 1000, 8
+    slot_key = hashlib.pbkdf2_hmac(hash_name, password, salt, iterations, key_bytes)
+    material = xts_encrypt(slot_key, af_split(master, hash_name), SECTOR)
+    payload_offset = material_offset + len(material) // SECTOR
+    payload_offset += -payload_offset % 8
+
+    header = b"LUKS\xba\xbe" + struct.pack(">H", 1)
+    header += padded("aes", 32) + padded("xts-plain64", 32) + padded(hash_name, 32)
+    header += struct.pack(">II", payload_offset, key_bytes)
+    header += digest + digest_salt + struct.pack(">I", digest_iterations)
+    header += padded("2f3a2c2e-1d1b-4a47-8e6b-5c0a1d6f7e01", 40)
+    header += struct.pack(">II", 0x00AC71F3, iterations) + salt
+    header += struct.pack(">II", material_offset, STRIPES)
+    for _ in range(7):
+        header += struct.pack(">II", 0x0000DEAD, 0) + bytes(32) + struct.pack(">II", 0, STRIPES)
+
+    image = bytearray(payload_offset * SECTOR)
+    image[:len(header)] = header
+    start = material_offset * SECTOR
+    image[start:start + len(material)] = material
+    return bytes(image) + xts_encrypt(master, payload, SECTOR)
+
+
+def luks2(passwords, payload):
+    key_bytes, hash_name, sector_size = 32, "sha256", 4096
+    header_size, area_size = 16384, 131072
+    master = rng.randbytes(key_bytes)
+
+    keyslots, areas = {}, []
+    for index, (password, kdf_type) in enumerate(passwords):
+        salt = rng.randbytes(32)
+        if kdf_type == "pbkdf2":
+            kdf = {"type": "pbkdf2", "hash": hash_name, "iterations": 1000}
+            slot_key = hashlib.pbkdf2_hmac(hash_name, password, salt, 1000, key_bytes)
+        else:
+            kdf = {"type": "argon2id", "time": 2, "memory": 64, "cpus": 1}
+            slot_key = Argon2id(
+                salt=salt, length=key_bytes, iterations=2, lanes=1, memory_cost=64
+            ).derive(password)
+        kdf["salt"] = base64.b64encode(salt).decode()
+        offset = 2 * header_size + index * area_size
+        areas.append((offset, xts_encrypt(slot_key, af_split(master, hash_name), SECTOR)))
+        keyslots[str(index)] = {
+            "type": "luks2",
+            "key_size": key_bytes,
+            "af": {"type": "luks1", "stripes": STRIPES, "hash": hash_name},
+            "area": {
+                "type": "raw",
+                "offset": str(offset),
+                "size": str(area_size),
+                "encryption": "aes-xts-plain64",
+                "key_size": key_bytes,
+            },
+            "kdf": kdf,
+        }
+
+    segment_offset = 2 * header_size + len(passwords) * area_size
+    digest_salt = rng.randbytes(32)
+    digest = hashlib.pbkdf2_hmac(hash_name, master, digest_salt, 1000, 32)
+    metadata = {
+        "keyslots": keyslots,
+        "tokens": {},
+        "segments": {
+            "0": {
+                "type": "crypt",
+                "offset": str(segment_offset),
+                "size": "dynamic",
+                "iv_tweak": "0",
+                "encryption": "aes-xts-plain64",
+                "sector_size": sector_size,
+            }
+        },
+        "digests": {
+            "0": {
+                "type": "pbkdf2",
+                "keyslots": sorted(keyslots),
+                "segments": ["0"],
+                "hash": hash_name,
+                "iterations": 1000,
+                "salt": base64.b64encode(digest_salt).decode(),
+                "digest": base64.b64encode(digest).decode(),
+            }
+        },
+        "config": {
+            "json_size": str(header_size - 4096),
+            "keyslots_size": str(len(passwords) * area_size),
+        },
+    }
+    area = json.dumps(metadata).encode().ljust(header_size - 4096, b"\0")
+
+    salt = rng.randbytes(64)
+    image = bytearray(segment_offset)
+    for magic, offset in [(b"LUKS\xba\xbe", 0), (b"SKUL\xba\xbe", header_size)]:
+        binary = magic + struct.pack(">HQQ", 2, header_size, 1) + bytes(48)
+        binary += padded("sha256", 32) + salt
+        binary += padded("6b1f0d2a-3c4e-4f58-9a7b-8c9d0e1f2a3b", 40) + bytes(48)
+        binary += struct.pack(">Q", offset) + bytes(184)
+        blank = binary + bytes(4096 - len(binary))
+        checksum = hashlib.sha256(blank + area).digest()
+        full = binary + checksum.ljust(64, b"\0")
+        full += bytes(4096 - len(full)) + area
+        image[offset:offset + header_size] = full
+    for offset, material in areas:
+        image[offset:offset + len(material)] = material
+    return bytes(image) + xts_encrypt(master, payload, sector_size)
+
+
+def main():
+    lines = [f"Sector data line {index:03}, padded to a fixed width.\n" for index in range(175)]
+    payload = "".join(lines).encode()[:8192]
+    with open("payload.txt", "wb") as file:
+        file.write(payload)
+    with open("luks1.img", "wb") as file:
+        file.write(luks1(b"correct horse", payload[:2048]))
+    with open("luks2.img", "wb") as file:
+        file.write(luks2([(b"correct horse", "pbkdf2"), (b"battery staple", "argon2id")], payload))
+
+
+if __name__ == "__main__":
+    main()
@@ -3,6 +3,7 @@ extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq)]
 pub enum AesSize {
     S128,
     S192,
@@ -28,4 +29,16 @@ impl AesSize {
             _ => Err(format!("Invalid AES size: {}", size)),
         }
     }
+
+    pub fn bits(&self) -> u16 {
+        match self {
+            AesSize::S128 => 128,
+            AesSize::S192 => 192,
+            AesSize::S256 => 256,
+        }
+    }
+
+    pub fn key_len(&self) -> usize {
+        self.bits() as usize / 8
+    }
 }
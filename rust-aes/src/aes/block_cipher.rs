@@ -0,0 +1,79 @@
+use super::{
+    decrypt_func, encrypt_func,
+    key::Key,
+    key_schedule::{key_schedule, legacy_key_schedule},
+};
+
+pub struct BlockCipher {
+    round_keys: Vec<Key>,
+}
+
+impl BlockCipher {
+    pub fn new(cipher: &Key) -> Self {
+        BlockCipher {
+            round_keys: key_schedule(cipher),
+        }
+    }
+
+    // Only for AES-192/256 data written before the key schedule fix.
+    pub fn with_legacy_schedule(cipher: &Key) -> Self {
+        BlockCipher {
+            round_keys: legacy_key_schedule(cipher),
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+        let state = Key::from_bytes(block);
+        let encrypted = encrypt_func::encrypt_block(&state, &self.round_keys);
+        to_block(&encrypted)
+    }
+
+    pub fn decrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+        let state = Key::from_bytes(block);
+        let decrypted = decrypt_func::decrypt_block(&state, &self.round_keys);
+        to_block(&decrypted)
+    }
+}
+
+fn to_block(state: &Key) -> [u8; 16] {
+    let mut block = [0; 16];
+    block.copy_from_slice(&state.to_bytes());
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+
+    #[test]
+    fn fips197_block() {
+        let key = Key::from_bytes(&from_hex("000102030405060708090a0b0c0d0e0f").unwrap());
+        let cipher = BlockCipher::new(&key);
+        let mut plaintext = [0; 16];
+        plaintext.copy_from_slice(&from_hex("00112233445566778899aabbccddeeff").unwrap());
+        let ciphertext = cipher.encrypt_block(&plaintext);
+        assert_eq!(
+            ciphertext.to_vec(),
+            from_hex("69c4e0d86a7b0430d8cdb78070b4c55a").unwrap()
+        );
+        assert_eq!(cipher.decrypt_block(&ciphertext), plaintext);
+    }
+
+    // FIPS 197 appendix C.2 and C.3
+    #[test]
+    fn fips197_longer_keys() {
+        let mut plaintext = [0; 16];
+        plaintext.copy_from_slice(&from_hex("00112233445566778899aabbccddeeff").unwrap());
+        for (key_len, expected) in [
+            (24, "dda97ca4864cdfe06eaf70a0ec0d7191"),
+            (32, "8ea2b7ca516745bfeafc49904b496089"),
+        ] {
+            let key: Vec<u8> = (0..key_len).collect();
+            let cipher = BlockCipher::new(&Key::from_bytes(&key));
+            let ciphertext = cipher.encrypt_block(&plaintext);
+            assert_eq!(ciphertext.to_vec(), from_hex(expected).unwrap());
+            assert_eq!(cipher.decrypt_block(&ciphertext), plaintext);
+        }
+    }
+}
@@ -23,7 +23,7 @@ pub fn add_round_key(key: &mut Key, round_key: &Key) {
         let col_index = col_index as isize;
         let col = key.get_col(col_index);
         let round_col = round_key.get_col(col_index);
-        let mut new_col = [0 as u8; 4];
+        let mut new_col = [0_u8; 4];
         for row_index in 0..4 {
             let v1 = col[row_index];
             let v2 = round_col[row_index];
@@ -1,4 +1,7 @@
-use super::{key::{shift, inv_sub_bytes as inv_sub_bytes_key, Key}, crypt_func::{g_mul, add_round_key}};
+use super::{
+    crypt_func::{add_round_key, g_mul},
+    key::{inv_sub_bytes as inv_sub_bytes_key, shift, Key},
+};
 
 pub fn inv_sub_bytes(key: &mut Key) {
     key.apply_all(inv_sub_bytes_key);
@@ -10,7 +13,6 @@ pub fn inv_shift_rows(key: &mut Key) {
     key.apply_row(3, shift(3));
 }
 
-
 pub fn inv_mix_columns(key: &mut Key) {
     for col_index in 0..key.col_amount() {
         let col = key.get_col(col_index as isize);
@@ -24,7 +26,7 @@ pub fn inv_mix_columns(key: &mut Key) {
     }
 }
 
-pub fn decrypt_block(block: &Key, round_keys: &Vec<Key>) -> Key {
+pub fn decrypt_block(block: &Key, round_keys: &[Key]) -> Key {
     let mut block = block.clone();
     add_round_key(&mut block, &round_keys[round_keys.len() - 1]);
     inv_shift_rows(&mut block);
@@ -38,5 +40,3 @@ pub fn decrypt_block(block: &Key, round_keys: &Vec<Key>) -> Key {
     add_round_key(&mut block, &round_keys[0]);
     block
 }
-
-
@@ -29,11 +29,11 @@ pub fn mix_columns(key: &mut Key) {
 pub fn encrypt_block(block: &Key, round_keys: &[Key]) -> Key {
     let mut block = block.clone();
     add_round_key(&mut block, &round_keys[0]);
-    for round_index in 1..round_keys.len() - 1 {
+    for round_key in &round_keys[1..round_keys.len() - 1] {
         sub_bytes(&mut block);
         shift_rows(&mut block);
         mix_columns(&mut block);
-        add_round_key(&mut block, &round_keys[round_index]);
+        add_round_key(&mut block, round_key);
     }
     sub_bytes(&mut block);
     shift_rows(&mut block);
@@ -1,10 +1,17 @@
 use std::fmt::Display;
 
+use crate::error::Error;
+use crate::hash::Sha256;
+use crate::kdf::hkdf;
+use crate::rng::RngSource;
+
 use super::{
     aes_size::AesSize,
     sbox::{INV_SBOX, SBOX},
 };
 
+pub const CHECK_LEN: usize = 8;
+
 pub struct Key {
     pub size: AesSize,
     pub key: Vec<[u8; 4]>,
@@ -31,7 +38,7 @@ impl Display for Key {
             for byte in row.iter() {
                 text.push_str(&format!("{:02x} ", byte));
             }
-            text.push_str("\n");
+            text.push('\n');
         }
         write!(f, "{}", text)
     }
@@ -52,9 +59,7 @@ impl Key {
         let mut key = Vec::new();
         for col_index in 0..bytes.len() / 4 {
             let mut col = [0; 4];
-            for row_index in 0..4 {
-                col[row_index] = bytes[col_index * 4 + row_index];
-            }
+            col.copy_from_slice(&bytes[col_index * 4..col_index * 4 + 4]);
             key.push(col);
         }
         Key::from_vec(key)
@@ -64,9 +69,7 @@ impl Key {
         let mut bytes = Vec::new();
         for col_index in 0..self.col_amount() {
             let col = self.get_col(col_index as isize);
-            for row_index in 0..4 {
-                bytes.push(col[row_index]);
-            }
+            bytes.extend_from_slice(&col);
         }
         bytes
     }
@@ -91,10 +94,10 @@ impl Key {
         let col_chunks = password_bytes.chunks(4).collect::<Vec<&[u8]>>();
         let repeat_chunks = col_chunks.chunks(col_amount);
         for repeat in repeat_chunks {
-            for col_index in 0..repeat.len() {
+            for (col_index, chunk) in repeat.iter().enumerate() {
                 let mut col = key.get_col(col_index as isize);
-                for row_index in 0..repeat[col_index].len() {
-                    col[row_index] ^= repeat[col_index][row_index];
+                for (row_index, byte) in chunk.iter().enumerate() {
+                    col[row_index] ^= byte;
                 }
                 key.set_col(col_index, col);
             }
@@ -102,6 +105,29 @@ impl Key {
         key
     }
 
+    pub fn random(size: AesSize, rng: &mut dyn RngSource) -> Result<Self, Error> {
+        Ok(Key::from_bytes(&rng.random_vec(size.key_len())?))
+    }
+
+    // HKDF-SHA256 with the master key as input keying material. The key size
+    // is appended to the info label so subkeys of different sizes are unrelated.
+    pub fn derive(&self, info: &[u8], size: AesSize) -> Self {
+        let mut label = info.to_vec();
+        label.extend_from_slice(&size.bits().to_be_bytes());
+        let okm = hkdf::derive::<Sha256>(&[], &self.to_bytes(), &label, size.key_len())
+            .expect("AES key length is within HKDF limits");
+        Key::from_bytes(&okm)
+    }
+
+    // Identifies the key without revealing it, so ciphertext can carry it
+    // and a wrong password or key size is caught before decrypting.
+    pub fn check_value(&self) -> [u8; CHECK_LEN] {
+        let derived = self.derive(b"aeslib key check", AesSize::S128).to_bytes();
+        let mut check = [0; CHECK_LEN];
+        check.copy_from_slice(&derived[..CHECK_LEN]);
+        check
+    }
+
     pub fn col_amount(&self) -> usize {
         self.validate_key();
         match self.size {
@@ -133,8 +159,8 @@ impl Key {
         if new_row.len() != self.key.len() {
             panic!("Invalid row length");
         }
-        for i in 0..self.key.len() {
-            self.key[i][row_index] = new_row[i];
+        for (col, byte) in self.key.iter_mut().zip(new_row) {
+            col[row_index] = byte;
         }
     }
 
@@ -144,8 +170,8 @@ impl Key {
         if new_col.len() != self.key.len() {
             panic!("Invalid col length");
         }
-        for i in 0..self.key.len() {
-            self.key[i][index] = new_col[i];
+        for (col, byte) in self.key.iter_mut().zip(new_col) {
+            col[index] = byte;
         }
     }
 
@@ -180,8 +206,8 @@ impl Key {
         if row.len() != self.key.len() {
             panic!("Invalid row length");
         }
-        for i in 0..self.key.len() {
-            self.key[i][u_row_index] = row[i];
+        for (col, byte) in self.key.iter_mut().zip(row) {
+            col[u_row_index] = byte;
         }
     }
 
@@ -189,9 +215,7 @@ impl Key {
         if col.len() != 4 {
             panic!("Invalid col length");
         }
-        for i in 0..4 {
-            self.key[index][i] = col[i];
-        }
+        self.key[index].copy_from_slice(&col);
     }
 
     pub fn apply_all(&mut self, func: fn(&[u8]) -> Vec<u8>) {
@@ -210,7 +234,7 @@ impl Key {
 }
 
 pub fn shift(mut amount: i64) -> impl Fn(&[u8]) -> Vec<u8> {
-    let swap = if amount < 0 { true } else { false };
+    let swap = amount < 0;
     if swap {
         amount = -amount;
     }
@@ -258,4 +282,23 @@ mod tests {
         let key = Key::from_password("password", AesSize::S256);
         println!("{}", key);
     }
+
+    #[test]
+    fn derive_test() {
+        let master = Key::from_password("master password", AesSize::S256);
+        let encryption = master.derive(b"file.txt/encryption", AesSize::S128);
+        let mac = master.derive(b"file.txt/mac", AesSize::S256);
+        let other_file = master.derive(b"other.txt/encryption", AesSize::S128);
+        assert_eq!(encryption.to_bytes().len(), 16);
+        assert_eq!(mac.to_bytes().len(), 32);
+        assert_ne!(encryption.to_bytes(), other_file.to_bytes());
+        let again = master.derive(b"file.txt/encryption", AesSize::S128);
+        assert_eq!(encryption.to_bytes(), again.to_bytes());
+
+        let long = master.derive(b"label", AesSize::S256).to_bytes();
+        let short = master.derive(b"label", AesSize::S128).to_bytes();
+        assert_ne!(&long[..16], &short[..]);
+        let medium = master.derive(b"label", AesSize::S192);
+        assert!(matches!(medium.size, AesSize::S192));
+    }
 }
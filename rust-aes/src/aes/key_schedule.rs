@@ -1,7 +1,54 @@
-use super::{key::{Key, shift, sub_bytes}, rcon::RCON, aes_size::AesSize};
-
+use super::{
+    aes_size::AesSize,
+    key::{shift, sub_bytes, Key},
+    rcon::RCON,
+};
 
+// FIPS 197 section 5.2: expands the Nk key words into 4 * (rounds + 1)
+// words and hands them out as one 4-word key per round.
 pub fn key_schedule(cipher: &Key) -> Vec<Key> {
+    let rounds = match cipher.size {
+        AesSize::S128 => 10,
+        AesSize::S192 => 12,
+        AesSize::S256 => 14,
+    };
+    let col_amount = cipher.col_amount();
+    let shift1 = shift(-1);
+    let mut words: Vec<Vec<u8>> = (0..col_amount)
+        .map(|i| cipher.get_col(i as isize))
+        .collect();
+    for i in col_amount..4 * (rounds + 1) {
+        let mut word = words[i - 1].clone();
+        if i % col_amount == 0 {
+            let rcon = RCON.get(i / col_amount - 1);
+            word = sub_bytes(&shift1(&word));
+            for (byte, rcon_byte) in word.iter_mut().zip(rcon) {
+                *byte ^= rcon_byte;
+            }
+        } else if col_amount > 6 && i % col_amount == 4 {
+            word = sub_bytes(&word);
+        }
+        for (byte, offset_byte) in word.iter_mut().zip(&words[i - col_amount]) {
+            *byte ^= offset_byte;
+        }
+        words.push(word);
+    }
+    words
+        .chunks(4)
+        .map(|chunk| {
+            let mut key = vec![[0; 4]; 4];
+            for (col, word) in key.iter_mut().zip(chunk) {
+                col.copy_from_slice(word);
+            }
+            Key::from_vec(key)
+        })
+        .collect()
+}
+
+// The expansion of releases before the fix above. It derives each round key
+// from the whole previous Nk-word key, which matches FIPS 197 only for
+// AES-128, and is kept solely to read AES-192/256 data those releases wrote.
+pub fn legacy_key_schedule(cipher: &Key) -> Vec<Key> {
     let rounds = match cipher.size {
         AesSize::S128 => 10,
         AesSize::S192 => 12,
@@ -31,7 +78,7 @@ pub fn key_schedule(cipher: &Key) -> Vec<Key> {
             let mut next_col = key.get_col(i as isize - 1);
             let offset_col = previous_key.get_col(i as isize);
             for row_index in 0..next_col.len() {
-                next_col[row_index] = next_col[row_index] ^ offset_col[row_index];
+                next_col[row_index] ^= offset_col[row_index];
             }
             key.set_col(i, next_col);
         }
@@ -44,6 +91,36 @@ pub fn key_schedule(cipher: &Key) -> Vec<Key> {
 mod tests {
     use super::*;
 
+    fn last_round_key(key: &str) -> String {
+        let cipher = Key::from_bytes(&crate::encoding::from_hex(key).unwrap());
+        let keys = key_schedule(&cipher);
+        crate::encoding::to_hex(&keys.last().unwrap().to_bytes())
+    }
+
+    // FIPS 197 appendix A.2 and A.3: w[48..52] and w[56..60].
+    #[test]
+    fn longer_keys() {
+        assert_eq!(
+            last_round_key("8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b"),
+            "e98ba06f448c773c8ecc720401002202"
+        );
+        assert_eq!(
+            last_round_key("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"),
+            "fe4890d1e6188d0b046df344706c631e"
+        );
+    }
+
+    #[test]
+    fn legacy_matches_for_aes128() {
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        let fixed: Vec<_> = key_schedule(&cipher).into_iter().map(|k| k.key).collect();
+        let legacy: Vec<_> = legacy_key_schedule(&cipher)
+            .into_iter()
+            .map(|k| k.key)
+            .collect();
+        assert_eq!(fixed, legacy);
+    }
+
     #[test]
     fn key_schedule_test() {
         let cipher = Key {
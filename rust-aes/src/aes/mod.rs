@@ -1,9 +1,15 @@
-use std::string::FromUtf8Error;
+use crate::error::Error;
+use crate::hash::constant_time_eq;
+use crate::modes::etm::{EncryptThenMac, Mode};
+use crate::padding::{Padding, ZeroPadding};
+use crate::rng::RngSource;
 
 pub use self::aes_size::AesSize;
-pub use self::key::Key;
+pub use self::block_cipher::BlockCipher;
+pub use self::key::{Key, CHECK_LEN};
 
 mod aes_size;
+mod block_cipher;
 mod crypt_func;
 mod decrypt_func;
 mod encrypt_func;
@@ -12,46 +18,162 @@ mod key_schedule;
 mod rcon;
 mod sbox;
 
+// Checked output is CHECK_MAGIC | key check value | the ECB blocks. Its
+// length is never a multiple of 16, so it cannot be mistaken for raw output.
+pub const CHECK_MAGIC: &[u8; 4] = b"AESK";
+pub const CHECK_PREFIX_LEN: usize = CHECK_MAGIC.len() + CHECK_LEN;
+// Authenticated output is SEALED_MAGIC | key check value | encrypt-then-MAC
+// in CBC mode, with the first two fields as associated data. The magic must
+// differ from those of containers and envelopes.
+pub const SEALED_MAGIC: &[u8; 4] = b"AESA";
+
+// Legacy only: writes unauthenticated ECB, which decrypt still reads.
+#[deprecated(note = "writes unauthenticated ECB; use encrypt_authenticated")]
 pub fn encrypt(cipher: &Key, plaintext: &str) -> Result<Vec<u8>, String> {
-    if plaintext.find("\0").is_some() {
+    if plaintext.contains('\0') {
         return Err("Plaintext contains null byte".into());
     }
-    let round_keys = key_schedule::key_schedule(&cipher);
-    let blocks = plaintext.as_bytes().chunks(16);
-    let mut ciphertext = Vec::new();
-    for block in blocks {
-        let mut byte_block = block.to_vec();
-        for _ in block.len()..16 {
-            byte_block.push(0);
-        }
-        let block = Key::from_bytes(&byte_block);
-        let encrypted_block = encrypt_func::encrypt_block(&block, &round_keys);
-        ciphertext.extend_from_slice(&encrypted_block.to_bytes());
+    encrypt_with_padding(cipher, plaintext.as_bytes(), &mut ZeroPadding).map_err(|e| e.to_string())
+}
+
+// Takes authenticated output as well as the legacy ECB formats. AES-192/256
+// ECB from before the key schedule fix needs decrypt_legacy_schedule.
+pub fn decrypt(cipher: &Key, ciphertext: &[u8]) -> Result<String, Error> {
+    let plaintext = if is_sealed(ciphertext) {
+        decrypt_authenticated(cipher, ciphertext)?
+    } else {
+        decrypt_with_padding(cipher, ciphertext, &ZeroPadding)?
+    };
+    String::from_utf8(plaintext).map_err(|_| Error::InvalidUtf8)
+}
+
+pub fn encrypt_authenticated(
+    cipher: &Key,
+    plaintext: &[u8],
+    rng: &mut dyn RngSource,
+) -> Result<Vec<u8>, Error> {
+    let header = [SEALED_MAGIC.as_slice(), &cipher.check_value()].concat();
+    let sealed = EncryptThenMac::new(cipher, Mode::Cbc).seal(rng, &header, plaintext)?;
+    Ok([header, sealed].concat())
+}
+
+// Verifies the tag before anything is decrypted, so tampering fails with
+// Error::Authentication rather than producing garbage.
+pub fn decrypt_authenticated(cipher: &Key, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_sealed(sealed) {
+        return Err(Error::InvalidInput(
+            "not authenticated aeslib output".to_string(),
+        ));
+    }
+    let (header, sealed) = sealed.split_at(CHECK_PREFIX_LEN);
+    if !constant_time_eq(&header[SEALED_MAGIC.len()..], &cipher.check_value()) {
+        return Err(Error::WrongKey);
+    }
+    EncryptThenMac::new(cipher, Mode::Cbc).open(header, sealed)
+}
+
+// Sealed output is never a multiple of 16 bytes long, unlike raw ECB.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.len() % 16 == CHECK_PREFIX_LEN && data.starts_with(SEALED_MAGIC)
+}
+
+pub fn encrypt_with_padding(
+    cipher: &Key,
+    plaintext: &[u8],
+    padding: &mut dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    let block_cipher = BlockCipher::new(cipher);
+    let mut ciphertext = padding.pad(plaintext)?;
+    for chunk in ciphertext.chunks_mut(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        chunk.copy_from_slice(&block_cipher.encrypt_block(&block));
     }
     Ok(ciphertext)
 }
 
-pub fn decrypt(cipher: &Key, ciphertext: &[u8]) -> Result<String, FromUtf8Error> {
-    let round_keys = key_schedule::key_schedule(&cipher);
-    let blocks = ciphertext.chunks(16);
-    let mut plaintext = Vec::new();
-    for block in blocks {
-        let byte_block = block.to_vec();
-        let block = Key::from_bytes(&byte_block);
-        let decrypted_block = decrypt_func::decrypt_block(&block, &round_keys);
-        plaintext.extend_from_slice(&decrypted_block.to_bytes());
-    }
-    let string = String::from_utf8(plaintext)?;
-    let null_index = match string.find("\0") {
-        Some(index) => index,
-        None => string.len(),
-    };
-    Ok(string[..null_index].to_string())
+// As encrypt_with_padding, prefixed with the key check value so a wrong
+// password or key size fails with Error::WrongKey.
+pub fn encrypt_checked(
+    cipher: &Key,
+    plaintext: &[u8],
+    padding: &mut dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    let mut output = check_prefix(cipher);
+    output.extend(encrypt_with_padding(cipher, plaintext, padding)?);
+    Ok(output)
+}
+
+pub fn check_prefix(cipher: &Key) -> Vec<u8> {
+    [CHECK_MAGIC.as_slice(), &cipher.check_value()].concat()
+}
+
+// Verifies the check value of a checked prefix.
+pub fn verify_check_prefix(cipher: &Key, prefix: &[u8]) -> Result<(), Error> {
+    if prefix.len() != CHECK_PREFIX_LEN || !prefix.starts_with(CHECK_MAGIC) {
+        return Err(Error::InvalidInput("missing key check value".to_string()));
+    }
+    if !constant_time_eq(&prefix[CHECK_MAGIC.len()..], &cipher.check_value()) {
+        return Err(Error::WrongKey);
+    }
+    Ok(())
+}
+
+// Takes both raw and checked ciphertext.
+pub fn decrypt_with_padding(
+    cipher: &Key,
+    ciphertext: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    decrypt_blocks(&BlockCipher::new(cipher), cipher, ciphertext, padding)
+}
+
+// As decrypt_with_padding, for AES-192/256 data written by releases before
+// the key schedule fix. AES-128 is unaffected.
+pub fn decrypt_legacy_schedule(
+    cipher: &Key,
+    ciphertext: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    let block_cipher = BlockCipher::with_legacy_schedule(cipher);
+    decrypt_blocks(&block_cipher, cipher, ciphertext, padding)
+}
+
+fn decrypt_blocks(
+    block_cipher: &BlockCipher,
+    cipher: &Key,
+    ciphertext: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    let ciphertext =
+        if ciphertext.len() % 16 == CHECK_PREFIX_LEN && ciphertext.starts_with(CHECK_MAGIC) {
+            verify_check_prefix(cipher, &ciphertext[..CHECK_PREFIX_LEN])?;
+            &ciphertext[CHECK_PREFIX_LEN..]
+        } else {
+            ciphertext
+        };
+    if !ciphertext.len().is_multiple_of(16) {
+        return Err(Error::InvalidLength(format!(
+            "ciphertext must be a multiple of 16 bytes, got {}",
+            ciphertext.len()
+        )));
+    }
+    let mut plaintext = ciphertext.to_vec();
+    for chunk in plaintext.chunks_mut(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        chunk.copy_from_slice(&block_cipher.decrypt_block(&block));
+    }
+    padding.unpad(plaintext)
 }
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests {
     use super::*;
+    use crate::container::{self, envelope};
+    use crate::padding::Pkcs7Padding;
+    use crate::rng::SeededRng;
 
     #[test]
     fn crypt_test() {
@@ -104,4 +226,118 @@ mod tests {
         let decrypted_plaintext = decrypt(&cipher, &ciphertext);
         assert_eq!(plaintext, decrypted_plaintext.unwrap());
     }
+
+    #[test]
+    fn zero_padding_compat_test() {
+        // produced by the implicit zero padding of earlier releases
+        let ciphertext = vec![
+            122, 227, 195, 243, 35, 175, 214, 21, 53, 11, 216, 211, 32, 181, 225, 136,
+        ];
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        assert_eq!(encrypt(&cipher, "Hello, World!").unwrap(), ciphertext);
+        assert_eq!(
+            encrypt_with_padding(&cipher, b"Hello, World!", &mut ZeroPadding).unwrap(),
+            ciphertext
+        );
+        let plaintext = decrypt_with_padding(&cipher, &ciphertext, &ZeroPadding).unwrap();
+        assert_eq!(plaintext, b"Hello, World!");
+        assert_eq!(encrypt(&cipher, "").unwrap(), Vec::<u8>::new());
+        assert_eq!(encrypt(&cipher, "0123456789abcdef").unwrap().len(), 16);
+    }
+
+    #[test]
+    fn legacy_schedule_test() {
+        // aes::encrypt output of releases before the key schedule fix
+        let old_192 = vec![
+            82, 60, 136, 224, 246, 155, 63, 151, 110, 117, 62, 186, 187, 182, 142, 220, 135, 123,
+            13, 217, 158, 195, 248, 212, 236, 231, 194, 246, 134, 119, 242, 100, 76, 20, 140, 236,
+            84, 93, 243, 162, 6, 213, 33, 57, 169, 55, 208, 243,
+        ];
+        let old_256 = vec![
+            173, 196, 170, 203, 210, 162, 201, 108, 6, 40, 216, 67, 244, 174, 230, 186, 93, 137, 1,
+            10, 170, 164, 106, 200, 30, 39, 99, 62, 119, 135, 182, 99, 156, 232, 42, 93, 10, 186,
+            251, 89, 240, 133, 145, 105, 47, 43, 141, 121,
+        ];
+        let plaintext = b"Hello, World! Written by an old build.";
+        for (size, ciphertext) in [(AesSize::S192, old_192), (AesSize::S256, old_256)] {
+            let cipher = Key::from_password("Password1234", size);
+            let decrypted = decrypt_legacy_schedule(&cipher, &ciphertext, &ZeroPadding).unwrap();
+            assert_eq!(decrypted, plaintext);
+            let current = decrypt_with_padding(&cipher, &ciphertext, &ZeroPadding);
+            assert_ne!(current.as_deref(), Ok(&plaintext[..]));
+        }
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        let ciphertext = encrypt(&cipher, "Hello, World!").unwrap();
+        assert_eq!(
+            decrypt_legacy_schedule(&cipher, &ciphertext, &ZeroPadding).unwrap(),
+            b"Hello, World!"
+        );
+    }
+
+    #[test]
+    fn check_value_test() {
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        let checked = encrypt_checked(&cipher, b"Hello, World!", &mut ZeroPadding).unwrap();
+        assert_eq!(checked.len(), CHECK_PREFIX_LEN + 16);
+        assert_eq!(
+            &checked[CHECK_PREFIX_LEN..],
+            encrypt(&cipher, "Hello, World!").unwrap()
+        );
+        assert_eq!(decrypt(&cipher, &checked).unwrap(), "Hello, World!");
+
+        let wrong_password = Key::from_password("Password1235", AesSize::S128);
+        assert_eq!(decrypt(&wrong_password, &checked), Err(Error::WrongKey));
+        let wrong_size = Key::from_password("Password1234", AesSize::S256);
+        assert_eq!(decrypt(&wrong_size, &checked), Err(Error::WrongKey));
+        assert_ne!(cipher.check_value(), wrong_size.check_value());
+    }
+
+    #[test]
+    fn authenticated_test() {
+        let cipher = Key::from_password("Password1234", AesSize::S256);
+        let sealed =
+            encrypt_authenticated(&cipher, b"Hello, World!", &mut SeededRng::from_u64(1)).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(decrypt(&cipher, &sealed).unwrap(), "Hello, World!");
+        assert_eq!(
+            decrypt_authenticated(&cipher, &sealed).unwrap(),
+            b"Hello, World!"
+        );
+        for index in CHECK_PREFIX_LEN..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert_eq!(decrypt(&cipher, &tampered), Err(Error::Authentication));
+        }
+        let wrong_password = Key::from_password("Password1235", AesSize::S256);
+        assert_eq!(decrypt(&wrong_password, &sealed), Err(Error::WrongKey));
+        let legacy = encrypt(&cipher, "Hello, World!").unwrap();
+        assert!(!is_sealed(&legacy));
+        assert!(decrypt_authenticated(&cipher, &legacy).is_err());
+    }
+
+    #[test]
+    fn sealed_is_not_an_envelope() {
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        let sealed = encrypt_authenticated(&cipher, b"data", &mut SeededRng::from_u64(1)).unwrap();
+        assert!(!envelope::is_envelope(&sealed));
+        assert!(!container::is_container(&sealed));
+        let mut envelope = envelope::MAGIC.to_vec();
+        envelope.resize(sealed.len(), 0);
+        assert!(!is_sealed(&envelope));
+    }
+
+    #[test]
+    fn padding_test() {
+        let cipher = Key::from_password("Password1234", AesSize::S128);
+        let mut padding = Pkcs7Padding;
+        let plaintext = b"binary\0data\0\0";
+        let ciphertext = encrypt_with_padding(&cipher, plaintext, &mut padding).unwrap();
+        assert_eq!(ciphertext.len(), 16);
+        let decrypted = decrypt_with_padding(&cipher, &ciphertext, &padding).unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert!(matches!(
+            decrypt_with_padding(&cipher, &ciphertext[..15], &padding),
+            Err(Error::InvalidLength(_))
+        ));
+    }
 }
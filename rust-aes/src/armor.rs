@@ -0,0 +1,319 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::encoding::{from_base64, to_base64};
+use crate::error::Error;
+
+pub const LABEL: &str = "AES MESSAGE";
+pub const LINE_LEN: usize = 64;
+
+const CRC24_INIT: u32 = 0xb7_04ce;
+const CRC24_POLY: u32 = 0x186_4cfb;
+
+// The OpenPGP checksum (RFC 4880 6.1).
+pub fn crc24(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = crc;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xff_ffff
+}
+
+// Armor in the OpenPGP style (RFC 4880 6.2) without header lines:
+//   -----BEGIN <label>-----
+//   base64, 64 columns
+//   =<base64 of the CRC-24>
+//   -----END <label>-----
+pub fn encode(label: &str, data: &[u8]) -> String {
+    let mut writer = ArmorWriter::new(Vec::new(), label);
+    writer
+        .write_all(data)
+        .expect("writing to a Vec cannot fail");
+    let bytes = writer.finish().expect("writing to a Vec cannot fail");
+    String::from_utf8(bytes).expect("armor is ASCII")
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    ArmorReader::new(text.as_bytes())?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// Whether `data` starts, after any whitespace, like armor.
+pub fn is_armored(data: &[u8]) -> bool {
+    let start = data.iter().position(|byte| !byte.is_ascii_whitespace());
+    start.is_some_and(|start| data[start..].starts_with(b"-----BEGIN "))
+}
+
+fn begin_line(label: &str) -> String {
+    format!("-----BEGIN {}-----", label)
+}
+
+fn end_line(label: &str) -> String {
+    format!("-----END {}-----", label)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("armor {}", message))
+}
+
+// Armors everything written to it; `finish` writes the checksum and the END
+// line.
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    label: String,
+    crc: u32,
+    // Input not yet encoded, always less than one 3-byte group.
+    pending: Vec<u8>,
+    column: usize,
+    started: bool,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    pub fn new(inner: W, label: &str) -> Self {
+        ArmorWriter {
+            inner,
+            label: label.to_string(),
+            crc: CRC24_INIT,
+            pending: Vec::new(),
+            column: 0,
+            started: false,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.start()?;
+        let tail = to_base64(&std::mem::take(&mut self.pending));
+        self.emit(&tail)?;
+        if self.column > 0 {
+            self.inner.write_all(b"\n")?;
+        }
+        let checksum = to_base64(&self.crc.to_be_bytes()[1..]);
+        writeln!(self.inner, "={}", checksum)?;
+        writeln!(self.inner, "{}", end_line(&self.label))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            writeln!(self.inner, "{}", begin_line(&self.label))?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &str) -> io::Result<()> {
+        let mut text = text.as_bytes();
+        while !text.is_empty() {
+            let take = text.len().min(LINE_LEN - self.column);
+            self.inner.write_all(&text[..take])?;
+            self.column += take;
+            text = &text[take..];
+            if self.column == LINE_LEN {
+                self.inner.write_all(b"\n")?;
+                self.column = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.start()?;
+        self.crc = crc24(self.crc, data);
+        self.pending.extend_from_slice(data);
+        let ready = self.pending.len() / 3 * 3;
+        let text = to_base64(&self.pending[..ready]);
+        self.pending.drain(..ready);
+        self.emit(&text)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Removes armor as it is read. Line breaks inside the base64 may fall
+// anywhere, and header lines ("Key: value") after BEGIN are skipped. The
+// checksum is required and checked when the END line is reached, so as with
+// DecryptReader the output is only trustworthy once reading hits the end.
+pub struct ArmorReader<R: BufRead> {
+    inner: R,
+    label: String,
+    crc: u32,
+    checksum: Option<u32>,
+    // Base64 digits not yet decoded, always less than one 4-digit group.
+    pending: String,
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let label = loop {
+            let line = read_line(&mut inner)?.ok_or_else(|| invalid("has no BEGIN line"))?;
+            if line.is_empty() {
+                continue;
+            }
+            break line
+                .strip_prefix("-----BEGIN ")
+                .and_then(|rest| rest.strip_suffix("-----"))
+                .ok_or_else(|| invalid("has no BEGIN line"))?
+                .to_string();
+        };
+        Ok(ArmorReader {
+            inner,
+            label,
+            crc: CRC24_INIT,
+            checksum: None,
+            pending: String::new(),
+            output: Vec::new(),
+            position: 0,
+            finished: false,
+        })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    // Decodes the next line into `output`.
+    fn next_line(&mut self) -> Result<(), Error> {
+        let line = read_line(&mut self.inner)?.ok_or_else(|| invalid("has no END line"))?;
+        self.output.clear();
+        self.position = 0;
+        if line.starts_with("-----END ") {
+            if line != end_line(&self.label) {
+                return Err(invalid("END line does not match BEGIN"));
+            }
+            if !self.pending.is_empty() {
+                return Err(invalid("body is truncated"));
+            }
+            if self.checksum != Some(self.crc) {
+                return Err(invalid("checksum does not match"));
+            }
+            self.finished = true;
+        } else if line.starts_with('=') && line.len() == 5 {
+            let bytes = from_base64(&line[1..]).map_err(|_| invalid("checksum is malformed"))?;
+            self.checksum = Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]));
+        } else if self.checksum.is_some() {
+            return Err(invalid("has data after the checksum"));
+        } else if !line.contains(':') {
+            self.pending.push_str(&line);
+            let ready = self.pending.len() / 4 * 4;
+            self.output =
+                from_base64(&self.pending[..ready]).map_err(|_| invalid("body is not base64"))?;
+            self.pending.drain(..ready);
+            self.crc = crc24(self.crc, &self.output);
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_line()?;
+        }
+        let available = &self.output[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// The next line without surrounding whitespace, or None at the end.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8(line).map_err(|_| invalid("is not ASCII"))?;
+    Ok(Some(line.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc24_check_value() {
+        assert_eq!(crc24(CRC24_INIT, b"123456789"), 0x21_cf02);
+        assert_eq!(crc24(CRC24_INIT, b""), CRC24_INIT);
+    }
+
+    #[test]
+    fn layout() {
+        let armored = encode(LABEL, b"foobar");
+        let expected = format!(
+            "-----BEGIN AES MESSAGE-----\nZm9vYmFy\n={}\n-----END AES MESSAGE-----\n",
+            to_base64(&crc24(CRC24_INIT, b"foobar").to_be_bytes()[1..])
+        );
+        assert_eq!(armored, expected);
+        assert!(is_armored(armored.as_bytes()));
+        assert!(is_armored(b"\r\n  -----BEGIN X-----"));
+        assert!(!is_armored(b"AESC"));
+
+        let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let armored = encode(LABEL, &data);
+        let lines: Vec<&str> = armored.lines().collect();
+        assert!(lines[1..lines.len() - 3]
+            .iter()
+            .all(|line| line.len() == LINE_LEN));
+        assert!(lines[lines.len() - 3].len() <= LINE_LEN);
+        assert_eq!(decode(&armored).unwrap(), data);
+    }
+
+    #[test]
+    fn tolerant_input() {
+        let data: Vec<u8> = (0..200).collect();
+        let armored = encode(LABEL, &data);
+        // Rewrapped, with CRLF line ends, indentation and a header line.
+        let body: String = armored
+            .lines()
+            .filter(|line| !line.starts_with('-') && !line.starts_with('='))
+            .collect();
+        let checksum = armored.lines().find(|line| line.starts_with('=')).unwrap();
+        let mut pasted = "\r\n-----BEGIN AES MESSAGE-----\r\nComment: pasted\r\n\r\n".to_string();
+        for line in body.as_bytes().chunks(50) {
+            pasted.push_str("  ");
+            pasted.push_str(std::str::from_utf8(line).unwrap());
+            pasted.push_str("\r\n");
+        }
+        pasted.push_str(checksum);
+        pasted.push_str("\r\n-----END AES MESSAGE-----");
+        assert_eq!(decode(&pasted).unwrap(), data);
+    }
+
+    #[test]
+    fn damage_is_detected() {
+        let armored = encode(LABEL, b"attack at dawn");
+        let flipped = armored.replacen("YXR0YWNr", "YXR0YWNs", 1);
+        assert_ne!(flipped, armored);
+        assert!(decode(&flipped).is_err());
+        let mut truncated: Vec<&str> = armored.lines().collect();
+        truncated.remove(1);
+        assert!(decode(&truncated.join("\n")).is_err());
+        assert!(decode(&armored.replace("END AES", "END PGP")).is_err());
+        assert!(decode(&armored[..armored.len() - 30]).is_err());
+        let no_checksum: Vec<&str> = armored
+            .lines()
+            .filter(|line| !line.starts_with('='))
+            .collect();
+        assert!(decode(&no_checksum.join("\n")).is_err());
+        assert!(decode("ciphertext").is_err());
+    }
+}
@@ -1,6 +1,19 @@
-use aeslib::aes::{decrypt, encrypt, AesSize, Key};
-use clap::{arg, Command};
-use std::io::Read;
+use aeslib::aes::{self, AesSize, BlockCipher, Key};
+use aeslib::armor::{self, ArmorReader, ArmorWriter};
+use aeslib::container::envelope::{self, Credential, Envelope, Lock};
+use aeslib::container::{self, Params, DEFAULT_ITERATIONS};
+use aeslib::encoding::{from_hex, to_hex};
+use aeslib::error::Error;
+use aeslib::formats::zip::{self, Archive, Encryption, Version};
+use aeslib::formats::{ansible, openssl};
+use aeslib::io::{DecryptReader, EncryptWriter};
+use aeslib::kex::x25519::{PublicKey, SecretKey};
+use aeslib::padding;
+use aeslib::rng::{OsRng, RngSource, SeededRng};
+use clap::{arg, ArgGroup, ArgMatches, Command, ValueSource};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 fn cli() -> Command<'static> {
@@ -10,26 +23,692 @@ fn cli() -> Command<'static> {
         .arg_required_else_help(true)
         .allow_external_subcommands(true)
         .allow_invalid_utf8_for_external_subcommands(true)
+        .arg(
+            arg!(--seed <SEED> "Seed for reproducible output (test builds only)")
+                .required(false)
+                .global(true)
+                .hide(!cfg!(feature = "deterministic-rng")),
+        )
         .subcommand(
             Command::new("encrypt")
                 .about("Encrypts data to AES")
                 .arg(arg!(-i --in <INFILE> "File to encrypt"))
                 .arg_required_else_help(true)
-                .arg(arg!(-p --password <KEY> "Key to use for encryption"))
-                .arg_required_else_help(true)
+                .arg(arg!(-p --password <KEY> "Key to use for encryption").required(false))
+                .arg(
+                    arg!(--recipient <PUBKEY> "X25519 public key in hex to encrypt to; writes an envelope and may be repeated")
+                        .required(false)
+                        .multiple_occurrences(true),
+                )
+                .group(
+                    ArgGroup::new("to")
+                        .args(&["password", "recipient", "hex-key"])
+                        .required(true)
+                        .multiple(true),
+                )
                 .arg(arg!(-o --out <OUTFILE> "File to write encrypted data to").required(false))
-                .arg(arg!(-s --size <SIZE> "Size of AES key to use").required(false)),
+                .arg(arg!(-s --size <SIZE> "Size of AES key to use").required(false))
+                .arg(
+                    arg!(-m --mode <MODE> "Mode: stream, gcm, ctr or cbc in a container, envelope for a multi-recipient file, or ecb for the legacy raw format; cbc or ctr with --openssl")
+                        .required(false)
+                        .default_value("stream"),
+                )
+                .arg(
+                    arg!(--iterations <COUNT> "PBKDF2 iterations for the container key or --openssl --pbkdf2")
+                        .required(false),
+                )
+                .arg(arg!(--openssl "Write the `openssl enc` format instead of a container"))
+                .arg(
+                    arg!(--name <NAME> "Recipient name for the password in an envelope")
+                        .required(false)
+                        .default_value("owner"),
+                )
+                .arg(arg!(-a --armor "Write ASCII armor instead of binary"))
+                .arg(padding_arg())
+                .arg(threads_arg())
+                .args(openssl_args()),
         )
         .subcommand(
             Command::new("decrypt")
                 .about("Decrypts data from AES")
                 .arg(arg!(-i --in <INFILE> "File to decrypt"))
                 .arg_required_else_help(true)
-                .arg(arg!(-p --password <KEY> "Key to use for decryption"))
-                .arg_required_else_help(true)
+                .arg(arg!(-p --password <KEY> "Key to use for decryption").required(false))
+                .arg(key_arg())
+                .arg(identity_arg())
+                .group(
+                    ArgGroup::new("unlock")
+                        .args(&["password", "key", "identity", "hex-key"])
+                        .required(true),
+                )
                 .arg(arg!(-o --out <OUTFILE> "File to write decrypted data to").required(false))
-                .arg(arg!(-s --size <SIZE> "Size of AES key to use").required(false)),
+                .arg(
+                    arg!(-s --size <SIZE> "Size of AES key for legacy and `openssl enc` files; containers record their own")
+                        .required(false),
+                )
+                .arg(
+                    arg!(--offset <BYTES> "Start decrypting at this plaintext offset (stream containers)")
+                        .required(false),
+                )
+                .arg(
+                    arg!(--length <BYTES> "Decrypt at most this many bytes (stream containers)")
+                        .required(false),
+                )
+                .arg(
+                    arg!(-m --mode <MODE> "Mode of `openssl enc` files: cbc or ctr")
+                        .required(false)
+                        .default_value("cbc"),
+                )
+                .arg(
+                    arg!(--iterations <COUNT> "PBKDF2 iterations of `openssl enc -pbkdf2` files")
+                        .required(false),
+                )
+                .arg(padding_arg())
+                .arg(
+                    arg!(--"legacy-schedule" "Use the AES-192/256 key schedule of older releases for legacy ECB files")
+                        .required(false),
+                )
+                .arg(threads_arg())
+                .args(openssl_args()),
+        )
+        .subcommand(
+            Command::new("recipients")
+                .about("Manages the recipients of an envelope")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("Lists the key slots of an envelope")
+                        .arg(arg!(-i --in <INFILE> "Envelope to inspect")),
+                )
+                .subcommand(
+                    new_credential_args(unlock_args(Command::new("add").about("Adds a recipient")))
+                        .arg(arg!(--name <NAME> "Name of the new recipient"))
+                        .arg(
+                            arg!(--"new-recipient" <PUBKEY> "X25519 public key in hex for the recipient")
+                                .required(false),
+                        )
+                        .group(
+                            ArgGroup::new("new")
+                                .args(&["new-password", "new-key", "new-recipient"])
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    unlock_args(Command::new("remove").about("Removes a recipient"))
+                        .arg(arg!(--name <NAME> "Name of the recipient to remove")),
+                )
+                .subcommand(
+                    new_credential_args(unlock_args(
+                        Command::new("rekey")
+                            .about("Changes the password or key of the unlocking recipient"),
+                    ))
+                    .group(
+                        ArgGroup::new("new")
+                            .args(&["new-password", "new-key"])
+                            .required(true),
+                    ),
+                ),
         )
+        .subcommand(
+            Command::new("vault")
+                .about("Edits Ansible Vault files in place")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    vault_args(Command::new("encrypt").about("Encrypts a plain file into a vault"))
+                        .arg(
+                            arg!(--"vault-id" <ID> "Vault id label, which writes format 1.2 for any id but default")
+                                .required(false),
+                        ),
+                )
+                .subcommand(vault_args(
+                    Command::new("decrypt").about("Replaces a vault with its plaintext"),
+                ))
+                .subcommand(vault_args(
+                    Command::new("view").about("Prints the plaintext of a vault"),
+                ))
+                .subcommand(
+                    vault_args(Command::new("rekey").about("Changes the vault password"))
+                        .arg(arg!(--"new-password" <PASSWORD> "New vault password")),
+                )
+                .subcommand(
+                    vault_args(
+                        Command::new("edit")
+                            .about("Replaces the plaintext, keeping the password and vault id"),
+                    )
+                    .arg(arg!(--from <FILE> "File holding the new plaintext")),
+                ),
+        )
+        .subcommand(
+            Command::new("zip")
+                .about("Writes a ZIP archive of stored files, WinZip AES encrypted with a password")
+                .arg_required_else_help(true)
+                .arg(arg!(-o --out <ZIPFILE> "Archive to write"))
+                .arg(arg!(-p --password <PASSWORD> "Password to encrypt the entries with").required(false))
+                .arg(arg!(-s --size <SIZE> "Size of AES key to use").required(false).default_value("256"))
+                .arg(arg!(--ae1 "Write AE-1 entries, which keep the CRC-32 of the plaintext"))
+                .arg(arg!(<FILES>... "Files to add, under their relative paths")),
+        )
+        .subcommand(
+            Command::new("unzip")
+                .about("Extracts stored and WinZip AES encrypted entries from a ZIP archive")
+                .arg_required_else_help(true)
+                .arg(arg!(-i --in <ZIPFILE> "Archive to read"))
+                .arg(arg!(-p --password <PASSWORD> "Password for encrypted entries").required(false))
+                .arg(arg!(-d --dir <DIR> "Directory to extract into").required(false).default_value("."))
+                .arg(arg!(-l --list "List the entries instead of extracting them")),
+        )
+        .subcommand(
+            Command::new("keygen")
+                .about("Generates a random AES key")
+                .arg(arg!(-s --size <SIZE> "Size of AES key to generate").required(false))
+                .arg(arg!(--x25519 "Generate an X25519 identity instead, with its public key in a comment")),
+        )
+}
+
+fn padding_arg() -> clap::Arg<'static> {
+    arg!(--padding <PADDING> "Padding scheme for legacy ECB files: zero, pkcs7, ansix923, iso10126 or iso7816")
+        .required(false)
+        .default_value("zero")
+}
+
+fn key_arg() -> clap::Arg<'static> {
+    arg!(-k --key <HEX> "Raw AES key in hex, for envelope recipients added with --new-key")
+        .required(false)
+}
+
+fn identity_arg() -> clap::Arg<'static> {
+    arg!(--identity <FILE> "X25519 identity file from keygen --x25519, for envelopes")
+        .required(false)
+}
+
+// Envelope updates are authorized by any existing recipient.
+fn unlock_args(command: Command<'static>) -> Command<'static> {
+    command
+        .arg(arg!(-i --in <INFILE> "Envelope to update in place"))
+        .arg(arg!(-p --password <PASSWORD> "Password of an existing recipient").required(false))
+        .arg(key_arg())
+        .arg(identity_arg())
+        .group(
+            ArgGroup::new("unlock")
+                .args(&["password", "key", "identity"])
+                .required(true),
+        )
+}
+
+// The caller adds the group that makes one of these required.
+fn new_credential_args(command: Command<'static>) -> Command<'static> {
+    command
+        .arg(arg!(--"new-password" <PASSWORD> "Password for the recipient").required(false))
+        .arg(arg!(--"new-key" <HEX> "Raw AES key in hex for the recipient").required(false))
+        .arg(arg!(--iterations <COUNT> "PBKDF2 iterations for a new password").required(false))
+}
+
+// Options of `openssl enc` shared by encrypt and decrypt. Salted files are
+// recognized on decryption, but their KDF and mode are not recorded.
+fn openssl_args() -> [clap::Arg<'static>; 4] {
+    [
+        arg!(--pbkdf2 "Derive the `openssl enc` key with PBKDF2-SHA256, as its -pbkdf2"),
+        arg!(--md <DIGEST> "EVP_BytesToKey digest for `openssl enc` without --pbkdf2: md5 or sha256")
+            .required(false)
+            .default_value("sha256"),
+        arg!(-K --"hex-key" <HEX> "Raw key in hex for `openssl enc -K` data, which has no header")
+            .required(false)
+            .requires("iv"),
+        arg!(--iv <HEX> "IV in hex to go with -K").required(false).requires("hex-key"),
+    ]
+}
+
+fn openssl_params(matches: &ArgMatches, size: AesSize, mode: &str) -> openssl::Params {
+    let mode = openssl::Mode::parse(mode).unwrap_or_else(|error| fail(error));
+    let kdf = if matches.is_present("pbkdf2") {
+        let iterations = match matches.get_one::<String>("iterations") {
+            Some(iterations) => iterations.parse().expect("invalid iteration count"),
+            None => openssl::DEFAULT_ITERATIONS,
+        };
+        openssl::Kdf::Pbkdf2Sha256 { iterations }
+    } else {
+        match matches.get_one::<String>("md").unwrap().as_str() {
+            "md5" => openssl::Kdf::BytesToKeyMd5,
+            "sha256" => openssl::Kdf::BytesToKeySha256,
+            digest => fail(format!("Invalid digest: {}", digest)),
+        }
+    };
+    openssl::Params { size, mode, kdf }
+}
+
+// The -K and --iv pair, if given.
+fn openssl_key(matches: &ArgMatches) -> Option<(Key, [u8; openssl::IV_LEN])> {
+    let key = parse_key(matches.get_one::<String>("hex-key")?);
+    let iv = match from_hex(matches.get_one::<String>("iv").unwrap()) {
+        Ok(bytes) if bytes.len() == openssl::IV_LEN => {
+            let mut iv = [0; openssl::IV_LEN];
+            iv.copy_from_slice(&bytes);
+            iv
+        }
+        _ => fail("--iv must be 32 hex digits"),
+    };
+    Some((key, iv))
+}
+
+fn parse_key(hex: &str) -> Key {
+    match from_hex(hex) {
+        Ok(bytes) if [16, 24, 32].contains(&bytes.len()) => Key::from_bytes(&bytes),
+        _ => fail("Keys must be 32, 48 or 64 hex digits"),
+    }
+}
+
+fn parse_public_key(hex: &str) -> PublicKey {
+    match from_hex(hex)
+        .map_err(Error::InvalidInput)
+        .and_then(|bytes| PublicKey::from_bytes(&bytes))
+    {
+        Ok(public) => public,
+        Err(_) => fail("X25519 public keys must be 64 hex digits"),
+    }
+}
+
+// Identity files hold the secret key in hex; lines starting with # are
+// comments.
+fn read_identity(path: &str) -> SecretKey {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| fail(format!("Failed to read identity: {}", error)));
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'));
+    let secret = line
+        .ok_or_else(|| Error::InvalidInput("no key found".to_string()))
+        .and_then(|line| from_hex(line).map_err(Error::InvalidInput))
+        .and_then(|bytes| SecretKey::from_bytes(&bytes));
+    secret.unwrap_or_else(|error| fail(format!("Invalid identity {}: {}", path, error)))
+}
+
+// Reads the credential from `password`, `key` and, for unlocking,
+// `identity`, each name after `prefix`.
+fn credential(matches: &ArgMatches, prefix: &str) -> Credential {
+    let name = |arg: &str| format!("{}{}", prefix, arg);
+    if let Some(key) = matches.get_one::<String>(&name("key")) {
+        return Credential::Key(parse_key(key));
+    }
+    if let Ok(Some(path)) = matches.try_get_one::<String>(&name("identity")) {
+        return Credential::X25519(read_identity(path));
+    }
+    Credential::Password(
+        matches
+            .get_one::<String>(&name("password"))
+            .unwrap()
+            .to_owned(),
+    )
+}
+
+fn iterations(matches: &ArgMatches) -> u32 {
+    match matches.get_one::<String>("iterations") {
+        Some(iterations) => iterations.parse().expect("invalid iteration count"),
+        None => DEFAULT_ITERATIONS,
+    }
+}
+
+// Rewrites the envelope at `path` with `change` applied to its header. The
+// payload is copied as is, and the file is only replaced once the new one
+// has been written completely.
+fn update_envelope(path: &str, change: impl FnOnce(&mut Envelope) -> Result<(), Error>) {
+    let mut input = BufReader::new(File::open(path).expect("file not found"));
+    let mut envelope = match Envelope::read(&mut input) {
+        Ok(envelope) => envelope,
+        Err(error) => fail(format!("Not an envelope: {}", error)),
+    };
+    if let Err(error) = change(&mut envelope) {
+        fail(format!("Failed to update recipients: {}", error));
+    }
+    let temporary = format!("{}.tmp", path);
+    let mut output = BufWriter::new(File::create(&temporary).expect("failed to create file"));
+    let result = envelope::rewrite(&envelope, &mut input, &mut output)
+        .and_then(|_| Ok(output.flush()?))
+        .and_then(|_| Ok(std::fs::rename(&temporary, path)?));
+    if let Err(error) = result {
+        drop(output);
+        let _ = std::fs::remove_file(&temporary);
+        fail(format!("Failed to update recipients: {}", error));
+    }
+}
+
+fn vault_args(command: Command<'static>) -> Command<'static> {
+    command
+        .arg(arg!(-i --in <FILE> "Vault file"))
+        .arg(arg!(-p --password <PASSWORD> "Vault password"))
+}
+
+// Replaces `path` only once the new contents are completely written. The
+// temporary file is only readable by its owner until it takes over the
+// permissions of the file it replaces.
+fn write_in_place(path: &str, data: &[u8]) -> Result<(), Error> {
+    let temporary = format!("{}.tmp", path);
+    let permissions = std::fs::metadata(path).map(|metadata| metadata.permissions());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = options.open(&temporary).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()?;
+        if let Ok(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        std::fs::rename(&temporary, path)
+    });
+    if let Err(error) = result {
+        if error.kind() != std::io::ErrorKind::AlreadyExists {
+            let _ = std::fs::remove_file(&temporary);
+        }
+        return Err(Error::Io(format!("{}: {}", temporary, error)));
+    }
+    Ok(())
+}
+
+fn vault(matches: &ArgMatches) {
+    let (command, sub_matches) = matches.subcommand().expect("subcommand required");
+    let path = sub_matches.get_one::<String>("in").expect("required");
+    let password = sub_matches.get_one::<String>("password").expect("required");
+    let data = std::fs::read(path).unwrap_or_else(|error| fail(format!("{}: {}", path, error)));
+    let mut rng = rng(sub_matches);
+    if command == "encrypt" {
+        if ansible::is_vault(&data) {
+            fail(format!("{} is already a vault", path));
+        }
+        let vault_id = sub_matches
+            .get_one::<String>("vault-id")
+            .map(String::as_str);
+        let result = ansible::encrypt(password, vault_id, rng.as_mut(), &data)
+            .and_then(|text| write_in_place(path, text.as_bytes()));
+        if let Err(error) = result {
+            fail(format!("Failed to encrypt: {}", error));
+        }
+        println!("Encrypted {}", path);
+        return;
+    }
+    let text = match String::from_utf8(data) {
+        Ok(text) if ansible::is_vault(text.as_bytes()) => text,
+        _ => fail(format!("{} is not a vault", path)),
+    };
+    let result = match command {
+        "decrypt" => ansible::decrypt(password, &text).and_then(|plaintext| {
+            write_in_place(path, &plaintext)?;
+            println!("Decrypted {}", path);
+            Ok(())
+        }),
+        "view" => ansible::decrypt(password, &text).and_then(|plaintext| {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&plaintext)?;
+            Ok(stdout.flush()?)
+        }),
+        "rekey" => {
+            let new_password = sub_matches.get_one::<String>("new-password").unwrap();
+            ansible::rekey(password, new_password, rng.as_mut(), &text).and_then(|rekeyed| {
+                write_in_place(path, rekeyed.as_bytes())?;
+                println!("Rekeyed {}", path);
+                Ok(())
+            })
+        }
+        "edit" => {
+            let from = sub_matches.get_one::<String>("from").unwrap();
+            let plaintext =
+                std::fs::read(from).unwrap_or_else(|error| fail(format!("{}: {}", from, error)));
+            ansible::decrypt(password, &text)
+                .and_then(|_| ansible::vault_id(&text))
+                .and_then(|vault_id| {
+                    ansible::encrypt(password, vault_id.as_deref(), rng.as_mut(), &plaintext)
+                })
+                .and_then(|edited| {
+                    write_in_place(path, edited.as_bytes())?;
+                    println!("Updated {}", path);
+                    Ok(())
+                })
+        }
+        _ => unreachable!(),
+    };
+    if let Err(error) = result {
+        fail(format!("Failed to {} {}: {}", command, path, error));
+    }
+}
+
+fn zip_files(matches: &ArgMatches) {
+    let out = matches.get_one::<String>("out").expect("required");
+    let password = matches.get_one::<String>("password");
+    let encryption = Encryption {
+        version: if matches.is_present("ae1") {
+            Version::Ae1
+        } else {
+            Version::Ae2
+        },
+        size: AesSize::parse(matches.get_one::<String>("size").unwrap())
+            .unwrap_or_else(|error| fail(error)),
+    };
+    let mut rng = rng(matches);
+    let mut writer = zip::Writer::new();
+    for path in matches.get_many::<String>("FILES").expect("required") {
+        let name = archive_name(path).unwrap_or_else(|| {
+            fail(format!(
+                "{}: only relative paths without '..' can be added",
+                path
+            ))
+        });
+        let contents =
+            std::fs::read(path).unwrap_or_else(|error| fail(format!("{}: {}", path, error)));
+        let password = password.map(|password| (password.as_str(), &encryption));
+        if let Err(error) = writer.add(&name, &contents, password, rng.as_mut()) {
+            fail(format!("Failed to add {}: {}", path, error));
+        }
+    }
+    if let Err(error) = write_in_place(out, &writer.finish()) {
+        fail(format!("Failed to write {}: {}", out, error));
+    }
+}
+
+fn unzip_files(matches: &ArgMatches) {
+    let path = matches.get_one::<String>("in").expect("required");
+    let data = std::fs::read(path).unwrap_or_else(|error| fail(format!("{}: {}", path, error)));
+    let archive =
+        Archive::parse(&data).unwrap_or_else(|error| fail(format!("{}: {}", path, error)));
+    if matches.is_present("list") {
+        for entry in archive.entries() {
+            let encryption = match &entry.encryption {
+                Some(encryption) => {
+                    format!("AES-{} {:?}", encryption.size.bits(), encryption.version)
+                }
+                None => "stored".to_string(),
+            };
+            println!("{:>10}  {:<12} {}", entry.size, encryption, entry.name);
+        }
+        return;
+    }
+    let dir = PathBuf::from(matches.get_one::<String>("dir").unwrap());
+    let password = matches.get_one::<String>("password").map(String::as_str);
+    for entry in archive.entries() {
+        // Names come from the archive, so they must not escape `dir`.
+        let name = archive_name(&entry.name)
+            .unwrap_or_else(|| fail(format!("Refusing to extract {:?}", entry.name)));
+        let target = dir.join(&name);
+        let result = if entry.is_dir() {
+            std::fs::create_dir_all(&target).map_err(Error::from)
+        } else {
+            archive.read(entry, password).and_then(|contents| {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Ok(std::fs::write(&target, contents)?)
+            })
+        };
+        if let Err(error) = result {
+            fail(format!("Failed to extract {}: {}", entry.name, error));
+        }
+    }
+}
+
+// The '/' separated form of a relative path, or None if it is absolute or
+// has '..' components.
+fn archive_name(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let parts: Vec<&str> = path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if path.starts_with('/')
+        || parts.is_empty()
+        || parts.iter().any(|part| *part == ".." || part.contains(':'))
+    {
+        return None;
+    }
+    let name = parts.join("/");
+    Some(if path.ends_with('/') {
+        name + "/"
+    } else {
+        name
+    })
+}
+
+fn recipients(matches: &ArgMatches) {
+    let (command, sub_matches) = matches.subcommand().expect("subcommand required");
+    let path = sub_matches.get_one::<String>("in").expect("required");
+    if command == "list" {
+        let mut input = BufReader::new(File::open(path).expect("file not found"));
+        let envelope = match Envelope::read(&mut input) {
+            Ok(envelope) => envelope,
+            Err(error) => fail(format!("Not an envelope: {}", error)),
+        };
+        for slot in &envelope.slots {
+            match &slot.lock {
+                Lock::Password { iterations, .. } => println!(
+                    "{}\tpassword (PBKDF2-SHA256, {} iterations)",
+                    slot.name, iterations
+                ),
+                Lock::Key => println!("{}\tkey", slot.name),
+                Lock::X25519 { .. } => println!("{}\tx25519", slot.name),
+            }
+        }
+        return;
+    }
+    let unlock = credential(sub_matches, "");
+    let mut rng = rng(sub_matches);
+    update_envelope(path, |envelope| {
+        let data_key = envelope.unlock(&unlock)?;
+        match command {
+            "add" => {
+                let name = sub_matches.get_one::<String>("name").unwrap();
+                match sub_matches.get_one::<String>("new-recipient") {
+                    Some(public) => envelope.add_public_key(
+                        &data_key,
+                        name,
+                        &parse_public_key(public),
+                        rng.as_mut(),
+                    ),
+                    None => envelope.add(
+                        &data_key,
+                        name,
+                        &credential(sub_matches, "new-"),
+                        iterations(sub_matches),
+                        rng.as_mut(),
+                    ),
+                }
+            }
+            "remove" => envelope.remove(sub_matches.get_one::<String>("name").unwrap()),
+            "rekey" => envelope.rekey(
+                &unlock,
+                &credential(sub_matches, "new-"),
+                iterations(sub_matches),
+                rng.as_mut(),
+            ),
+            _ => unreachable!(),
+        }
+    });
+    println!("Updated recipients of {}", path);
+}
+
+// The encrypted output file, optionally armored.
+enum Output {
+    Binary(BufWriter<File>),
+    Armored(ArmorWriter<BufWriter<File>>),
+}
+
+impl Output {
+    fn create(path: &str, armored: bool) -> Output {
+        let file = BufWriter::new(File::create(path).expect("failed to create file"));
+        if armored {
+            Output::Armored(ArmorWriter::new(file, armor::LABEL))
+        } else {
+            Output::Binary(file)
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            Output::Binary(mut file) => file.flush()?,
+            Output::Armored(writer) => writer.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Binary(file) => file.write(data),
+            Output::Armored(writer) => writer.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Binary(file) => file.flush(),
+            Output::Armored(writer) => writer.flush(),
+        }
+    }
+}
+
+fn threads_arg() -> clap::Arg<'static> {
+    arg!(--threads <COUNT> "Worker threads for stream containers and ECB; output is the same for any count")
+        .required(false)
+        .default_value("1")
+}
+
+fn threads(matches: &ArgMatches) -> usize {
+    match matches.get_one::<String>("threads").unwrap().parse() {
+        Ok(threads) if threads > 0 => threads,
+        _ => fail("--threads must be a positive number"),
+    }
+}
+
+fn rng(matches: &ArgMatches) -> Box<dyn RngSource> {
+    match matches.get_one::<String>("seed") {
+        Some(seed) if cfg!(feature = "deterministic-rng") => {
+            Box::new(SeededRng::new(seed.as_bytes()))
+        }
+        Some(_) => {
+            eprintln!("Ignoring --seed, it is only honored in test builds");
+            Box::new(OsRng)
+        }
+        None => Box::new(OsRng),
+    }
+}
+
+fn fail(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn decrypt_range(
+    password: &str,
+    input: BufReader<File>,
+    output: &mut impl Write,
+    offset: u64,
+    length: u64,
+    threads: usize,
+) -> Result<(), Error> {
+    let mut reader = container::open_reader(password, input)?.with_threads(threads);
+    reader.seek(SeekFrom::Start(offset))?;
+    std::io::copy(&mut reader.take(length), output)?;
+    Ok(())
 }
 
 fn main() {
@@ -38,7 +717,15 @@ fn main() {
     match matches.subcommand() {
         Some(("encrypt", sub_matches)) => {
             let file_path = sub_matches.get_one::<String>("in").expect("required");
-            let password = sub_matches.get_one::<String>("password").expect("required");
+            let password = sub_matches.get_one::<String>("password");
+            let recipients: Vec<PublicKey> = sub_matches
+                .get_many::<String>("recipient")
+                .into_iter()
+                .flatten()
+                .map(|public| parse_public_key(public))
+                .collect();
+            let threads = threads(sub_matches);
+            let armored = sub_matches.is_present("armor");
             let size = match sub_matches.get_one::<String>("size") {
                 Some(size) => AesSize::parse(size).expect("invalid size"),
                 None => AesSize::S128,
@@ -52,19 +739,112 @@ fn main() {
                     out_file_path.to_str().unwrap().to_string()
                 }
             };
+            let mode = sub_matches.get_one::<String>("mode").unwrap();
+            let iterations = iterations(sub_matches);
             println!("Encrypting {}", file_path);
-            let mut file = std::fs::File::open(file_buf).expect("file not found");
-            let mut file_contents = String::new();
-            file.read_to_string(&mut file_contents)
-                .expect("failed to read file");
-            let cipher = Key::from_password(password, size);
-            let encrypted_data = encrypt(&cipher, &file_contents).expect("failed to encrypt");
-            std::fs::write(&out_file_path, encrypted_data).expect("failed to write file");
+            let mut input = BufReader::new(File::open(file_buf).expect("file not found"));
+            let openssl_key = openssl_key(sub_matches);
+            if openssl_key.is_some() || sub_matches.is_present("openssl") {
+                // Container modes do not apply, so default to CBC like OpenSSL.
+                let mode = match sub_matches.value_source("mode") {
+                    Some(ValueSource::DefaultValue) => "cbc",
+                    _ => mode,
+                };
+                let params = openssl_params(sub_matches, size, mode);
+                let mut output = Output::create(&out_file_path, armored);
+                let result = match (&openssl_key, password) {
+                    (Some((key, iv)), _) => {
+                        openssl::encrypt_with_key(key, iv, params.mode, &mut input, &mut output)
+                    }
+                    (None, Some(password)) => openssl::encrypt_stream(
+                        password,
+                        &params,
+                        rng(sub_matches).as_mut(),
+                        &mut input,
+                        &mut output,
+                    ),
+                    (None, None) => fail("--openssl needs a password or -K and --iv"),
+                }
+                .and_then(|_| output.finish());
+                if let Err(error) = result {
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to encrypt: {}", error));
+                }
+            } else if mode == "envelope" || !recipients.is_empty() {
+                let mut rng = rng(sub_matches);
+                let name = sub_matches.get_one::<String>("name").unwrap();
+                let mut output = Output::create(&out_file_path, armored);
+                let result = Envelope::new(size, rng.as_mut())
+                    .and_then(|(mut envelope, data_key)| {
+                        if let Some(password) = password {
+                            let credential = Credential::Password(password.to_owned());
+                            envelope.add(&data_key, name, &credential, iterations, rng.as_mut())?;
+                        }
+                        for public in &recipients {
+                            let name = to_hex(&public.0);
+                            envelope.add_public_key(&data_key, &name, public, rng.as_mut())?;
+                        }
+                        envelope::seal_stream(
+                            &envelope,
+                            &data_key,
+                            threads,
+                            &mut input,
+                            &mut output,
+                        )
+                    })
+                    .and_then(|_| output.finish());
+                if let Err(error) = result {
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to encrypt: {}", error));
+                }
+            } else if mode == "ecb" {
+                let password = password.expect("required");
+                let padding_name = sub_matches.get_one::<String>("padding").unwrap();
+                let padding =
+                    padding::parse(padding_name, rng(sub_matches)).expect("invalid padding");
+                let key = Key::from_password(password, size);
+                let mut output = Output::create(&out_file_path, armored);
+                // Lets decrypt reject a wrong password or size up front.
+                let prefix = output.write_all(&aes::check_prefix(&key));
+                let mut encryptor = EncryptWriter::ecb(output, BlockCipher::new(&key), padding)
+                    .with_threads(threads);
+                let result = prefix
+                    .and_then(|_| std::io::copy(&mut input, &mut encryptor))
+                    .map_err(Error::from)
+                    .and_then(|_| encryptor.finish())
+                    .and_then(Output::finish);
+                if let Err(error) = result {
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to encrypt: {}", error));
+                }
+            } else {
+                let password = password.expect("required");
+                let params = Params {
+                    size,
+                    mode: container::Mode::parse(mode).expect("invalid mode"),
+                    iterations,
+                    threads,
+                };
+                let mut output = Output::create(&out_file_path, armored);
+                let result = container::seal_stream(
+                    password,
+                    &params,
+                    rng(sub_matches).as_mut(),
+                    &mut input,
+                    &mut output,
+                )
+                .and_then(|_| output.finish());
+                if let Err(error) = result {
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to encrypt: {}", error));
+                }
+            }
             println!("Encrypted data written to {:#?}", out_file_path);
         }
         Some(("decrypt", sub_matches)) => {
             let file_path = sub_matches.get_one::<String>("in").expect("required");
-            let password = sub_matches.get_one::<String>("password").expect("required");
+            let openssl_key = openssl_key(sub_matches);
+            let threads = threads(sub_matches);
             let size = match sub_matches.get_one::<String>("size") {
                 Some(size) => AesSize::parse(size).expect("invalid size"),
                 None => AesSize::S128,
@@ -79,15 +859,173 @@ fn main() {
                 }
             };
             println!("Decrypting {}", file_path);
-            let mut file = std::fs::File::open(file_buf).expect("file not found");
-            let mut file_contents = Vec::new();
-            file.read_to_end(&mut file_contents)
-                .expect("failed to read file");
-            let cipher = Key::from_password(password, size);
-            let decrypted_data = decrypt(&cipher, &file_contents).expect("Invalid Password");
-            std::fs::write(&out_file_path, decrypted_data).expect("failed to write file");
+            let mut file = BufReader::new(File::open(&file_buf).expect("file not found"));
+            let armored = armor::is_armored(file.fill_buf().expect("failed to read file"));
+            let offset = sub_matches.get_one::<String>("offset");
+            let length = sub_matches.get_one::<String>("length");
+            if armored && (offset.is_some() || length.is_some()) {
+                fail("--offset and --length need binary input");
+            }
+            let mut input: Box<dyn BufRead> = if armored {
+                match ArmorReader::new(file) {
+                    Ok(reader) => Box::new(BufReader::new(reader)),
+                    Err(error) => fail(format!("Failed to decrypt: {}", error)),
+                }
+            } else {
+                Box::new(file)
+            };
+            let header = match input.fill_buf() {
+                Ok(header) => header,
+                Err(error) => fail(format!("Failed to decrypt: {}", Error::from(error))),
+            };
+            if let Some((key, iv)) = &openssl_key {
+                let mode = sub_matches.get_one::<String>("mode").unwrap();
+                let mode = openssl::Mode::parse(mode).unwrap_or_else(|error| fail(error));
+                let mut output =
+                    BufWriter::new(File::create(&out_file_path).expect("failed to create file"));
+                let result = openssl::decrypt_with_key(key, iv, mode, &mut input, &mut output)
+                    .and_then(|_| Ok(output.flush()?));
+                if let Err(error) = result {
+                    drop(output);
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            } else if envelope::is_envelope(header) {
+                let mut output =
+                    BufWriter::new(File::create(&out_file_path).expect("failed to create file"));
+                let credential = credential(sub_matches, "");
+                let result = envelope::open_stream(&credential, threads, &mut input, &mut output)
+                    .and_then(|_| Ok(output.flush()?));
+                if let Err(error) = result {
+                    drop(output);
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            } else if container::is_container(header) {
+                let password = match credential(sub_matches, "") {
+                    Credential::Password(password) => password,
+                    _ => fail("--key and --identity only open envelopes"),
+                };
+                let mut output =
+                    BufWriter::new(File::create(&out_file_path).expect("failed to create file"));
+                let result = if offset.is_some() || length.is_some() {
+                    let offset = offset.map_or(0, |offset| offset.parse().expect("invalid offset"));
+                    let length =
+                        length.map_or(u64::MAX, |length| length.parse().expect("invalid length"));
+                    let input = BufReader::new(File::open(&file_buf).expect("file not found"));
+                    decrypt_range(&password, input, &mut output, offset, length, threads)
+                } else {
+                    container::open_stream(&password, threads, &mut input, &mut output)
+                }
+                .and_then(|_| Ok(output.flush()?));
+                if let Err(error) = result {
+                    // Never leave unauthenticated plaintext behind.
+                    drop(output);
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            } else if openssl::is_salted(header) {
+                let password = match credential(sub_matches, "") {
+                    Credential::Password(password) => password,
+                    _ => fail("--key and --identity only open envelopes"),
+                };
+                let mode = sub_matches.get_one::<String>("mode").unwrap();
+                let params = openssl_params(sub_matches, size, mode);
+                let mut output =
+                    BufWriter::new(File::create(&out_file_path).expect("failed to create file"));
+                let result = openssl::decrypt_stream(&password, &params, &mut input, &mut output)
+                    .and_then(|_| Ok(output.flush()?));
+                if let Err(error) = result {
+                    drop(output);
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            } else if header.starts_with(aes::SEALED_MAGIC) {
+                let password = match credential(sub_matches, "") {
+                    Credential::Password(password) => password,
+                    _ => fail("--key and --identity only open envelopes"),
+                };
+                let key = Key::from_password(&password, size);
+                // Written by aes::encrypt_authenticated, which is not streamed.
+                let mut sealed = Vec::new();
+                let result = input
+                    .read_to_end(&mut sealed)
+                    .map_err(Error::from)
+                    .and_then(|_| aes::decrypt_authenticated(&key, &sealed))
+                    .and_then(|plaintext| Ok(std::fs::write(&out_file_path, plaintext)?));
+                if let Err(error) = result {
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            } else {
+                let password = match credential(sub_matches, "") {
+                    Credential::Password(password) => password,
+                    _ => fail("--key and --identity only open envelopes"),
+                };
+                let padding_name = sub_matches.get_one::<String>("padding").unwrap();
+                let padding =
+                    padding::parse(padding_name, Box::new(OsRng)).expect("invalid padding");
+                let key = Key::from_password(&password, size);
+                // Files written before the check value existed have no prefix.
+                let checked = header.starts_with(aes::CHECK_MAGIC);
+                if checked {
+                    let mut prefix = [0; aes::CHECK_PREFIX_LEN];
+                    let result = input
+                        .read_exact(&mut prefix)
+                        .map_err(Error::from)
+                        .and_then(|_| aes::verify_check_prefix(&key, &prefix));
+                    if let Err(error) = result {
+                        fail(format!("Failed to decrypt: {}", error));
+                    }
+                }
+                let legacy_schedule = sub_matches.is_present("legacy-schedule");
+                if legacy_schedule && checked {
+                    fail("--legacy-schedule only applies to files without a key check value");
+                }
+                if !legacy_schedule && !checked && !matches!(key.size, AesSize::S128) {
+                    eprintln!(
+                        "{} has no key check value; if it predates the AES-{} key schedule fix, decrypt it with --legacy-schedule",
+                        file_path,
+                        key.size.bits()
+                    );
+                }
+                let cipher = if legacy_schedule {
+                    BlockCipher::with_legacy_schedule(&key)
+                } else {
+                    BlockCipher::new(&key)
+                };
+                let mut output =
+                    BufWriter::new(File::create(&out_file_path).expect("failed to create file"));
+                let mut decryptor =
+                    DecryptReader::ecb(input, cipher, padding).with_threads(threads);
+                let result = std::io::copy(&mut decryptor, &mut output)
+                    .and_then(|_| output.flush())
+                    .map_err(Error::from);
+                if let Err(error) = result {
+                    drop(output);
+                    let _ = std::fs::remove_file(&out_file_path);
+                    fail(format!("Failed to decrypt: {}", error));
+                }
+            }
             println!("Decrypted data written to {:#?}", out_file_path);
         }
+        Some(("keygen", sub_matches)) if sub_matches.is_present("x25519") => {
+            let secret =
+                SecretKey::generate(rng(sub_matches).as_mut()).expect("failed to generate key");
+            println!("# public key: {}", to_hex(&secret.public_key().0));
+            println!("{}", to_hex(&secret.to_bytes()));
+        }
+        Some(("keygen", sub_matches)) => {
+            let size = match sub_matches.get_one::<String>("size") {
+                Some(size) => AesSize::parse(size).expect("invalid size"),
+                None => AesSize::S128,
+            };
+            let key = Key::random(size, rng(sub_matches).as_mut()).expect("failed to generate key");
+            println!("{}", to_hex(&key.to_bytes()));
+        }
+        Some(("recipients", sub_matches)) => recipients(sub_matches),
+        Some(("vault", sub_matches)) => vault(sub_matches),
+        Some(("zip", sub_matches)) => zip_files(sub_matches),
+        Some(("unzip", sub_matches)) => unzip_files(sub_matches),
         _ => unreachable!(),
     }
 }
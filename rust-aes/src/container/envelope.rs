@@ -0,0 +1,612 @@
+use std::io::{Read, Write};
+
+use super::header::{cipher_from_id, cipher_id, invalid, read_with, Reader};
+use super::{MAX_ITERATIONS, SALT_LEN};
+use crate::aes::{AesSize, BlockCipher, Key};
+use crate::error::{Error, HeaderError};
+use crate::hash::Sha256;
+use crate::io::{DecryptReader, EncryptWriter};
+use crate::kdf::{hkdf, pbkdf2};
+use crate::kex::x25519::{self, PublicKey, SecretKey};
+use crate::modes::kw;
+use crate::modes::stream::{Stream, NONCE_PREFIX_LEN};
+use crate::rng::RngSource;
+
+pub const MAGIC: &[u8; 4] = b"AESE";
+pub const VERSION: u8 = 1;
+pub const MAX_SLOTS: usize = 255;
+
+// What a recipient unlocks their slot with.
+pub enum Credential {
+    Password(String),
+    Key(Key),
+    X25519(SecretKey),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lock {
+    // The key-encryption key is PBKDF2-SHA256 of the password.
+    Password { iterations: u32, salt: Vec<u8> },
+    Key,
+    // The key-encryption key comes from an agreement between an ephemeral
+    // key and the recipient's public key, through HKDF-SHA256.
+    X25519 { ephemeral: PublicKey },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    pub name: String,
+    pub lock: Lock,
+    // The data key under AES-KW.
+    pub wrapped: Vec<u8>,
+}
+
+// Layout, all integers big-endian:
+//   magic "AESE" | version | cipher id | STREAM nonce prefix | slot count |
+//   slots
+// A slot is name length | name | lock id | lock params | wrapped length |
+// wrapped key, with lock id 1 for a password (iterations (u32) | salt
+// length | salt), 2 for a raw key and 3 for an X25519 public key (the
+// 32-byte ephemeral public key). The payload is STREAM under the data
+// key with only the bytes before the slot count as associated data, so slots
+// can be added, removed or rewrapped without re-encrypting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub size: AesSize,
+    pub prefix: [u8; NONCE_PREFIX_LEN],
+    pub slots: Vec<Slot>,
+}
+
+impl Envelope {
+    // A fresh envelope without recipients and its random data key.
+    pub fn new(size: AesSize, rng: &mut dyn RngSource) -> Result<(Envelope, Key), Error> {
+        let data_key = Key::random(size.clone(), rng)?;
+        let mut prefix = [0; NONCE_PREFIX_LEN];
+        prefix.copy_from_slice(&rng.random_vec(NONCE_PREFIX_LEN)?);
+        let envelope = Envelope {
+            size,
+            prefix,
+            slots: Vec::new(),
+        };
+        Ok((envelope, data_key))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.aad();
+        bytes.push(self.slots.len() as u8);
+        for slot in &self.slots {
+            bytes.push(slot.name.len() as u8);
+            bytes.extend_from_slice(slot.name.as_bytes());
+            match &slot.lock {
+                Lock::Password { iterations, salt } => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&iterations.to_be_bytes());
+                    bytes.push(salt.len() as u8);
+                    bytes.extend_from_slice(salt);
+                }
+                Lock::Key => bytes.push(2),
+                Lock::X25519 { ephemeral } => {
+                    bytes.push(3);
+                    bytes.extend_from_slice(&ephemeral.0);
+                }
+            }
+            bytes.push(slot.wrapped.len() as u8);
+            bytes.extend_from_slice(&slot.wrapped);
+        }
+        bytes
+    }
+
+    // Returns the envelope and the length of its header in bytes.
+    pub fn parse(data: &[u8]) -> Result<(Envelope, usize), Error> {
+        let mut reader = Reader { data, position: 0 };
+        if reader
+            .bytes(MAGIC.len())
+            .map_err(|_| HeaderError::BadMagic)?
+            != MAGIC
+        {
+            return Err(HeaderError::BadMagic.into());
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(HeaderError::UnsupportedVersion(version).into());
+        }
+        let size = cipher_from_id(reader.byte()?)?;
+        let prefix = reader.array()?;
+        let count = reader.byte()?;
+        let mut slots = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name_len = reader.byte()? as usize;
+            let name = String::from_utf8(reader.bytes(name_len)?.to_vec())
+                .map_err(|_| invalid("slot names must be UTF-8"))?;
+            let lock = match reader.byte()? {
+                1 => {
+                    let iterations = u32::from_be_bytes(reader.array()?);
+                    let salt_len = reader.byte()? as usize;
+                    let salt = reader.bytes(salt_len)?.to_vec();
+                    if iterations == 0 || iterations > MAX_ITERATIONS || salt.is_empty() {
+                        return Err(invalid(&format!(
+                            "PBKDF2 needs 1 to {} iterations and a salt",
+                            MAX_ITERATIONS
+                        )));
+                    }
+                    Lock::Password { iterations, salt }
+                }
+                2 => Lock::Key,
+                3 => Lock::X25519 {
+                    ephemeral: PublicKey(reader.array()?),
+                },
+                id => return Err(HeaderError::UnknownKdf(id).into()),
+            };
+            let wrapped_len = reader.byte()? as usize;
+            let wrapped = reader.bytes(wrapped_len)?.to_vec();
+            slots.push(Slot {
+                name,
+                lock,
+                wrapped,
+            });
+        }
+        let envelope = Envelope {
+            size,
+            prefix,
+            slots,
+        };
+        Ok((envelope, reader.position))
+    }
+
+    // Reads exactly the header from the front of a stream, leaving the
+    // reader at the payload.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Envelope, Error> {
+        let (envelope, _) = read_with(reader, MAGIC.len(), Envelope::parse)?;
+        Ok(envelope)
+    }
+
+    // Tries every slot the credential could open. The key wrap integrity
+    // check doubles as a key check value, so a wrong password or key fails
+    // with Error::WrongKey before the payload is touched.
+    pub fn unlock(&self, credential: &Credential) -> Result<Key, Error> {
+        self.unlock_slot(credential).map(|(_, key)| key)
+    }
+
+    pub fn add(
+        &mut self,
+        data_key: &Key,
+        name: &str,
+        credential: &Credential,
+        iterations: u32,
+        rng: &mut dyn RngSource,
+    ) -> Result<(), Error> {
+        self.check_new(name)?;
+        let slot = self.wrap(data_key, name, credential, iterations, rng)?;
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    // Adds a recipient that can only open the envelope with the matching
+    // X25519 secret key.
+    pub fn add_public_key(
+        &mut self,
+        data_key: &Key,
+        name: &str,
+        public: &PublicKey,
+        rng: &mut dyn RngSource,
+    ) -> Result<(), Error> {
+        self.check_new(name)?;
+        let slot = self.wrap_to_public_key(data_key, name, public, rng)?;
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let index = self.find(name)?;
+        if self.slots.len() == 1 {
+            return Err(Error::InvalidInput(
+                "cannot remove the last recipient".to_string(),
+            ));
+        }
+        self.slots.remove(index);
+        Ok(())
+    }
+
+    // Rewraps the data key for whichever slot `old` opens, under `new` and
+    // a fresh salt. The payload stays as it is.
+    pub fn rekey(
+        &mut self,
+        old: &Credential,
+        new: &Credential,
+        iterations: u32,
+        rng: &mut dyn RngSource,
+    ) -> Result<(), Error> {
+        let (index, data_key) = self.unlock_slot(old)?;
+        let name = self.slots[index].name.clone();
+        self.slots[index] = self.wrap(&data_key, &name, new, iterations, rng)?;
+        Ok(())
+    }
+
+    fn check_new(&self, name: &str) -> Result<(), Error> {
+        if name.is_empty() || name.len() > 255 {
+            return Err(Error::InvalidInput(
+                "recipient names must be 1 to 255 bytes".to_string(),
+            ));
+        }
+        if self.slots.iter().any(|slot| slot.name == name) {
+            return Err(Error::InvalidInput(format!(
+                "recipient {} already exists",
+                name
+            )));
+        }
+        if self.slots.len() == MAX_SLOTS {
+            return Err(Error::InvalidInput(format!(
+                "an envelope holds at most {} recipients",
+                MAX_SLOTS
+            )));
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<usize, Error> {
+        self.slots
+            .iter()
+            .position(|slot| slot.name == name)
+            .ok_or_else(|| Error::InvalidInput(format!("no recipient named {}", name)))
+    }
+
+    fn wrap(
+        &self,
+        data_key: &Key,
+        name: &str,
+        credential: &Credential,
+        iterations: u32,
+        rng: &mut dyn RngSource,
+    ) -> Result<Slot, Error> {
+        self.check_data_key(data_key)?;
+        let lock = match credential {
+            Credential::Password(_) => {
+                if iterations == 0 || iterations > MAX_ITERATIONS {
+                    return Err(Error::InvalidInput(format!(
+                        "PBKDF2 needs 1 to {} iterations",
+                        MAX_ITERATIONS
+                    )));
+                }
+                Lock::Password {
+                    iterations,
+                    salt: rng.random_vec(SALT_LEN)?,
+                }
+            }
+            Credential::Key(_) => Lock::Key,
+            Credential::X25519(secret) => {
+                return self.wrap_to_public_key(data_key, name, &secret.public_key(), rng)
+            }
+        };
+        let kek = self
+            .kek(credential, &lock)
+            .expect("lock matches credential");
+        Ok(Slot {
+            name: name.to_string(),
+            wrapped: kw::wrap(&kek, &data_key.to_bytes())?,
+            lock,
+        })
+    }
+
+    fn wrap_to_public_key(
+        &self,
+        data_key: &Key,
+        name: &str,
+        public: &PublicKey,
+        rng: &mut dyn RngSource,
+    ) -> Result<Slot, Error> {
+        self.check_data_key(data_key)?;
+        let ephemeral = SecretKey::generate(rng)?;
+        let shared = ephemeral.agree(public)?;
+        let ephemeral = ephemeral.public_key();
+        let kek = self.x25519_kek(&shared, &ephemeral, public);
+        Ok(Slot {
+            name: name.to_string(),
+            lock: Lock::X25519 { ephemeral },
+            wrapped: kw::wrap(&kek, &data_key.to_bytes())?,
+        })
+    }
+
+    fn check_data_key(&self, data_key: &Key) -> Result<(), Error> {
+        if data_key.size != self.size {
+            return Err(Error::InvalidInput(format!(
+                "envelope needs an AES-{} data key",
+                self.size.bits()
+            )));
+        }
+        Ok(())
+    }
+
+    fn unlock_slot(&self, credential: &Credential) -> Result<(usize, Key), Error> {
+        for (index, slot) in self.slots.iter().enumerate() {
+            let kek = match self.kek(credential, &slot.lock) {
+                Some(kek) => kek,
+                None => continue,
+            };
+            if let Ok(bytes) = kw::unwrap(&kek, &slot.wrapped) {
+                if bytes.len() == self.size.key_len() {
+                    return Ok((index, Key::from_bytes(&bytes)));
+                }
+            }
+        }
+        Err(Error::WrongKey)
+    }
+
+    // None when the credential cannot open this kind of lock.
+    fn kek(&self, credential: &Credential, lock: &Lock) -> Option<BlockCipher> {
+        match (credential, lock) {
+            (Credential::Password(password), Lock::Password { iterations, salt }) => {
+                let bytes = pbkdf2::derive::<Sha256>(
+                    password.as_bytes(),
+                    salt,
+                    *iterations,
+                    self.size.key_len(),
+                );
+                Some(BlockCipher::new(&Key::from_bytes(&bytes)))
+            }
+            (Credential::Key(key), Lock::Key) => Some(BlockCipher::new(key)),
+            (Credential::X25519(secret), Lock::X25519 { ephemeral }) => {
+                let shared = secret.agree(ephemeral).ok()?;
+                Some(self.x25519_kek(&shared, ephemeral, &secret.public_key()))
+            }
+            _ => None,
+        }
+    }
+
+    // Both public keys go into the salt, binding the KEK to this agreement.
+    fn x25519_kek(
+        &self,
+        shared: &[u8; x25519::KEY_LEN],
+        ephemeral: &PublicKey,
+        recipient: &PublicKey,
+    ) -> BlockCipher {
+        let salt = [ephemeral.0, recipient.0].concat();
+        let bytes = hkdf::derive::<Sha256>(
+            &salt,
+            shared,
+            b"aeslib envelope x25519",
+            self.size.key_len(),
+        )
+        .expect("AES keys are within the HKDF output limit");
+        BlockCipher::new(&Key::from_bytes(&bytes))
+    }
+
+    // The fixed part of the header, bound to the payload.
+    fn aad(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(cipher_id(&self.size));
+        bytes.extend_from_slice(&self.prefix);
+        bytes
+    }
+}
+
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn seal_stream<R: Read, W: Write>(
+    envelope: &Envelope,
+    data_key: &Key,
+    threads: usize,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    if envelope.slots.is_empty() {
+        return Err(Error::InvalidInput(
+            "an envelope needs at least one recipient".to_string(),
+        ));
+    }
+    writer.write_all(&envelope.to_bytes())?;
+    let stream = Stream::new(data_key, &envelope.prefix)?;
+    let mut encryptor =
+        EncryptWriter::stream(writer, stream, &envelope.aad()).with_threads(threads);
+    std::io::copy(reader, &mut encryptor)?;
+    encryptor.finish()?;
+    Ok(())
+}
+
+// As with DecryptReader, output written before an error must be discarded.
+pub fn open_stream<R: Read, W: Write>(
+    credential: &Credential,
+    threads: usize,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let envelope = Envelope::read(reader)?;
+    let data_key = envelope.unlock(credential)?;
+    let stream = Stream::new(&data_key, &envelope.prefix)?;
+    let mut decryptor =
+        DecryptReader::stream(reader, stream, &envelope.aad()).with_threads(threads);
+    std::io::copy(&mut decryptor, writer)?;
+    Ok(())
+}
+
+// Writes `envelope` followed by the untouched payload left in `reader`,
+// after the caller has read and changed the header.
+pub fn rewrite<R: Read, W: Write>(
+    envelope: &Envelope,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    writer.write_all(&envelope.to_bytes())?;
+    std::io::copy(reader, writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    fn password(text: &str) -> Credential {
+        Credential::Password(text.to_string())
+    }
+
+    fn sealed(rng: &mut SeededRng, plaintext: &[u8]) -> Vec<u8> {
+        let (mut envelope, data_key) = Envelope::new(AesSize::S128, rng).unwrap();
+        envelope
+            .add(&data_key, "alice", &password("a"), 10, rng)
+            .unwrap();
+        let bob = Credential::Key(Key::from_bytes(&[0xb0; 32]));
+        envelope.add(&data_key, "bob", &bob, 10, rng).unwrap();
+        let mut sealed = Vec::new();
+        seal_stream(&envelope, &data_key, 1, &mut &plaintext[..], &mut sealed).unwrap();
+        sealed
+    }
+
+    fn open(credential: &Credential, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut plaintext = Vec::new();
+        open_stream(credential, 1, &mut &sealed[..], &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    // Applies `change` to the header and copies the payload across.
+    fn update(sealed: &[u8], change: impl FnOnce(&mut Envelope)) -> Vec<u8> {
+        let mut reader = sealed;
+        let mut envelope = Envelope::read(&mut reader).unwrap();
+        change(&mut envelope);
+        let mut updated = Vec::new();
+        rewrite(&envelope, &mut reader, &mut updated).unwrap();
+        updated
+    }
+
+    #[test]
+    fn every_recipient_opens() {
+        let mut rng = SeededRng::from_u64(1);
+        let sealed = sealed(&mut rng, b"shared");
+        assert!(is_envelope(&sealed));
+        assert_eq!(open(&password("a"), &sealed).unwrap(), b"shared");
+        let bob = Credential::Key(Key::from_bytes(&[0xb0; 32]));
+        assert_eq!(open(&bob, &sealed).unwrap(), b"shared");
+        assert_eq!(open(&password("b"), &sealed), Err(Error::WrongKey));
+        let wrong_key = Credential::Key(Key::from_bytes(&[0xb1; 32]));
+        assert_eq!(open(&wrong_key, &sealed), Err(Error::WrongKey));
+    }
+
+    #[test]
+    fn manage_recipients() {
+        let mut rng = SeededRng::from_u64(2);
+        let original = sealed(&mut rng, b"payload");
+        let (_, original_header) = Envelope::parse(&original).unwrap();
+        let payload = &original[original_header..];
+
+        let added = update(&original, |envelope| {
+            let data_key = envelope.unlock(&password("a")).unwrap();
+            envelope
+                .add(&data_key, "carol", &password("c"), 10, &mut rng)
+                .unwrap();
+            assert!(envelope
+                .add(&data_key, "carol", &password("x"), 10, &mut rng)
+                .is_err());
+        });
+        assert_eq!(open(&password("c"), &added).unwrap(), b"payload");
+        assert!(added.ends_with(payload));
+
+        let removed = update(&added, |envelope| {
+            envelope.remove("alice").unwrap();
+            assert!(envelope.remove("dave").is_err());
+        });
+        assert_eq!(open(&password("a"), &removed), Err(Error::WrongKey));
+        assert_eq!(open(&password("c"), &removed).unwrap(), b"payload");
+        let (envelope, _) = Envelope::parse(&removed).unwrap();
+        let names: Vec<&str> = envelope
+            .slots
+            .iter()
+            .map(|slot| slot.name.as_str())
+            .collect();
+        assert_eq!(names, ["bob", "carol"]);
+
+        let rekeyed = update(&removed, |envelope| {
+            envelope
+                .rekey(&password("c"), &password("c2"), 10, &mut rng)
+                .unwrap();
+            assert_eq!(
+                envelope.rekey(&password("c"), &password("c3"), 10, &mut rng),
+                Err(Error::WrongKey)
+            );
+        });
+        assert_eq!(open(&password("c"), &rekeyed), Err(Error::WrongKey));
+        assert_eq!(open(&password("c2"), &rekeyed).unwrap(), b"payload");
+        assert!(rekeyed.ends_with(payload));
+    }
+
+    #[test]
+    fn public_key_recipients() {
+        let mut rng = SeededRng::from_u64(5);
+        let secret = SecretKey::generate(&mut rng).unwrap();
+        let (mut envelope, data_key) = Envelope::new(AesSize::S128, &mut rng).unwrap();
+        envelope
+            .add_public_key(&data_key, "ci", &secret.public_key(), &mut rng)
+            .unwrap();
+        let mut sealed = Vec::new();
+        seal_stream(&envelope, &data_key, 1, &mut &b"build"[..], &mut sealed).unwrap();
+        let (parsed, _) = Envelope::parse(&sealed).unwrap();
+        assert_eq!(parsed, envelope);
+
+        assert_eq!(
+            open(&Credential::X25519(secret.clone()), &sealed).unwrap(),
+            b"build"
+        );
+        let other = Credential::X25519(SecretKey::generate(&mut rng).unwrap());
+        assert_eq!(open(&other, &sealed), Err(Error::WrongKey));
+        assert_eq!(open(&password("a"), &sealed), Err(Error::WrongKey));
+
+        // Each wrap uses a fresh ephemeral key.
+        envelope
+            .add(
+                &data_key,
+                "again",
+                &Credential::X25519(secret),
+                10,
+                &mut rng,
+            )
+            .unwrap();
+        assert_ne!(envelope.slots[0].lock, envelope.slots[1].lock);
+        let low_order = PublicKey([0; x25519::KEY_LEN]);
+        assert!(envelope
+            .add_public_key(&data_key, "bad", &low_order, &mut rng)
+            .is_err());
+    }
+
+    #[test]
+    fn last_recipient_stays() {
+        let mut rng = SeededRng::from_u64(3);
+        let (mut envelope, data_key) = Envelope::new(AesSize::S192, &mut rng).unwrap();
+        envelope
+            .add(&data_key, "only", &password("p"), 10, &mut rng)
+            .unwrap();
+        assert!(envelope.remove("only").is_err());
+        let empty = Envelope {
+            slots: Vec::new(),
+            ..envelope
+        };
+        assert!(seal_stream(&empty, &data_key, 1, &mut &b""[..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut rng = SeededRng::from_u64(4);
+        let sealed = sealed(&mut rng, b"");
+        let (envelope, header_len) = Envelope::parse(&sealed).unwrap();
+        assert_eq!(envelope.to_bytes(), &sealed[..header_len]);
+        assert!(matches!(
+            envelope.slots[0].lock,
+            Lock::Password { iterations: 10, .. }
+        ));
+        assert_eq!(envelope.slots[1].lock, Lock::Key);
+        for len in 0..header_len {
+            assert_eq!(
+                Envelope::parse(&sealed[..len]).unwrap_err(),
+                if len < MAGIC.len() {
+                    HeaderError::BadMagic.into()
+                } else {
+                    HeaderError::Truncated.into()
+                }
+            );
+        }
+        // The fixed header is bound to the payload.
+        let mut tampered = sealed.clone();
+        tampered[6] ^= 1;
+        assert_eq!(open(&password("a"), &tampered), Err(Error::Authentication));
+    }
+}
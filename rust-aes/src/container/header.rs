@@ -0,0 +1,386 @@
+use crate::aes::{AesSize, CHECK_LEN};
+use crate::error::{Error, HeaderError};
+use std::io::Read;
+
+use crate::modes::{gcm, stream};
+
+pub const MAGIC: &[u8; 4] = b"AESC";
+// Version 1 headers had no key check value and are no longer read.
+pub const VERSION: u8 = 2;
+// Keeps a forged header from stalling decryption in the KDF.
+pub const MAX_ITERATIONS: u32 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Gcm,
+    EtmCtr,
+    EtmCbc,
+    Stream,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Result<Mode, String> {
+        match name {
+            "gcm" => Ok(Mode::Gcm),
+            "ctr" => Ok(Mode::EtmCtr),
+            "cbc" => Ok(Mode::EtmCbc),
+            "stream" => Ok(Mode::Stream),
+            _ => Err(format!("Invalid mode: {}", name)),
+        }
+    }
+
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            Mode::Gcm => gcm::NONCE_LEN,
+            Mode::EtmCtr | Mode::EtmCbc => 16,
+            Mode::Stream => stream::NONCE_PREFIX_LEN,
+        }
+    }
+
+    // For Stream this is the tag of each segment.
+    pub fn tag_len(&self) -> usize {
+        match self {
+            Mode::Gcm | Mode::Stream => gcm::TAG_LEN,
+            Mode::EtmCtr | Mode::EtmCbc => 32,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Mode::Gcm => 1,
+            Mode::EtmCtr => 2,
+            Mode::EtmCbc => 3,
+            Mode::Stream => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Mode, HeaderError> {
+        match id {
+            1 => Ok(Mode::Gcm),
+            2 => Ok(Mode::EtmCtr),
+            3 => Ok(Mode::EtmCbc),
+            4 => Ok(Mode::Stream),
+            _ => Err(HeaderError::UnknownMode(id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kdf {
+    // The caller supplies the AES key directly.
+    None,
+    Pbkdf2Sha256 { iterations: u32, salt: Vec<u8> },
+}
+
+impl Kdf {
+    fn id(&self) -> u8 {
+        match self {
+            Kdf::None => 0,
+            Kdf::Pbkdf2Sha256 { .. } => 1,
+        }
+    }
+}
+
+// Layout, all integers big-endian:
+//   magic "AESC" | version | cipher id | mode id | kdf id | kdf params |
+//   nonce length | nonce | key check value
+// PBKDF2 params are iterations (u32) | salt length | salt. The whole header
+// is authenticated as associated data; ciphertext and tag follow it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub size: AesSize,
+    pub mode: Mode,
+    pub kdf: Kdf,
+    pub nonce: Vec<u8>,
+    // Lets a wrong password or key be rejected before decrypting anything.
+    pub check: [u8; CHECK_LEN],
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(cipher_id(&self.size));
+        bytes.push(self.mode.id());
+        bytes.push(self.kdf.id());
+        if let Kdf::Pbkdf2Sha256 { iterations, salt } = &self.kdf {
+            bytes.extend_from_slice(&iterations.to_be_bytes());
+            bytes.push(salt.len() as u8);
+            bytes.extend_from_slice(salt);
+        }
+        bytes.push(self.nonce.len() as u8);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.check);
+        bytes
+    }
+
+    // Returns the header and its length in bytes.
+    pub fn parse(data: &[u8]) -> Result<(Header, usize), Error> {
+        let mut reader = Reader { data, position: 0 };
+        if reader
+            .bytes(MAGIC.len())
+            .map_err(|_| HeaderError::BadMagic)?
+            != MAGIC
+        {
+            return Err(HeaderError::BadMagic.into());
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(HeaderError::UnsupportedVersion(version).into());
+        }
+        let size = cipher_from_id(reader.byte()?)?;
+        let mode = Mode::from_id(reader.byte()?)?;
+        let kdf = match reader.byte()? {
+            0 => Kdf::None,
+            1 => {
+                let iterations = u32::from_be_bytes(reader.array()?);
+                let salt_len = reader.byte()? as usize;
+                let salt = reader.bytes(salt_len)?.to_vec();
+                if iterations == 0 || iterations > MAX_ITERATIONS || salt.is_empty() {
+                    return Err(invalid(&format!(
+                        "PBKDF2 needs 1 to {} iterations and a salt",
+                        MAX_ITERATIONS
+                    )));
+                }
+                Kdf::Pbkdf2Sha256 { iterations, salt }
+            }
+            id => return Err(HeaderError::UnknownKdf(id).into()),
+        };
+        let nonce_len = reader.byte()? as usize;
+        if nonce_len != mode.nonce_len() {
+            return Err(invalid(&format!(
+                "nonce must be {} bytes, got {}",
+                mode.nonce_len(),
+                nonce_len
+            )));
+        }
+        let nonce = reader.bytes(nonce_len)?.to_vec();
+        let check = reader.array()?;
+        let header = Header {
+            size,
+            mode,
+            kdf,
+            nonce,
+            check,
+        };
+        Ok((header, reader.position))
+    }
+
+    // Reads exactly the header from the front of a stream and returns it
+    // with its raw bytes.
+    pub fn read<R: Read>(reader: &mut R) -> Result<(Header, Vec<u8>), Error> {
+        read_with(reader, MAGIC.len(), Header::parse)
+    }
+}
+
+// Reads a self-delimiting structure from a stream by feeding `parse` one
+// more byte each time it reports the input as truncated, so nothing past the
+// end of the structure is consumed.
+pub(super) fn read_with<R, T, F>(
+    reader: &mut R,
+    minimum: usize,
+    parse: F,
+) -> Result<(T, Vec<u8>), Error>
+where
+    R: Read,
+    F: Fn(&[u8]) -> Result<(T, usize), Error>,
+{
+    let mut bytes = Vec::new();
+    reader
+        .by_ref()
+        .take(minimum as u64)
+        .read_to_end(&mut bytes)?;
+    loop {
+        match parse(&bytes) {
+            Err(Error::Header(HeaderError::Truncated)) => {
+                let mut byte = [0];
+                reader.read_exact(&mut byte).map_err(|error| {
+                    if error.kind() == std::io::ErrorKind::UnexpectedEof {
+                        HeaderError::Truncated.into()
+                    } else {
+                        Error::from(error)
+                    }
+                })?;
+                bytes.push(byte[0]);
+            }
+            Ok((value, _)) => return Ok((value, bytes)),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+pub(super) fn invalid(message: &str) -> Error {
+    HeaderError::InvalidParameter(message.to_string()).into()
+}
+
+pub(super) fn cipher_id(size: &AesSize) -> u8 {
+    match size {
+        AesSize::S128 => 1,
+        AesSize::S192 => 2,
+        AesSize::S256 => 3,
+    }
+}
+
+pub(super) fn cipher_from_id(id: u8) -> Result<AesSize, HeaderError> {
+    match id {
+        1 => Ok(AesSize::S128),
+        2 => Ok(AesSize::S192),
+        3 => Ok(AesSize::S256),
+        _ => Err(HeaderError::UnknownCipher(id)),
+    }
+}
+
+pub(super) struct Reader<'a> {
+    pub data: &'a [u8],
+    pub position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], HeaderError> {
+        let end = self.position + len;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or(HeaderError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn byte(&mut self) -> Result<u8, HeaderError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], HeaderError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            size: AesSize::S256,
+            mode: Mode::Gcm,
+            kdf: Kdf::Pbkdf2Sha256 {
+                iterations: 1000,
+                salt: vec![0xaa; 4],
+            },
+            nonce: vec![0x11; 12],
+            check: [0x22; CHECK_LEN],
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let mut expected = b"AESC".to_vec();
+        expected.extend_from_slice(&[2, 3, 1, 1, 0, 0, 0x03, 0xe8, 4, 0xaa, 0xaa, 0xaa, 0xaa]);
+        expected.push(12);
+        expected.extend_from_slice(&[0x11; 12]);
+        expected.extend_from_slice(&[0x22; CHECK_LEN]);
+        assert_eq!(header().to_bytes(), expected);
+
+        let mut data = expected.clone();
+        data.extend_from_slice(b"ciphertext");
+        assert_eq!(Header::parse(&data).unwrap(), (header(), expected.len()));
+    }
+
+    #[test]
+    fn version_1_is_rejected() {
+        let mut bytes = header().to_bytes();
+        bytes[4] = 1;
+        bytes.truncate(bytes.len() - CHECK_LEN);
+        assert_eq!(
+            Header::parse(&bytes),
+            Err(Error::Header(HeaderError::UnsupportedVersion(1)))
+        );
+    }
+
+    #[test]
+    fn read_from_stream() {
+        let bytes = header().to_bytes();
+        let mut data = bytes.clone();
+        data.extend_from_slice(b"body");
+        let mut reader = &data[..];
+        assert_eq!(
+            Header::read(&mut reader).unwrap(),
+            (header(), bytes.clone())
+        );
+        assert_eq!(reader, b"body");
+
+        let mut truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            Header::read(&mut truncated),
+            Err(Error::Header(HeaderError::Truncated))
+        );
+        assert_eq!(
+            Header::read(&mut &b"AE"[..]),
+            Err(Error::Header(HeaderError::BadMagic))
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = header().to_bytes();
+        for len in MAGIC.len()..bytes.len() {
+            assert_eq!(
+                Header::parse(&bytes[..len]),
+                Err(Error::Header(HeaderError::Truncated)),
+                "length {}",
+                len
+            );
+        }
+        for len in 0..MAGIC.len() {
+            assert_eq!(
+                Header::parse(&bytes[..len]),
+                Err(Error::Header(HeaderError::BadMagic))
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let bytes = header().to_bytes();
+        let with = |index: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = value;
+            Header::parse(&bytes)
+        };
+        assert_eq!(with(0, b'X'), Err(Error::Header(HeaderError::BadMagic)));
+        assert_eq!(
+            with(4, 3),
+            Err(Error::Header(HeaderError::UnsupportedVersion(3)))
+        );
+        assert_eq!(
+            with(5, 9),
+            Err(Error::Header(HeaderError::UnknownCipher(9)))
+        );
+        assert_eq!(with(6, 0), Err(Error::Header(HeaderError::UnknownMode(0))));
+        assert_eq!(with(7, 7), Err(Error::Header(HeaderError::UnknownKdf(7))));
+        assert!(matches!(
+            with(6, 2),
+            Err(Error::Header(HeaderError::InvalidParameter(_)))
+        ));
+        let mut no_iterations = header();
+        no_iterations.kdf = Kdf::Pbkdf2Sha256 {
+            iterations: 0,
+            salt: vec![1],
+        };
+        assert!(matches!(
+            Header::parse(&no_iterations.to_bytes()),
+            Err(Error::Header(HeaderError::InvalidParameter(_)))
+        ));
+        let mut too_many = header();
+        too_many.kdf = Kdf::Pbkdf2Sha256 {
+            iterations: u32::MAX,
+            salt: vec![1],
+        };
+        assert!(matches!(
+            Header::parse(&too_many.to_bytes()),
+            Err(Error::Header(HeaderError::InvalidParameter(_)))
+        ));
+    }
+}
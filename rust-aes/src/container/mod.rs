@@ -0,0 +1,442 @@
+use std::io::{Read, Write};
+
+use crate::aes::{AesSize, Key};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Sha256};
+use crate::io::{DecryptReader, EncryptWriter};
+use crate::kdf::pbkdf2;
+use crate::modes::etm::{self, EncryptThenMac};
+use crate::modes::gcm::Gcm;
+use crate::modes::stream::Stream;
+use crate::rng::RngSource;
+
+pub use self::header::{Header, Kdf, Mode, MAGIC, MAX_ITERATIONS, VERSION};
+
+pub mod envelope;
+mod header;
+
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+pub const SALT_LEN: usize = 16;
+
+pub struct Params {
+    pub size: AesSize,
+    pub mode: Mode,
+    pub iterations: u32,
+    // Worker threads for stream containers; the output does not depend on it.
+    pub threads: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            size: AesSize::S128,
+            mode: Mode::Stream,
+            iterations: DEFAULT_ITERATIONS,
+            threads: 1,
+        }
+    }
+}
+
+pub fn is_container(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn seal(
+    password: &str,
+    params: &Params,
+    rng: &mut dyn RngSource,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut sealed = Vec::new();
+    seal_stream(password, params, rng, &mut &plaintext[..], &mut sealed)?;
+    Ok(sealed)
+}
+
+// Only Mode::Stream runs in constant memory, the other modes buffer the
+// whole input.
+pub fn seal_stream<R: Read, W: Write>(
+    password: &str,
+    params: &Params,
+    rng: &mut dyn RngSource,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    if params.iterations == 0 || params.iterations > MAX_ITERATIONS {
+        return Err(Error::InvalidInput(format!(
+            "PBKDF2 needs 1 to {} iterations",
+            MAX_ITERATIONS
+        )));
+    }
+    let kdf = Kdf::Pbkdf2Sha256 {
+        iterations: params.iterations,
+        salt: rng.random_vec(SALT_LEN)?,
+    };
+    let key = derive_key(password, &params.size, &kdf)?;
+    let header = Header {
+        size: params.size.clone(),
+        mode: params.mode,
+        kdf,
+        nonce: rng.random_vec(params.mode.nonce_len())?,
+        check: key.check_value(),
+    };
+    write_body(&key, &header, params.threads, reader, writer)
+}
+
+pub fn seal_with_key(
+    key: &Key,
+    mode: Mode,
+    rng: &mut dyn RngSource,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let header = Header {
+        size: key.size.clone(),
+        mode,
+        kdf: Kdf::None,
+        nonce: rng.random_vec(mode.nonce_len())?,
+        check: key.check_value(),
+    };
+    let mut sealed = Vec::new();
+    write_body(key, &header, 1, &mut &plaintext[..], &mut sealed)?;
+    Ok(sealed)
+}
+
+pub fn open(password: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut plaintext = Vec::new();
+    open_stream(password, 1, &mut &data[..], &mut plaintext)?;
+    Ok(plaintext)
+}
+
+// As with DecryptReader, output written before an error must be discarded.
+pub fn open_stream<R: Read, W: Write>(
+    password: &str,
+    threads: usize,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let (header, header_bytes) = Header::read(reader)?;
+    let key = derive_key(password, &header.size, &header.kdf)?;
+    verify_check(&key, &header)?;
+    read_body(&key, &header, &header_bytes, threads, reader, writer)
+}
+
+pub fn open_with_key(key: &Key, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let (header, header_len) = Header::parse(data)?;
+    if header.kdf != Kdf::None {
+        return Err(Error::InvalidInput(
+            "container is protected by a password".to_string(),
+        ));
+    }
+    if header.size != key.size {
+        return Err(Error::InvalidInput(format!(
+            "container needs an AES-{} key",
+            header.size.bits()
+        )));
+    }
+    verify_check(key, &header)?;
+    let mut plaintext = Vec::new();
+    read_body(
+        key,
+        &header,
+        &data[..header_len],
+        1,
+        &mut &data[header_len..],
+        &mut plaintext,
+    )?;
+    Ok(plaintext)
+}
+
+// The reader implements Seek when `reader` does, giving random access into
+// stream containers without decrypting from the start.
+pub fn open_reader<R: Read>(password: &str, mut reader: R) -> Result<DecryptReader<R>, Error> {
+    let (header, header_bytes) = Header::read(&mut reader)?;
+    if header.mode != Mode::Stream {
+        return Err(Error::InvalidInput(
+            "only stream containers can be read incrementally".to_string(),
+        ));
+    }
+    let key = derive_key(password, &header.size, &header.kdf)?;
+    verify_check(&key, &header)?;
+    let stream = Stream::new(&key, &header.nonce)?;
+    Ok(DecryptReader::stream(reader, stream, &header_bytes))
+}
+
+fn derive_key(password: &str, size: &AesSize, kdf: &Kdf) -> Result<Key, Error> {
+    match kdf {
+        Kdf::Pbkdf2Sha256 { iterations, salt } => {
+            let bytes =
+                pbkdf2::derive::<Sha256>(password.as_bytes(), salt, *iterations, size.key_len());
+            Ok(Key::from_bytes(&bytes))
+        }
+        Kdf::None => Err(Error::InvalidInput(
+            "container is sealed with a raw key, not a password".to_string(),
+        )),
+    }
+}
+
+fn verify_check(key: &Key, header: &Header) -> Result<(), Error> {
+    if constant_time_eq(&header.check, &key.check_value()) {
+        Ok(())
+    } else {
+        Err(Error::WrongKey)
+    }
+}
+
+fn write_body<R: Read, W: Write>(
+    key: &Key,
+    header: &Header,
+    threads: usize,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let header_bytes = header.to_bytes();
+    writer.write_all(&header_bytes)?;
+    if header.mode == Mode::Stream {
+        let stream = Stream::new(key, &header.nonce)?;
+        let mut encryptor =
+            EncryptWriter::stream(writer, stream, &header_bytes).with_threads(threads);
+        std::io::copy(reader, &mut encryptor)?;
+        encryptor.finish()?;
+        return Ok(());
+    }
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    let body = match header.mode {
+        Mode::EtmCtr | Mode::EtmCbc => {
+            let mut iv = [0; 16];
+            iv.copy_from_slice(&header.nonce);
+            // The IV is already in the header, drop the copy EtM prepends.
+            etm_cipher(key, header.mode).seal_with_iv(&iv, &header_bytes, &plaintext)[16..].to_vec()
+        }
+        _ => Gcm::new(key).seal(&header.nonce, &header_bytes, &plaintext)?,
+    };
+    writer.write_all(&body)?;
+    Ok(())
+}
+
+fn read_body<R: Read, W: Write>(
+    key: &Key,
+    header: &Header,
+    header_bytes: &[u8],
+    threads: usize,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    if header.mode == Mode::Stream {
+        let stream = Stream::new(key, &header.nonce)?;
+        std::io::copy(
+            &mut DecryptReader::stream(reader, stream, header_bytes).with_threads(threads),
+            writer,
+        )?;
+        return Ok(());
+    }
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+    if body.len() < header.mode.tag_len() {
+        return Err(Error::InvalidLength(format!(
+            "container body must be at least {} bytes, got {}",
+            header.mode.tag_len(),
+            body.len()
+        )));
+    }
+    let plaintext = match header.mode {
+        Mode::EtmCtr | Mode::EtmCbc => {
+            let sealed = [header.nonce.as_slice(), &body].concat();
+            etm_cipher(key, header.mode).open(header_bytes, &sealed)?
+        }
+        _ => Gcm::new(key).open(&header.nonce, header_bytes, &body)?,
+    };
+    writer.write_all(&plaintext)?;
+    Ok(())
+}
+
+fn etm_cipher(key: &Key, mode: Mode) -> EncryptThenMac {
+    let mode = match mode {
+        Mode::EtmCbc => etm::Mode::Cbc,
+        _ => etm::Mode::Ctr,
+    };
+    EncryptThenMac::new(key, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HeaderError;
+    use crate::modes::stream::SEGMENT_SIZE;
+    use crate::rng::SeededRng;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    fn params(size: AesSize, mode: Mode) -> Params {
+        Params {
+            size,
+            mode,
+            iterations: 10,
+            threads: 1,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = SeededRng::from_u64(1);
+        for mode in [Mode::Gcm, Mode::EtmCtr, Mode::EtmCbc, Mode::Stream] {
+            for size in [AesSize::S128, AesSize::S192, AesSize::S256] {
+                let plaintext = b"self-describing";
+                let sealed = seal("hunter2", &params(size, mode), &mut rng, plaintext).unwrap();
+                assert!(is_container(&sealed));
+                assert_eq!(open("hunter2", &sealed).unwrap(), plaintext);
+                assert_eq!(open("hunter3", &sealed), Err(Error::WrongKey));
+            }
+        }
+    }
+
+    #[test]
+    fn raw_key() {
+        let mut rng = SeededRng::from_u64(2);
+        let key = Key::random(AesSize::S256, &mut rng).unwrap();
+        let sealed = seal_with_key(&key, Mode::EtmCtr, &mut rng, b"data").unwrap();
+        assert_eq!(open_with_key(&key, &sealed).unwrap(), b"data");
+        assert!(matches!(
+            open("password", &sealed),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let other = Key::random(AesSize::S128, &mut rng).unwrap();
+        assert!(matches!(
+            open_with_key(&other, &sealed),
+            Err(Error::InvalidInput(_))
+        ));
+        let password_sealed = seal(
+            "password",
+            &params(AesSize::S128, Mode::Gcm),
+            &mut rng,
+            b"data",
+        )
+        .unwrap();
+        assert!(matches!(
+            open_with_key(&other, &password_sealed),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_key_writes_nothing() {
+        let mut rng = SeededRng::from_u64(7);
+        let sealed = seal(
+            "pw",
+            &params(AesSize::S128, Mode::Stream),
+            &mut rng,
+            b"data",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        assert_eq!(
+            open_stream("pv", 1, &mut &sealed[..], &mut output),
+            Err(Error::WrongKey)
+        );
+        assert!(output.is_empty());
+        assert!(matches!(
+            open_reader("pv", &sealed[..]),
+            Err(Error::WrongKey)
+        ));
+
+        let key = Key::random(AesSize::S128, &mut rng).unwrap();
+        let other = Key::random(AesSize::S128, &mut rng).unwrap();
+        let sealed = seal_with_key(&key, Mode::Gcm, &mut rng, b"data").unwrap();
+        assert_eq!(open_with_key(&other, &sealed), Err(Error::WrongKey));
+    }
+
+    #[test]
+    fn header_is_authenticated() {
+        let mut rng = SeededRng::from_u64(3);
+        let key = Key::random(AesSize::S128, &mut rng).unwrap();
+        for mode in [Mode::Gcm, Mode::EtmCtr, Mode::Stream] {
+            let sealed = seal_with_key(&key, mode, &mut rng, b"data").unwrap();
+            let (_, header_len) = Header::parse(&sealed).unwrap();
+            for index in 0..sealed.len() {
+                let mut tampered = sealed.clone();
+                tampered[index] ^= 0x01;
+                let result = open_with_key(&key, &tampered);
+                if index < header_len {
+                    assert!(result.is_err(), "byte {}", index);
+                } else {
+                    assert_eq!(result, Err(Error::Authentication), "byte {}", index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stream_segments() {
+        let mut rng = SeededRng::from_u64(5);
+        let plaintext = vec![0x5a; SEGMENT_SIZE + 100];
+        let mut sealed = Vec::new();
+        seal_stream(
+            "pw",
+            &params(AesSize::S128, Mode::Stream),
+            &mut rng,
+            &mut &plaintext[..],
+            &mut sealed,
+        )
+        .unwrap();
+        let (_, header_len) = Header::parse(&sealed).unwrap();
+        assert_eq!(sealed.len(), header_len + plaintext.len() + 2 * 16);
+
+        let mut opened = Vec::new();
+        open_stream("pw", 1, &mut &sealed[..], &mut opened).unwrap();
+        assert_eq!(opened, plaintext);
+
+        let mut rng = SeededRng::from_u64(5);
+        let mut threaded = Vec::new();
+        let params = Params {
+            threads: 4,
+            ..params(AesSize::S128, Mode::Stream)
+        };
+        seal_stream("pw", &params, &mut rng, &mut &plaintext[..], &mut threaded).unwrap();
+        assert_eq!(threaded, sealed);
+        assert_eq!(
+            open("pw", &sealed[..header_len + SEGMENT_SIZE + 16]),
+            Err(Error::Authentication)
+        );
+    }
+
+    #[test]
+    fn random_access() {
+        let mut rng = SeededRng::from_u64(6);
+        let plaintext: Vec<u8> = (0..SEGMENT_SIZE + 1000).map(|i| (i % 199) as u8).collect();
+        let sealed = seal(
+            "pw",
+            &params(AesSize::S128, Mode::Stream),
+            &mut rng,
+            &plaintext,
+        )
+        .unwrap();
+        let mut reader = open_reader("pw", Cursor::new(&sealed)).unwrap();
+        reader
+            .seek(SeekFrom::Start(SEGMENT_SIZE as u64 + 10))
+            .unwrap();
+        let mut range = [0; 100];
+        reader.read_exact(&mut range).unwrap();
+        assert_eq!(range, plaintext[SEGMENT_SIZE + 10..SEGMENT_SIZE + 110]);
+
+        let gcm = seal("pw", &params(AesSize::S128, Mode::Gcm), &mut rng, b"data").unwrap();
+        assert!(matches!(
+            open_reader("pw", &gcm[..]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut rng = SeededRng::from_u64(4);
+        let sealed = seal("pw", &params(AesSize::S128, Mode::EtmCbc), &mut rng, b"").unwrap();
+        let (_, header_len) = Header::parse(&sealed).unwrap();
+        assert_eq!(
+            open("pw", &sealed[..header_len - 1]),
+            Err(Error::Header(HeaderError::Truncated))
+        );
+        assert!(matches!(
+            open("pw", &sealed[..header_len + 31]),
+            Err(Error::InvalidLength(_))
+        ));
+        assert!(!is_container(b"AES"));
+    }
+}
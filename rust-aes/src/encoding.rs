@@ -0,0 +1,165 @@
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("Invalid hex length: {}", hex.len()));
+    }
+    hex.chunks(2)
+        .map(|pair| Ok((hex_digit(pair[0])? << 4) | hex_digit(pair[1])?))
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Result<u8, String> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(format!("Invalid hex digit: {:?}", digit as char)),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Standard base64 (RFC 4648) with padding.
+pub fn to_base64(bytes: &[u8]) -> String {
+    encode_base64(bytes, BASE64)
+}
+
+pub fn from_base64(text: &str) -> Result<Vec<u8>, String> {
+    decode_base64(text, BASE64)
+}
+
+// The URL and filename safe alphabet of RFC 4648 section 5, with padding.
+pub fn to_base64_url(bytes: &[u8]) -> String {
+    encode_base64(bytes, BASE64_URL)
+}
+
+pub fn from_base64_url(text: &str) -> Result<Vec<u8>, String> {
+    decode_base64(text, BASE64_URL)
+}
+
+// Unpadded base64url, as JOSE uses in compact serializations.
+pub fn to_base64_url_unpadded(bytes: &[u8]) -> String {
+    let mut text = to_base64_url(bytes);
+    text.truncate(text.trim_end_matches('=').len());
+    text
+}
+
+pub fn from_base64_url_unpadded(text: &str) -> Result<Vec<u8>, String> {
+    if text.contains('=') || text.len() % 4 == 1 {
+        return Err(format!("Invalid unpadded base64 length: {}", text.len()));
+    }
+    let padding = "=".repeat((4 - text.len() % 4) % 4);
+    from_base64_url(&(text.to_string() + &padding))
+}
+
+fn encode_base64(bytes: &[u8], alphabet: &[u8; 64]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let value = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(alphabet[(value >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str, alphabet: &[u8; 64]) -> Result<Vec<u8>, String> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return Err(format!("Invalid base64 length: {}", text.len()));
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (index, group) in text.chunks(4).enumerate() {
+        let padding = group
+            .iter()
+            .rev()
+            .take_while(|&&digit| digit == b'=')
+            .count();
+        if padding > 2 || (padding > 0 && index + 1 != text.len() / 4) {
+            return Err("Invalid base64 padding".to_string());
+        }
+        let mut value = 0u32;
+        for &digit in &group[..4 - padding] {
+            value = value << 6 | base64_digit(digit, alphabet)?;
+        }
+        value <<= 6 * padding;
+        bytes.extend_from_slice(&value.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+fn base64_digit(digit: u8, alphabet: &[u8; 64]) -> Result<u32, String> {
+    match digit {
+        b'A'..=b'Z' => Ok((digit - b'A') as u32),
+        b'a'..=b'z' => Ok((digit - b'a' + 26) as u32),
+        b'0'..=b'9' => Ok((digit - b'0' + 52) as u32),
+        _ if digit == alphabet[62] => Ok(62),
+        _ if digit == alphabet[63] => Ok(63),
+        _ => Err(format!("Invalid base64 digit: {:?}", digit as char)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = vec![0x00, 0x0f, 0xa5, 0xff];
+        assert_eq!(to_hex(&bytes), "000fa5ff");
+        assert_eq!(from_hex("000fA5ff").unwrap(), bytes);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+    }
+
+    // RFC 4648 10
+    #[test]
+    fn base64_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(to_base64(plain.as_bytes()), encoded);
+            assert_eq!(from_base64(encoded).unwrap(), plain.as_bytes());
+        }
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(from_base64(&to_base64(&bytes)).unwrap(), bytes);
+        assert!(from_base64("Zm9").is_err());
+        assert!(from_base64("Zg==Zm9v").is_err());
+        assert!(from_base64("Z===").is_err());
+        assert!(from_base64("Zm9!").is_err());
+    }
+
+    #[test]
+    fn base64_url() {
+        let bytes = [0xfb, 0xff, 0xbf];
+        assert_eq!(to_base64(&bytes), "+/+/");
+        assert_eq!(to_base64_url(&bytes), "-_-_");
+        assert_eq!(from_base64_url("-_-_").unwrap(), bytes);
+        assert_eq!(from_base64_url("Zm8=").unwrap(), b"fo");
+        assert!(from_base64_url("+/+/").is_err());
+        assert!(from_base64("-_-_").is_err());
+        assert_eq!(to_base64_url_unpadded(b"fo"), "Zm8");
+        assert_eq!(from_base64_url_unpadded("Zm8").unwrap(), b"fo");
+        assert_eq!(from_base64_url_unpadded("").unwrap(), b"");
+        assert!(from_base64_url_unpadded("Zm8=").is_err());
+        assert!(from_base64_url_unpadded("Zm9vY").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    InvalidLength(String),
+    InvalidPadding,
+    Authentication,
+    // Caught by a key check value before anything was decrypted.
+    WrongKey,
+    // A timestamped token outside its validity period.
+    Expired,
+    // A packet index that was already accepted or is behind the replay window.
+    Replay,
+    Entropy(String),
+    InvalidUtf8,
+    InvalidInput(String),
+    Header(HeaderError),
+    Io(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLength(message) => write!(f, "Invalid length: {}", message),
+            Error::InvalidPadding => write!(f, "Invalid padding"),
+            Error::Authentication => write!(f, "Authentication failed"),
+            Error::WrongKey => write!(f, "Wrong password or key"),
+            Error::Expired => write!(f, "Token expired"),
+            Error::Replay => write!(f, "Replayed or stale packet"),
+            Error::Entropy(message) => write!(f, "Entropy source failed: {}", message),
+            Error::InvalidUtf8 => write!(f, "Decrypted data is not valid UTF-8"),
+            Error::InvalidInput(message) => write!(f, "Invalid input: {}", message),
+            Error::Header(error) => write!(f, "Invalid header: {}", error),
+            Error::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        // Errors raised inside the crate's Read/Write adapters come back out
+        // unchanged.
+        match error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Error>())
+        {
+            Some(inner) => inner.clone(),
+            None => Error::Io(error.to_string()),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownCipher(u8),
+    UnknownMode(u8),
+    UnknownKdf(u8),
+    InvalidParameter(String),
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::Truncated => write!(f, "data ends inside the header"),
+            HeaderError::BadMagic => write!(f, "not an aeslib container"),
+            HeaderError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            HeaderError::UnknownCipher(id) => write!(f, "unknown cipher id {}", id),
+            HeaderError::UnknownMode(id) => write!(f, "unknown mode id {}", id),
+            HeaderError::UnknownKdf(id) => write!(f, "unknown KDF id {}", id),
+            HeaderError::InvalidParameter(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<HeaderError> for Error {
+    fn from(error: HeaderError) -> Self {
+        Error::Header(error)
+    }
+}
@@ -0,0 +1,229 @@
+use crate::aes::{BlockCipher, Key};
+use crate::encoding::{from_hex, to_hex};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha256};
+use crate::kdf::pbkdf2;
+use crate::modes::ctr;
+use crate::padding::{Padding, Pkcs7Padding};
+use crate::rng::RngSource;
+
+// Ansible Vault, as written by `ansible-vault encrypt`:
+//   $ANSIBLE_VAULT;1.1;AES256            (or 1.2;AES256;<vault id>)
+//   hex(hex(salt) "\n" hex(hmac) "\n" hex(ciphertext)), in 80 column lines
+// PBKDF2-SHA256 of the password gives the AES-256 key, the HMAC-SHA256 key
+// and the CTR counter block. The plaintext is PKCS#7 padded even in CTR.
+pub const MAGIC: &str = "$ANSIBLE_VAULT";
+pub const CIPHER: &str = "AES256";
+pub const ITERATIONS: u32 = 10_000;
+pub const SALT_LEN: usize = 32;
+const KEY_LEN: usize = 32;
+const LINE_LEN: usize = 80;
+// Ansible writes this id as a 1.1 vault with no id.
+const DEFAULT_VAULT_ID: &str = "default";
+
+pub fn is_vault(data: &[u8]) -> bool {
+    data.trim_ascii_start()
+        .starts_with(format!("{};", MAGIC).as_bytes())
+}
+
+// The vault id of a 1.2 vault, None for 1.1.
+pub fn vault_id(text: &str) -> Result<Option<String>, Error> {
+    let (vault_id, _) = parse(text)?;
+    Ok(vault_id)
+}
+
+// Writes a 1.2 vault when `vault_id` is set, as Ansible does for any id but
+// "default".
+pub fn encrypt(
+    password: &str,
+    vault_id: Option<&str>,
+    rng: &mut dyn RngSource,
+    plaintext: &[u8],
+) -> Result<String, Error> {
+    let salt = rng.random_vec(SALT_LEN)?;
+    encrypt_with_salt(password, vault_id, &salt, plaintext)
+}
+
+// A wrong password and a modified vault both fail the HMAC check with
+// Error::Authentication.
+pub fn decrypt(password: &str, text: &str) -> Result<Vec<u8>, Error> {
+    let (_, body) = parse(text)?;
+    let fields: Vec<&[u8]> = body.splitn(3, |byte| *byte == b'\n').collect();
+    if fields.len() != 3 {
+        return Err(invalid("expected salt, HMAC and ciphertext"));
+    }
+    let field = |index: usize| {
+        std::str::from_utf8(fields[index])
+            .map_err(|_| invalid("fields must be hex"))
+            .and_then(|hex| from_hex(hex.trim()).map_err(|_| invalid("fields must be hex")))
+    };
+    let (salt, mac, ciphertext) = (field(0)?, field(1)?, field(2)?);
+    let (cipher, mac_key, counter) = derive(password, &salt);
+    if !constant_time_eq(&Hmac::<Sha256>::mac(&mac_key, &ciphertext), &mac) {
+        return Err(Error::Authentication);
+    }
+    Pkcs7Padding.unpad(ctr::apply(&cipher, &counter, &ciphertext))
+}
+
+// Re-encrypts under a new password with a fresh salt, keeping the vault id.
+pub fn rekey(
+    old_password: &str,
+    new_password: &str,
+    rng: &mut dyn RngSource,
+    text: &str,
+) -> Result<String, Error> {
+    let plaintext = decrypt(old_password, text)?;
+    encrypt(new_password, vault_id(text)?.as_deref(), rng, &plaintext)
+}
+
+fn encrypt_with_salt(
+    password: &str,
+    vault_id: Option<&str>,
+    salt: &[u8],
+    plaintext: &[u8],
+) -> Result<String, Error> {
+    let header = match vault_id.filter(|&vault_id| vault_id != DEFAULT_VAULT_ID) {
+        Some(vault_id) => {
+            if vault_id.is_empty() || vault_id.contains([';', '\n', '\r']) {
+                return Err(invalid("vault ids must be one line without ';'"));
+            }
+            format!("{};1.2;{};{}", MAGIC, CIPHER, vault_id)
+        }
+        None => format!("{};1.1;{}", MAGIC, CIPHER),
+    };
+    let (cipher, mac_key, counter) = derive(password, salt);
+    let ciphertext = ctr::apply(&cipher, &counter, &Pkcs7Padding.pad(plaintext)?);
+    let mac = Hmac::<Sha256>::mac(&mac_key, &ciphertext);
+    let body = [to_hex(salt), to_hex(&mac), to_hex(&ciphertext)].join("\n");
+    let hex = to_hex(body.as_bytes());
+    let mut text = header;
+    for line in hex.as_bytes().chunks(LINE_LEN) {
+        text.push('\n');
+        text.push_str(std::str::from_utf8(line).expect("hex is ASCII"));
+    }
+    text.push('\n');
+    Ok(text)
+}
+
+fn derive(password: &str, salt: &[u8]) -> (BlockCipher, Vec<u8>, [u8; 16]) {
+    let bytes = pbkdf2::derive::<Sha256>(password.as_bytes(), salt, ITERATIONS, 2 * KEY_LEN + 16);
+    let cipher = BlockCipher::new(&Key::from_bytes(&bytes[..KEY_LEN]));
+    let mut counter = [0; 16];
+    counter.copy_from_slice(&bytes[2 * KEY_LEN..]);
+    (cipher, bytes[KEY_LEN..2 * KEY_LEN].to_vec(), counter)
+}
+
+// Returns the vault id and the decoded body. Indentation is ignored, so
+// vaults embedded in YAML with `!vault |` can be passed as they are.
+fn parse(text: &str) -> Result<(Option<String>, Vec<u8>), Error> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let header = lines.next().ok_or_else(|| invalid("empty input"))?;
+    let fields: Vec<&str> = header.split(';').collect();
+    if fields[0] != MAGIC || fields.len() < 3 {
+        return Err(invalid("missing $ANSIBLE_VAULT header"));
+    }
+    let vault_id = match (fields[1], fields.len()) {
+        ("1.1", 3) => None,
+        ("1.2", 4) => Some(fields[3].to_string()),
+        (version, _) => {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+    };
+    if fields[2] != CIPHER {
+        return Err(invalid(&format!("unsupported cipher {}", fields[2])));
+    }
+    let hex: String = lines.collect();
+    let body = from_hex(&hex).map_err(|_| invalid("body must be hex"))?;
+    Ok((vault_id, body))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("ansible vault: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    const PASSWORD: &str = "correct horse";
+    const PLAINTEXT: &[u8] = include_bytes!("../../fixtures/ansible/secrets.yml");
+    const VAULT_1_1: &str = include_str!("../../fixtures/ansible/vault-1.1.yml");
+    const VAULT_1_2: &str = include_str!("../../fixtures/ansible/vault-1.2.yml");
+
+    #[test]
+    fn reads_fixtures() {
+        assert!(is_vault(VAULT_1_1.as_bytes()));
+        assert_eq!(decrypt(PASSWORD, VAULT_1_1).unwrap(), PLAINTEXT);
+        assert_eq!(vault_id(VAULT_1_1).unwrap(), None);
+        assert_eq!(decrypt(PASSWORD, VAULT_1_2).unwrap(), PLAINTEXT);
+        assert_eq!(vault_id(VAULT_1_2).unwrap().as_deref(), Some("prod"));
+        assert_eq!(decrypt("wrong", VAULT_1_1), Err(Error::Authentication));
+
+        let embedded: String = VAULT_1_1
+            .lines()
+            .map(|line| format!("          {}\n", line))
+            .collect();
+        assert!(is_vault(embedded.as_bytes()));
+        assert_eq!(decrypt(PASSWORD, &embedded).unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn writes_fixtures() {
+        let salt: Vec<u8> = (0..32).collect();
+        assert_eq!(
+            encrypt_with_salt(PASSWORD, None, &salt, PLAINTEXT).unwrap(),
+            VAULT_1_1
+        );
+        let salt: Vec<u8> = (32..64).collect();
+        assert_eq!(
+            encrypt_with_salt(PASSWORD, Some("prod"), &salt, PLAINTEXT).unwrap(),
+            VAULT_1_2
+        );
+    }
+
+    #[test]
+    fn default_vault_id_writes_1_1() {
+        let salt: Vec<u8> = (0..32).collect();
+        assert_eq!(
+            encrypt_with_salt(PASSWORD, Some("default"), &salt, PLAINTEXT).unwrap(),
+            VAULT_1_1
+        );
+    }
+
+    #[test]
+    fn rekey_keeps_vault_id() {
+        let mut rng = SeededRng::from_u64(1);
+        let rekeyed = rekey(PASSWORD, "battery staple", &mut rng, VAULT_1_2).unwrap();
+        assert_eq!(vault_id(&rekeyed).unwrap().as_deref(), Some("prod"));
+        assert_eq!(decrypt("battery staple", &rekeyed).unwrap(), PLAINTEXT);
+        assert_eq!(decrypt(PASSWORD, &rekeyed), Err(Error::Authentication));
+        assert_eq!(
+            rekey("wrong", "x", &mut rng, VAULT_1_2),
+            Err(Error::Authentication)
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let mut tampered = VAULT_1_1.to_string();
+        tampered.replace_range(40..41, if &tampered[40..41] == "3" { "4" } else { "3" });
+        assert!(decrypt(PASSWORD, &tampered).is_err());
+        for text in [
+            "",
+            "$ANSIBLE_VAULT;1.0;AES256\n00",
+            "$ANSIBLE_VAULT;1.1;AES128\n00",
+            "$ANSIBLE_VAULT;1.2;AES256\n00",
+            "$ANSIBLE_VAULT;1.1;AES256\nzz",
+            "$ANSIBLE_VAULT;1.1;AES256\n6162",
+        ] {
+            assert!(
+                matches!(decrypt(PASSWORD, text), Err(Error::InvalidInput(_))),
+                "{:?}",
+                text
+            );
+        }
+        let mut rng = SeededRng::from_u64(2);
+        assert!(encrypt(PASSWORD, Some("a;b"), &mut rng, b"").is_err());
+    }
+}
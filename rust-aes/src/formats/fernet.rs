@@ -0,0 +1,304 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::aes::{BlockCipher, Key};
+use crate::encoding::{from_base64_url, to_base64_url};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha256};
+use crate::modes::cbc;
+use crate::padding::{Padding, Pkcs7Padding};
+use crate::rng::RngSource;
+
+// Fernet tokens, https://github.com/fernet/spec:
+//   0x80 | timestamp (u64) | IV | AES-128-CBC ciphertext | HMAC-SHA256
+// in URL-safe base64. The 32-byte key is the HMAC key followed by the AES
+// key, also URL-safe base64.
+pub const VERSION: u8 = 0x80;
+pub const KEY_LEN: usize = 32;
+// How far ahead of the clock a token may be dated when a TTL is checked.
+pub const MAX_CLOCK_SKEW: u64 = 60;
+const HEADER_LEN: usize = 1 + 8 + 16;
+const TAG_LEN: usize = 32;
+
+// Seconds since the Unix epoch; injected so expiry can be tested.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+// Not available on wasm32-unknown-unknown, which has no system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+pub struct Fernet {
+    signing_key: Vec<u8>,
+    cipher: BlockCipher,
+    clock: Box<dyn Clock>,
+}
+
+impl Fernet {
+    pub fn new(key: &str) -> Result<Fernet, Error> {
+        let bytes = from_base64_url(key)
+            .ok()
+            .filter(|bytes| bytes.len() == KEY_LEN)
+            .ok_or_else(|| {
+                Error::InvalidInput("Fernet keys are 32 bytes of URL-safe base64".to_string())
+            })?;
+        Ok(Fernet {
+            signing_key: bytes[..16].to_vec(),
+            cipher: BlockCipher::new(&Key::from_bytes(&bytes[16..])),
+            clock: Box::new(SystemClock),
+        })
+    }
+
+    pub fn generate_key(rng: &mut dyn RngSource) -> Result<String, Error> {
+        Ok(to_base64_url(&rng.random_vec(KEY_LEN)?))
+    }
+
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], rng: &mut dyn RngSource) -> Result<String, Error> {
+        self.encrypt_at(self.clock.now(), plaintext, rng)
+    }
+
+    pub fn encrypt_at(
+        &self,
+        timestamp: u64,
+        plaintext: &[u8],
+        rng: &mut dyn RngSource,
+    ) -> Result<String, Error> {
+        let iv = rng.random_block()?;
+        let mut token = vec![VERSION];
+        token.extend_from_slice(&timestamp.to_be_bytes());
+        token.extend_from_slice(&iv);
+        token.extend(cbc::encrypt(
+            &self.cipher,
+            &iv,
+            &Pkcs7Padding.pad(plaintext)?,
+        )?);
+        let tag = Hmac::<Sha256>::mac(&self.signing_key, &token);
+        token.extend_from_slice(&tag);
+        Ok(to_base64_url(&token))
+    }
+
+    // With a TTL, tokens older than `ttl` seconds or dated more than
+    // MAX_CLOCK_SKEW into the future fail with Error::Expired.
+    pub fn decrypt(&self, token: &str, ttl: Option<u64>) -> Result<Vec<u8>, Error> {
+        let (timestamp, bytes) = self.verify(token)?;
+        if let Some(ttl) = ttl {
+            let now = self.clock.now();
+            if timestamp.saturating_add(ttl) < now || now.saturating_add(MAX_CLOCK_SKEW) < timestamp {
+                return Err(Error::Expired);
+            }
+        }
+        let mut iv = [0; 16];
+        iv.copy_from_slice(&bytes[9..HEADER_LEN]);
+        let ciphertext = &bytes[HEADER_LEN..bytes.len() - TAG_LEN];
+        Pkcs7Padding.unpad(cbc::decrypt(&self.cipher, &iv, ciphertext)?)
+    }
+
+    // The creation time of an authentic token.
+    pub fn timestamp(&self, token: &str) -> Result<u64, Error> {
+        Ok(self.verify(token)?.0)
+    }
+
+    fn verify(&self, token: &str) -> Result<(u64, Vec<u8>), Error> {
+        let bytes = from_base64_url(token)
+            .map_err(|error| Error::InvalidInput(format!("Fernet token: {}", error)))?;
+        if bytes.len() < HEADER_LEN + TAG_LEN + 16 || bytes[0] != VERSION {
+            return Err(Error::InvalidInput(
+                "Fernet token: bad version or length".to_string(),
+            ));
+        }
+        let (signed, tag) = bytes.split_at(bytes.len() - TAG_LEN);
+        if !constant_time_eq(&Hmac::<Sha256>::mac(&self.signing_key, signed), tag) {
+            return Err(Error::Authentication);
+        }
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[1..9]);
+        Ok((u64::from_be_bytes(timestamp), bytes))
+    }
+}
+
+// Key rotation: encrypts with the first key and decrypts with any of them.
+pub struct MultiFernet {
+    fernets: Vec<Fernet>,
+}
+
+impl MultiFernet {
+    pub fn new(fernets: Vec<Fernet>) -> Result<MultiFernet, Error> {
+        if fernets.is_empty() {
+            return Err(Error::InvalidInput(
+                "MultiFernet needs at least one key".to_string(),
+            ));
+        }
+        Ok(MultiFernet { fernets })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], rng: &mut dyn RngSource) -> Result<String, Error> {
+        self.fernets[0].encrypt(plaintext, rng)
+    }
+
+    pub fn decrypt(&self, token: &str, ttl: Option<u64>) -> Result<Vec<u8>, Error> {
+        self.find(token)?.decrypt(token, ttl)
+    }
+
+    // Re-encrypts under the first key, keeping the original timestamp so
+    // rotation does not extend a token's lifetime.
+    pub fn rotate(&self, token: &str, rng: &mut dyn RngSource) -> Result<String, Error> {
+        let fernet = self.find(token)?;
+        let timestamp = fernet.timestamp(token)?;
+        let plaintext = fernet.decrypt(token, None)?;
+        self.fernets[0].encrypt_at(timestamp, &plaintext, rng)
+    }
+
+    fn find(&self, token: &str) -> Result<&Fernet, Error> {
+        let mut error = Error::Authentication;
+        for fernet in &self.fernets {
+            match fernet.verify(token) {
+                Ok(_) => return Ok(fernet),
+                Err(Error::Authentication) => {}
+                Err(other) => error = other,
+            }
+        }
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    // The fernet/spec generate.json and verify.json case
+    const SECRET: &str = "cw_0x689RpI-jtRR7oE8h_eQsKImvJapLeSbXpwF4e4=";
+    const TOKEN: &str = "gAAAAAAdwJ6wAAECAwQFBgcICQoLDA0ODy021cpGVWKZ_eEwCGM4BLLF_5CV9dOPmrhuVUPgJobwOz7JcbmrR64jVmpU4IwqDA==";
+    // 1985-10-26T01:20:00-07:00
+    const NOW: u64 = 499_162_800;
+
+    struct FixedIv;
+
+    impl RngSource for FixedIv {
+        fn fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+            Ok(())
+        }
+    }
+
+    fn fernet(now: u64) -> Fernet {
+        Fernet::new(SECRET)
+            .unwrap()
+            .with_clock(Box::new(FixedClock(now)))
+    }
+
+    #[test]
+    fn spec_vectors() {
+        assert_eq!(fernet(NOW).encrypt(b"hello", &mut FixedIv).unwrap(), TOKEN);
+        assert_eq!(fernet(NOW + 1).decrypt(TOKEN, Some(60)).unwrap(), b"hello");
+        assert_eq!(fernet(NOW).timestamp(TOKEN).unwrap(), NOW);
+    }
+
+    #[test]
+    fn ttl() {
+        assert_eq!(fernet(NOW + 60).decrypt(TOKEN, Some(60)).unwrap(), b"hello");
+        assert_eq!(
+            fernet(NOW + 61).decrypt(TOKEN, Some(60)),
+            Err(Error::Expired)
+        );
+        assert_eq!(fernet(NOW - 60).decrypt(TOKEN, Some(60)).unwrap(), b"hello");
+        assert_eq!(
+            fernet(NOW - 61).decrypt(TOKEN, Some(60)),
+            Err(Error::Expired)
+        );
+        assert_eq!(fernet(u64::MAX).decrypt(TOKEN, None).unwrap(), b"hello");
+        assert_eq!(
+            fernet(u64::MAX).decrypt(TOKEN, Some(60)),
+            Err(Error::Expired)
+        );
+        assert_eq!(
+            fernet(u64::MAX).decrypt(TOKEN, Some(u64::MAX)).unwrap(),
+            b"hello"
+        );
+    }
+
+    // Cases from the fernet/spec invalid.json
+    #[test]
+    fn invalid_tokens() {
+        let fernet = fernet(NOW);
+        let bytes = from_base64_url(TOKEN).unwrap();
+        let mut version = bytes.clone();
+        version[0] = 0x81;
+        let mut tag = bytes.clone();
+        *tag.last_mut().unwrap() ^= 1;
+        let mut ciphertext = bytes.clone();
+        ciphertext[HEADER_LEN] ^= 1;
+        for token in [tag, ciphertext] {
+            assert_eq!(
+                fernet.decrypt(&to_base64_url(&token), None),
+                Err(Error::Authentication)
+            );
+        }
+        for token in [
+            to_base64_url(&version),
+            to_base64_url(&bytes[..bytes.len() - 1]),
+            to_base64_url(&bytes[..HEADER_LEN + TAG_LEN]),
+            TOKEN.replace('_', "/"),
+            TOKEN.trim_end_matches('=').to_string(),
+        ] {
+            assert!(matches!(
+                fernet.decrypt(&token, None),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        assert!(Fernet::new("c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn rotation() {
+        let mut rng = SeededRng::from_u64(1);
+        let old = Fernet::generate_key(&mut rng).unwrap();
+        let new = Fernet::generate_key(&mut rng).unwrap();
+        let keys = |names: &[&str]| {
+            let fernets = names
+                .iter()
+                .map(|key| {
+                    Fernet::new(key)
+                        .unwrap()
+                        .with_clock(Box::new(FixedClock(NOW)))
+                })
+                .collect();
+            MultiFernet::new(fernets).unwrap()
+        };
+        let token = keys(&[&old]).encrypt(b"secret", &mut rng).unwrap();
+        let rotating = keys(&[&new, &old]);
+        assert_eq!(rotating.decrypt(&token, Some(10)).unwrap(), b"secret");
+
+        let rotated = rotating.rotate(&token, &mut rng).unwrap();
+        assert_ne!(rotated, token);
+        assert_eq!(keys(&[&new]).decrypt(&rotated, None).unwrap(), b"secret");
+        assert_eq!(Fernet::new(&new).unwrap().timestamp(&rotated).unwrap(), NOW);
+        assert_eq!(
+            keys(&[&new]).decrypt(&token, None),
+            Err(Error::Authentication)
+        );
+        assert!(MultiFernet::new(Vec::new()).is_err());
+    }
+}
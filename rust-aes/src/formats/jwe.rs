@@ -0,0 +1,524 @@
+use super::json::{self, Value};
+use crate::aes::{BlockCipher, Key};
+use crate::encoding::{from_base64_url_unpadded, to_base64_url_unpadded};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha256};
+use crate::kdf::pbkdf2;
+use crate::modes::gcm::Gcm;
+use crate::modes::{cbc, kw};
+use crate::padding::{Padding, Pkcs7Padding};
+use crate::rng::RngSource;
+
+// JWE compact serialization, RFC 7516:
+//   header . encrypted key . IV . ciphertext . tag
+// each unpadded base64url. The encoded protected header is the AAD. Only
+// symmetric key management from RFC 7518 is supported, and the key passed
+// in is the shared key, or the password for PBES2.
+pub const PBES2_SALT_LEN: usize = 16;
+// What encrypt uses for PBES2; RFC 7518 asks for at least 1000.
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+// Higher p2c values are refused on decrypt, since the sender picks them.
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+// The "alg" header: how the content encryption key is agreed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alg {
+    // The key is the content encryption key.
+    Direct,
+    A128Kw,
+    A256Kw,
+    // PBKDF2-SHA256 of the password gives an A128KW key.
+    Pbes2Hs256A128Kw { iterations: u32 },
+}
+
+impl Alg {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Alg::Direct => "dir",
+            Alg::A128Kw => "A128KW",
+            Alg::A256Kw => "A256KW",
+            Alg::Pbes2Hs256A128Kw { .. } => "PBES2-HS256+A128KW",
+        }
+    }
+}
+
+// The "enc" header: how the content is encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enc {
+    A128Gcm,
+    A256Gcm,
+    // AES-128-CBC with an HMAC-SHA256 tag truncated to 16 bytes, RFC 7518
+    // section 5.2.
+    A128CbcHs256,
+}
+
+impl Enc {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Enc::A128Gcm => "A128GCM",
+            Enc::A256Gcm => "A256GCM",
+            Enc::A128CbcHs256 => "A128CBC-HS256",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Enc, Error> {
+        [Enc::A128Gcm, Enc::A256Gcm, Enc::A128CbcHs256]
+            .into_iter()
+            .find(|enc| enc.name() == name)
+            .ok_or_else(|| invalid(&format!("unsupported enc {:?}", name)))
+    }
+
+    pub fn key_len(&self) -> usize {
+        match self {
+            Enc::A128Gcm => 16,
+            Enc::A256Gcm | Enc::A128CbcHs256 => 32,
+        }
+    }
+
+    fn iv_len(&self) -> usize {
+        match self {
+            Enc::A128Gcm | Enc::A256Gcm => 12,
+            Enc::A128CbcHs256 => 16,
+        }
+    }
+
+    fn seal(&self, cek: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Enc::A128Gcm | Enc::A256Gcm => Gcm::new(&Key::from_bytes(cek)).seal(iv, aad, plaintext),
+            Enc::A128CbcHs256 => {
+                let cipher = BlockCipher::new(&Key::from_bytes(&cek[16..]));
+                let ciphertext =
+                    cbc::encrypt(&cipher, &to_block(iv), &Pkcs7Padding.pad(plaintext)?)?;
+                let tag = cbc_hs256_tag(&cek[..16], aad, iv, &ciphertext);
+                Ok([ciphertext, tag].concat())
+            }
+        }
+    }
+
+    fn open(&self, cek: &[u8], iv: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Enc::A128Gcm | Enc::A256Gcm => Gcm::new(&Key::from_bytes(cek)).open(iv, aad, sealed),
+            Enc::A128CbcHs256 => {
+                let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+                if !constant_time_eq(&cbc_hs256_tag(&cek[..16], aad, iv, ciphertext), tag) {
+                    return Err(Error::Authentication);
+                }
+                let cipher = BlockCipher::new(&Key::from_bytes(&cek[16..]));
+                Pkcs7Padding.unpad(cbc::decrypt(&cipher, &to_block(iv), ciphertext)?)
+            }
+        }
+    }
+}
+
+// Both content encryption algorithms have 16-byte tags.
+const TAG_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub alg: Alg,
+    pub enc: Enc,
+    pub kid: Option<String>,
+}
+
+impl Header {
+    pub fn new(alg: Alg, enc: Enc) -> Header {
+        Header {
+            alg,
+            enc,
+            kid: None,
+        }
+    }
+
+    fn to_json(&self, salt: &[u8]) -> Value {
+        let string = |text: &str| Value::String(text.to_string());
+        let mut fields = vec![("alg".to_string(), string(self.alg.name()))];
+        if let Some(kid) = &self.kid {
+            fields.push(("kid".to_string(), string(kid)));
+        }
+        if let Alg::Pbes2Hs256A128Kw { iterations } = self.alg {
+            fields.push(("p2s".to_string(), string(&to_base64_url_unpadded(salt))));
+            fields.push(("p2c".to_string(), Value::Number(iterations as f64)));
+        }
+        fields.push(("enc".to_string(), string(self.enc.name())));
+        Value::Object(fields)
+    }
+
+    // Returns the header and the PBES2 salt input, if any.
+    fn from_json(value: &Value) -> Result<(Header, Vec<u8>), Error> {
+        if !matches!(value, Value::Object(_)) {
+            return Err(invalid("the header must be an object"));
+        }
+        for unsupported in ["zip", "crit"] {
+            if value.get(unsupported).is_some() {
+                return Err(invalid(&format!("unsupported header {:?}", unsupported)));
+            }
+        }
+        let string = |name: &str| {
+            value
+                .get(name)
+                .map(|field| {
+                    field
+                        .as_str()
+                        .ok_or_else(|| invalid(&format!("{} must be a string", name)))
+                })
+                .transpose()
+        };
+        let enc = Enc::parse(string("enc")?.ok_or_else(|| invalid("missing enc"))?)?;
+        let kid = string("kid")?.map(str::to_string);
+        let mut salt = Vec::new();
+        let alg = match string("alg")?.ok_or_else(|| invalid("missing alg"))? {
+            "dir" => Alg::Direct,
+            "A128KW" => Alg::A128Kw,
+            "A256KW" => Alg::A256Kw,
+            "PBES2-HS256+A128KW" => {
+                salt = string("p2s")?
+                    .and_then(|p2s| from_base64_url_unpadded(p2s).ok())
+                    .filter(|salt| salt.len() >= 8)
+                    .ok_or_else(|| invalid("p2s must be at least 8 bytes"))?;
+                let iterations = value
+                    .get("p2c")
+                    .and_then(Value::as_u64)
+                    .filter(|count| (1..=MAX_ITERATIONS as u64).contains(count))
+                    .ok_or_else(|| invalid(&format!("p2c must be from 1 to {}", MAX_ITERATIONS)))?;
+                Alg::Pbes2Hs256A128Kw {
+                    iterations: iterations as u32,
+                }
+            }
+            other => return Err(invalid(&format!("unsupported alg {:?}", other))),
+        };
+        Ok((Header { alg, enc, kid }, salt))
+    }
+}
+
+pub fn encrypt(
+    header: &Header,
+    key: &[u8],
+    rng: &mut dyn RngSource,
+    plaintext: &[u8],
+) -> Result<String, Error> {
+    let cek = match header.alg {
+        Alg::Direct => key.to_vec(),
+        _ => rng.random_vec(header.enc.key_len())?,
+    };
+    let salt = match header.alg {
+        Alg::Pbes2Hs256A128Kw { .. } => rng.random_vec(PBES2_SALT_LEN)?,
+        _ => Vec::new(),
+    };
+    let iv = rng.random_vec(header.enc.iv_len())?;
+    encrypt_with(header, key, &salt, &cek, &iv, plaintext)
+}
+
+// Returns the protected header along with the plaintext, so callers can
+// check the algorithms or pick a key by kid. Wrong keys and modified tokens
+// fail with Error::Authentication.
+pub fn decrypt(token: &str, key: &[u8]) -> Result<(Header, Vec<u8>), Error> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    if parts.len() != 5 {
+        return Err(invalid("a compact JWE has five parts"));
+    }
+    let decoded = parts
+        .iter()
+        .map(|part| from_base64_url_unpadded(part).map_err(|error| invalid(&error)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let header_json =
+        std::str::from_utf8(&decoded[0]).map_err(|_| invalid("the header must be UTF-8"))?;
+    let (header, salt) =
+        Header::from_json(&json::parse(header_json).map_err(|error| invalid(&error))?)?;
+    let (encrypted_key, iv, ciphertext, tag) = (&decoded[1], &decoded[2], &decoded[3], &decoded[4]);
+    if iv.len() != header.enc.iv_len() || tag.len() != TAG_LEN {
+        return Err(invalid("wrong IV or tag length"));
+    }
+    let cek = match header.alg {
+        Alg::Direct if encrypted_key.is_empty() => {
+            check_key_len(key, header.enc.key_len(), "dir")?;
+            key.to_vec()
+        }
+        Alg::Direct => return Err(invalid("dir takes no encrypted key")),
+        alg => {
+            let cek = kw::unwrap(&key_encryption_key(alg, key, &salt)?, encrypted_key)?;
+            if cek.len() != header.enc.key_len() {
+                return Err(invalid("wrong content encryption key length"));
+            }
+            cek
+        }
+    };
+    let sealed = [ciphertext.as_slice(), tag].concat();
+    let plaintext = header.enc.open(&cek, iv, parts[0].as_bytes(), &sealed)?;
+    Ok((header, plaintext))
+}
+
+fn encrypt_with(
+    header: &Header,
+    key: &[u8],
+    salt: &[u8],
+    cek: &[u8],
+    iv: &[u8],
+    plaintext: &[u8],
+) -> Result<String, Error> {
+    let encrypted_key = match header.alg {
+        Alg::Direct => {
+            check_key_len(key, header.enc.key_len(), "dir")?;
+            Vec::new()
+        }
+        alg => kw::wrap(&key_encryption_key(alg, key, salt)?, cek)?,
+    };
+    let protected = to_base64_url_unpadded(header.to_json(salt).to_string().as_bytes());
+    let mut sealed = header.enc.seal(cek, iv, protected.as_bytes(), plaintext)?;
+    let tag = sealed.split_off(sealed.len() - TAG_LEN);
+    Ok([
+        protected,
+        to_base64_url_unpadded(&encrypted_key),
+        to_base64_url_unpadded(iv),
+        to_base64_url_unpadded(&sealed),
+        to_base64_url_unpadded(&tag),
+    ]
+    .join("."))
+}
+
+fn key_encryption_key(alg: Alg, key: &[u8], salt: &[u8]) -> Result<BlockCipher, Error> {
+    let kek = match alg {
+        Alg::Direct => unreachable!("dir has no key encryption key"),
+        Alg::A128Kw => check_key_len(key, 16, "A128KW")?.to_vec(),
+        Alg::A256Kw => check_key_len(key, 32, "A256KW")?.to_vec(),
+        Alg::Pbes2Hs256A128Kw { iterations } => {
+            if !(1..=MAX_ITERATIONS).contains(&iterations) {
+                return Err(invalid(&format!(
+                    "p2c must be from 1 to {}",
+                    MAX_ITERATIONS
+                )));
+            }
+            // The salt is the algorithm name, a zero byte and p2s.
+            let salt = [alg.name().as_bytes(), &[0], salt].concat();
+            pbkdf2::derive::<Sha256>(key, &salt, iterations, 16)
+        }
+    };
+    Ok(BlockCipher::new(&Key::from_bytes(&kek)))
+}
+
+fn check_key_len<'a>(key: &'a [u8], len: usize, alg: &str) -> Result<&'a [u8], Error> {
+    if key.len() != len {
+        return Err(Error::InvalidLength(format!(
+            "{} needs a {}-byte key, got {}",
+            alg,
+            len,
+            key.len()
+        )));
+    }
+    Ok(key)
+}
+
+fn cbc_hs256_tag(mac_key: &[u8], aad: &[u8], iv: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut hmac = Hmac::<Sha256>::new(mac_key);
+    hmac.update(aad);
+    hmac.update(iv);
+    hmac.update(ciphertext);
+    hmac.update(&(aad.len() as u64 * 8).to_be_bytes());
+    let mut tag = hmac.finalize();
+    tag.truncate(TAG_LEN);
+    tag
+}
+
+fn to_block(iv: &[u8]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block.copy_from_slice(iv);
+    block
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("jwe: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    // RFC 7516 appendix A.3: A128KW with A128CBC-HS256
+    const A3_KEY: &str = "GawgguFyGrWKav7AX4VKUg";
+    const A3_CEK: [u8; 32] = [
+        4, 211, 31, 197, 84, 157, 252, 254, 11, 100, 157, 250, 63, 170, 106, 206, 107, 124, 212,
+        45, 111, 107, 9, 219, 200, 177, 0, 240, 143, 156, 44, 207,
+    ];
+    const A3_IV: [u8; 16] = [
+        3, 22, 60, 12, 43, 67, 104, 105, 108, 108, 105, 99, 111, 116, 104, 101,
+    ];
+    const A3_TOKEN: &str = "eyJhbGciOiJBMTI4S1ciLCJlbmMiOiJBMTI4Q0JDLUhTMjU2In0.\
+        6KB707dM9YTIgHtLvtgWQ8mKwboJW3of9locizkDTHzBC2IlrT1oOQ.\
+        AxY8DCtDaGlsbGljb3RoZQ.\
+        KDlTtXchhZTGufMYmOYGS4HffxPSUrfmqCHXaI9wOGY.\
+        U0m_YmjN04DJvceFICbCVQ";
+
+    // RFC 7520 section 5.8: A128KW with A128GCM and a kid
+    const FRODO: &str = "You can trust us to stick with you through thick and thin\u{2013}to the \
+        bitter end. And you can trust us to keep any secret of yours\u{2013}closer than you keep \
+        it yourself. But you cannot trust us to let you face trouble alone, and go off without \
+        a word. We are your friends, Frodo.";
+    const S58_TOKEN: &str = "eyJhbGciOiJBMTI4S1ciLCJraWQiOiI4MWIyMDk2NS04MzMyLTQzZDktYTQ2OC04MjE2\
+        MGFkOTFhYzgiLCJlbmMiOiJBMTI4R0NNIn0.\
+        CBI6oDw8MydIx1IBntf_lQcw2MmJKIQx.\
+        Qx0pmsDa8KnJc9Jo.\
+        AwliP-KmWgsZ37BvzCefNen6VTbRK3QMA4TkvRkH0tP1bTdhtFJgJxeVmJkLD61A1hnWGetdg11c9ADsnWgL56NyxwSYjU1ZEHcGkd3E\
+        kU0vjHi9gTlb90qSYFfeF0LwkcTtjbYKCsiNJQkcIp1yeM03OmuiYSoYJVSpf7ej6zaYcMv3WwdxDFl8REwOhNImk2Xld2JXq6BR53TS\
+        FkyT7PwVLuq-1GwtGHlQeg7gDT6xW0JqHDPn_H-puQsmthc9Zg0ojmJfqqFvETUxLAF-KjcBTS5dNy6egwkYtOt8EIHK-oEsKYtZRaa8\
+        Z7MOZ7UGxGIMvEmxrGCPeJa14slv2-gaqK0kEThkaSqdYw0FkQZF.\
+        ER7MWJZ1FBI_NKvn7Zb1Lw";
+
+    fn decode(text: &str) -> Vec<u8> {
+        from_base64_url_unpadded(text).unwrap()
+    }
+
+    #[test]
+    fn rfc7516_a3() {
+        let key = decode(A3_KEY);
+        let (header, plaintext) = decrypt(A3_TOKEN, &key).unwrap();
+        assert_eq!(header, Header::new(Alg::A128Kw, Enc::A128CbcHs256));
+        assert_eq!(plaintext, b"Live long and prosper.");
+        let token = encrypt_with(&header, &key, &[], &A3_CEK, &A3_IV, &plaintext).unwrap();
+        assert_eq!(token, A3_TOKEN);
+    }
+
+    #[test]
+    fn rfc7520_5_8() {
+        let key = decode("GZy6sIZ6wl9NJOKB-jnmVQ");
+        let (header, plaintext) = decrypt(S58_TOKEN, &key).unwrap();
+        assert_eq!(
+            header.kid.as_deref(),
+            Some("81b20965-8332-43d9-a468-82160ad91ac8")
+        );
+        assert_eq!(plaintext, FRODO.as_bytes());
+        let cek = decode("aY5_Ghmk9KxWPBLu_glx1w");
+        let iv = decode("Qx0pmsDa8KnJc9Jo");
+        let token = encrypt_with(&header, &key, &[], &cek, &iv, &plaintext).unwrap();
+        assert_eq!(token, S58_TOKEN);
+    }
+
+    // Computed with the Python cryptography package.
+    #[test]
+    fn a256kw_a256gcm() {
+        let key: Vec<u8> = (0..32).collect();
+        let cek: Vec<u8> = (32..64).collect();
+        let iv: Vec<u8> = (64..76).collect();
+        let header = Header::new(Alg::A256Kw, Enc::A256Gcm);
+        let token = encrypt_with(&header, &key, &[], &cek, &iv, b"Live long and prosper.").unwrap();
+        assert_eq!(
+            token,
+            "eyJhbGciOiJBMjU2S1ciLCJlbmMiOiJBMjU2R0NNIn0.\
+             BPijw8MC07C36UsU3Pha0dppzXQFbteQfTy0n7J3maQQTbBY8pAa2w.QEFCQ0RFRkdISUpL.\
+             jj3L5HAaqmU1EjFG4WohAHz97eaHbg._QEn0EV1AlTkef1BanCScA"
+        );
+        assert_eq!(decrypt(&token, &key).unwrap().1, b"Live long and prosper.");
+    }
+
+    // RFC 7517 appendix C wraps a JWK with PBES2; this checks its key.
+    #[test]
+    fn rfc7517_pbes2_key_wrap() {
+        let cek = [
+            111, 27, 25, 52, 66, 29, 20, 78, 92, 176, 56, 240, 65, 208, 82, 112, 161, 131, 36, 55,
+            202, 236, 185, 172, 129, 23, 153, 194, 195, 48, 253, 182,
+        ];
+        let kek = key_encryption_key(
+            Alg::Pbes2Hs256A128Kw { iterations: 4096 },
+            b"Thus from my lips, by yours, my sin is purged.",
+            &decode("2WCTcJZ1Rvd_CJuJripQ1w"),
+        )
+        .unwrap();
+        assert_eq!(
+            to_base64_url_unpadded(&kw::wrap(&kek, &cek).unwrap()),
+            "TrqXOwuNUfDV9VPTNbyGvEJ9JMjefAVn-TR1uIxR9p6hsRQh9Tk7BA"
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut rng = SeededRng::from_u64(1);
+        for enc in [Enc::A128Gcm, Enc::A256Gcm, Enc::A128CbcHs256] {
+            for (alg, key) in [
+                (Alg::Direct, vec![7; enc.key_len()]),
+                (Alg::A128Kw, vec![8; 16]),
+                (Alg::A256Kw, vec![9; 32]),
+                (
+                    Alg::Pbes2Hs256A128Kw { iterations: 1000 },
+                    b"password".to_vec(),
+                ),
+            ] {
+                let mut header = Header::new(alg, enc);
+                header.kid = Some("key \"1\"".to_string());
+                let token = encrypt(&header, &key, &mut rng, b"hello").unwrap();
+                assert_eq!(decrypt(&token, &key).unwrap(), (header, b"hello".to_vec()));
+                let mut wrong = key.clone();
+                wrong[0] ^= 1;
+                assert_eq!(
+                    decrypt(&token, &wrong),
+                    Err(Error::Authentication),
+                    "{:?}",
+                    alg
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_modified_tokens() {
+        let key = decode(A3_KEY);
+        let parts: Vec<&str> = A3_TOKEN.split('.').collect();
+        for index in 1..5 {
+            let mut bytes = decode(parts[index]);
+            bytes[0] ^= 1;
+            let mut modified = parts.clone();
+            let encoded = to_base64_url_unpadded(&bytes);
+            modified[index] = &encoded;
+            assert_eq!(
+                decrypt(&modified.join("."), &key),
+                Err(Error::Authentication),
+                "part {}",
+                index
+            );
+        }
+        // A different protected header changes the AAD.
+        let header = to_base64_url_unpadded(br#"{"enc":"A128CBC-HS256","alg":"A128KW"}"#);
+        let reordered = [header.as_str(), parts[1], parts[2], parts[3], parts[4]].join(".");
+        assert_eq!(decrypt(&reordered, &key), Err(Error::Authentication));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let key = decode(A3_KEY);
+        let parts: Vec<&str> = A3_TOKEN.split('.').collect();
+        let with_header = |json: &str| {
+            let header = to_base64_url_unpadded(json.as_bytes());
+            [header.as_str(), parts[1], parts[2], parts[3], parts[4]].join(".")
+        };
+        for token in [
+            parts[..4].join("."),
+            A3_TOKEN.replace('_', "/"),
+            with_header("[]"),
+            with_header(r#"{"alg":"A128KW"}"#),
+            with_header(r#"{"alg":"RSA-OAEP","enc":"A128CBC-HS256"}"#),
+            with_header(r#"{"alg":"A128KW","enc":"A128CBC-HS256","zip":"DEF"}"#),
+            with_header(r#"{"alg":"A128KW","enc":"A128CBC-HS256","crit":["exp"]}"#),
+            with_header(r#"{"alg":"dir","enc":"A128CBC-HS256"}"#),
+            with_header(
+                r#"{"alg":"PBES2-HS256+A128KW","p2s":"AAAAAAAAAAA","p2c":2000000,"enc":"A128GCM"}"#,
+            ),
+            with_header(r#"{"alg":"PBES2-HS256+A128KW","p2s":"AAAA","p2c":1000,"enc":"A128GCM"}"#),
+            with_header(r#"{"alg":"A128KW","enc":"A128GCM"}"#),
+        ] {
+            assert!(
+                matches!(decrypt(&token, &key), Err(Error::InvalidInput(_))),
+                "{}",
+                token
+            );
+        }
+        let header = Header::new(Alg::A256Kw, Enc::A128Gcm);
+        let mut rng = SeededRng::from_u64(2);
+        assert!(matches!(
+            encrypt(&header, &key, &mut rng, b""),
+            Err(Error::InvalidLength(_))
+        ));
+        let header = Header::new(Alg::Pbes2Hs256A128Kw { iterations: 0 }, Enc::A128Gcm);
+        assert!(matches!(
+            encrypt(&header, b"pw", &mut rng, b""),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,810 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::json::{self, Value};
+use crate::encoding::from_base64;
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hash, Sha1, Sha256};
+use crate::kdf::{argon2, pbkdf2};
+use crate::modes::xts::Xts;
+
+// LUKS1 and LUKS2 volumes as described by the cryptsetup on-disk format
+// specifications. Only aes-xts-plain64 is supported, for both the keyslot
+// areas and the payload. A keyslot holds the volume key split into 4000
+// anti-forensic stripes, encrypted under a key derived from the password; the
+// key is accepted when its PBKDF2 digest matches the one in the header.
+pub const SECTOR_SIZE: usize = 512;
+pub const STRIPES: u32 = 4000;
+// cryptsetup's limit on Argon2 memory, in KiB. Headers are untrusted, so a
+// keyslot asking for more is refused rather than allocated.
+pub const MAX_ARGON2_MEMORY: u32 = 4 * 1024 * 1024;
+// Likewise for the iteration counts, set well above what cryptsetup's
+// benchmark picks on current hardware.
+pub const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+pub const MAX_ARGON2_TIME: u32 = 1000;
+const MAGIC: &[u8; 6] = b"LUKS\xba\xbe";
+const SECONDARY_MAGIC: &[u8; 6] = b"SKUL\xba\xbe";
+const LUKS1_HEADER_LEN: usize = 592;
+const LUKS1_KEYSLOTS: usize = 8;
+const LUKS1_KEYSLOT_LEN: usize = 48;
+const LUKS1_DIGEST_LEN: usize = 20;
+const KEYSLOT_ACTIVE: u32 = 0x00ac_71f3;
+const LUKS2_BINARY_LEN: usize = 4096;
+const LUKS2_MAX_HEADER_LEN: u64 = 4 << 20;
+// Where a LUKS2 secondary header may start, one per allowed header size.
+const LUKS2_SECONDARY_OFFSETS: [u64; 9] = [
+    0x4000, 0x8000, 0x10000, 0x20000, 0x40000, 0x80000, 0x100000, 0x200000, 0x400000,
+];
+const LUKS2_CHECKSUM_OFFSET: usize = 448;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn parse(name: &str) -> Result<HashAlgorithm, Error> {
+        match name {
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(invalid(&format!("unsupported hash {}", name))),
+        }
+    }
+
+    fn output_size(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => Sha1::OUTPUT_SIZE,
+            HashAlgorithm::Sha256 => Sha256::OUTPUT_SIZE,
+        }
+    }
+
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => Sha1::digest(data),
+            HashAlgorithm::Sha256 => Sha256::digest(data),
+        }
+    }
+
+    fn pbkdf2(&self, password: &[u8], salt: &[u8], iterations: u32, length: usize) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => pbkdf2::derive::<Sha1>(password, salt, iterations, length),
+            HashAlgorithm::Sha256 => pbkdf2::derive::<Sha256>(password, salt, iterations, length),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2 {
+        hash: HashAlgorithm,
+        iterations: u32,
+    },
+    // LUKS2 only.
+    Argon2(argon2::Params),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keyslot {
+    pub index: usize,
+    pub kdf: Kdf,
+    pub salt: Vec<u8>,
+    pub key_size: usize,
+    // Byte offset of the encrypted key material.
+    pub offset: u64,
+    pub af_hash: HashAlgorithm,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Digest {
+    hash: HashAlgorithm,
+    iterations: u32,
+    salt: Vec<u8>,
+    value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub uuid: String,
+    pub key_size: usize,
+    // Active keyslots that can unlock the payload.
+    pub keyslots: Vec<Keyslot>,
+    // Byte offset of the payload, and its length when it does not run to the
+    // end of the image.
+    pub payload_offset: u64,
+    pub payload_len: Option<u64>,
+    pub sector_size: usize,
+    // Added to the payload IVs, in 512-byte sectors.
+    pub iv_tweak: u64,
+    digest: Digest,
+}
+
+impl Header {
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Header, Error> {
+        let start = read_at(reader, 0, LUKS1_HEADER_LEN)?;
+        if &start[..6] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        match be_u16(&start[6..]) {
+            1 => parse_luks1(&start),
+            2 => parse_luks2(reader),
+            version => Err(invalid(&format!("unsupported version {}", version))),
+        }
+    }
+
+    // Tries every keyslot and returns the volume key. If the password opens
+    // none of them, the first keyslot error is returned, or Error::WrongKey
+    // when every keyslot could be tried.
+    pub fn unlock<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        password: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut first_error = None;
+        for keyslot in &self.keyslots {
+            match self.open_keyslot(reader, keyslot, password) {
+                Ok(key) if self.verify(&key) => return Ok(key),
+                Ok(_) => {}
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        Err(first_error.unwrap_or(Error::WrongKey))
+    }
+
+    fn open_keyslot<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        keyslot: &Keyslot,
+        password: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let slot_key = match &keyslot.kdf {
+            Kdf::Pbkdf2 { hash, iterations } => {
+                if *iterations > MAX_PBKDF2_ITERATIONS {
+                    return Err(invalid(&format!(
+                        "keyslot {} asks for {} PBKDF2 iterations, more than {}",
+                        keyslot.index, iterations, MAX_PBKDF2_ITERATIONS
+                    )));
+                }
+                hash.pbkdf2(password, &keyslot.salt, *iterations, keyslot.key_size)
+            }
+            Kdf::Argon2(params) => {
+                if params.memory > MAX_ARGON2_MEMORY {
+                    return Err(invalid(&format!(
+                        "keyslot {} asks for {} KiB of Argon2 memory, more than {}",
+                        keyslot.index, params.memory, MAX_ARGON2_MEMORY
+                    )));
+                }
+                if params.iterations > MAX_ARGON2_TIME {
+                    return Err(invalid(&format!(
+                        "keyslot {} asks for {} Argon2 passes, more than {}",
+                        keyslot.index, params.iterations, MAX_ARGON2_TIME
+                    )));
+                }
+                argon2::derive(password, &keyslot.salt, params, keyslot.key_size)?
+            }
+        };
+        let material_len = keyslot.key_size * STRIPES as usize;
+        let encrypted = read_at(
+            reader,
+            keyslot.offset,
+            material_len.next_multiple_of(SECTOR_SIZE),
+        )?;
+        let material = Xts::new(&slot_key)?.decrypt(0, SECTOR_SIZE, &encrypted)?;
+        Ok(af_merge(
+            &material[..material_len],
+            keyslot.key_size,
+            keyslot.af_hash,
+        ))
+    }
+
+    fn verify(&self, key: &[u8]) -> bool {
+        let digest = &self.digest;
+        let computed = digest
+            .hash
+            .pbkdf2(key, &digest.salt, digest.iterations, digest.value.len());
+        constant_time_eq(&computed, &digest.value)
+    }
+}
+
+// Decrypts payload sectors of an unlocked volume.
+pub struct Volume<R> {
+    reader: R,
+    xts: Xts,
+    offset: u64,
+    sector_size: usize,
+    iv_tweak: u64,
+    sectors: u64,
+}
+
+impl<R: Read + Seek> Volume<R> {
+    pub fn open(mut reader: R, password: &[u8]) -> Result<Volume<R>, Error> {
+        let header = Header::read(&mut reader)?;
+        let key = header.unlock(&mut reader, password)?;
+        Volume::with_key(reader, &header, &key)
+    }
+
+    pub fn with_key(mut reader: R, header: &Header, key: &[u8]) -> Result<Volume<R>, Error> {
+        let payload_len = match header.payload_len {
+            Some(len) => len,
+            None => reader
+                .seek(SeekFrom::End(0))?
+                .checked_sub(header.payload_offset)
+                .ok_or_else(|| invalid("image ends before the payload"))?,
+        };
+        Ok(Volume {
+            reader,
+            xts: Xts::new(key)?,
+            offset: header.payload_offset,
+            sector_size: header.sector_size,
+            iv_tweak: header.iv_tweak,
+            sectors: payload_len / header.sector_size as u64,
+        })
+    }
+
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn read_sector(&mut self, index: u64) -> Result<Vec<u8>, Error> {
+        if index >= self.sectors {
+            return Err(Error::InvalidInput(format!(
+                "LUKS: sector {} is past the end of the payload",
+                index
+            )));
+        }
+        let position = self.offset + index * self.sector_size as u64;
+        let data = read_at(&mut self.reader, position, self.sector_size)?;
+        // IVs count 512-byte sectors plus the tweak, then for larger sectors
+        // are divided down so that each sector has its own IV.
+        let scale = (self.sector_size / SECTOR_SIZE) as u128;
+        let iv = (index as u128 * scale + self.iv_tweak as u128) / scale;
+        self.xts.decrypt_sector(iv, &data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn parse_luks1(header: &[u8]) -> Result<Header, Error> {
+    let cipher = c_string(&header[8..40])?;
+    let mode = c_string(&header[40..72])?;
+    if cipher != "aes" || mode != "xts-plain64" {
+        return Err(invalid(&format!("unsupported cipher {}-{}", cipher, mode)));
+    }
+    let hash = HashAlgorithm::parse(&c_string(&header[72..104])?)?;
+    let payload_offset = be_u32(&header[104..]) as u64 * SECTOR_SIZE as u64;
+    let key_size = be_u32(&header[108..]) as usize;
+    check_key_size(key_size)?;
+    let digest = Digest {
+        hash,
+        iterations: be_u32(&header[164..]),
+        salt: header[132..164].to_vec(),
+        value: header[112..112 + LUKS1_DIGEST_LEN].to_vec(),
+    };
+    check_digest_iterations(digest.iterations)?;
+    let mut keyslots = Vec::new();
+    for index in 0..LUKS1_KEYSLOTS {
+        let slot = &header[208 + index * LUKS1_KEYSLOT_LEN..][..LUKS1_KEYSLOT_LEN];
+        if be_u32(slot) != KEYSLOT_ACTIVE {
+            continue;
+        }
+        check_stripes(be_u32(&slot[44..]))?;
+        keyslots.push(Keyslot {
+            index,
+            kdf: Kdf::Pbkdf2 {
+                hash,
+                iterations: be_u32(&slot[4..]),
+            },
+            salt: slot[8..40].to_vec(),
+            key_size,
+            offset: be_u32(&slot[40..]) as u64 * SECTOR_SIZE as u64,
+            af_hash: hash,
+        });
+    }
+    Ok(Header {
+        version: 1,
+        uuid: c_string(&header[168..208])?,
+        key_size,
+        keyslots,
+        payload_offset,
+        payload_len: None,
+        sector_size: SECTOR_SIZE,
+        iv_tweak: 0,
+        digest,
+    })
+}
+
+// Reads the newer of the two LUKS2 header copies whose checksum is intact.
+fn parse_luks2<R: Read + Seek>(reader: &mut R) -> Result<Header, Error> {
+    let mut newest: Option<(u64, String, Value)> = None;
+    let mut error = invalid("no intact header");
+    for offset in std::iter::once(0).chain(LUKS2_SECONDARY_OFFSETS) {
+        match read_luks2_copy(reader, offset) {
+            Ok(copy) => {
+                if newest.as_ref().is_none_or(|newest| copy.0 > newest.0) {
+                    newest = Some(copy);
+                }
+            }
+            Err(copy_error) if offset == 0 => error = copy_error,
+            Err(_) => {}
+        }
+    }
+    match newest {
+        Some((_, uuid, metadata)) => parse_metadata(&metadata, uuid),
+        None => Err(error),
+    }
+}
+
+fn read_luks2_copy<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+) -> Result<(u64, String, Value), Error> {
+    let mut binary = read_at(reader, offset, LUKS2_BINARY_LEN)?;
+    let magic = if offset == 0 { MAGIC } else { SECONDARY_MAGIC };
+    if &binary[..6] != magic || be_u16(&binary[6..]) != 2 {
+        return Err(invalid("bad magic"));
+    }
+    let header_len = be_u64(&binary[8..]);
+    if be_u64(&binary[256..]) != offset
+        || header_len <= LUKS2_BINARY_LEN as u64
+        || header_len > LUKS2_MAX_HEADER_LEN
+        || !header_len.is_multiple_of(LUKS2_BINARY_LEN as u64)
+    {
+        return Err(invalid("bad header size or offset"));
+    }
+    if c_string(&binary[72..104])? != "sha256" {
+        return Err(invalid("unsupported header checksum"));
+    }
+    let area = read_at(
+        reader,
+        offset + LUKS2_BINARY_LEN as u64,
+        header_len as usize - LUKS2_BINARY_LEN,
+    )?;
+    let checksum = binary[LUKS2_CHECKSUM_OFFSET..][..Sha256::OUTPUT_SIZE].to_vec();
+    binary[LUKS2_CHECKSUM_OFFSET..][..64].fill(0);
+    let mut hash = Sha256::new();
+    hash.update(&binary);
+    hash.update(&area);
+    if !constant_time_eq(&hash.finalize(), &checksum) {
+        return Err(invalid("header checksum mismatch"));
+    }
+    let end = area
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(area.len());
+    let text = std::str::from_utf8(&area[..end]).map_err(|_| invalid("metadata is not UTF-8"))?;
+    let metadata = json::parse(text).map_err(|error| invalid(&error))?;
+    Ok((
+        be_u64(&binary[16..]),
+        c_string(&binary[168..208])?,
+        metadata,
+    ))
+}
+
+fn parse_metadata(metadata: &Value, uuid: String) -> Result<Header, Error> {
+    let segments = object(field(metadata, "segments")?)?;
+    let [(segment_id, segment)] = segments else {
+        return Err(invalid("only volumes with a single segment are supported"));
+    };
+    if string(segment, "type")? != "crypt" {
+        return Err(invalid("the segment is not of type crypt"));
+    }
+    check_encryption(segment)?;
+    let sector_size = number(segment, "sector_size")? as usize;
+    if !sector_size.is_power_of_two() || !(SECTOR_SIZE..=4096).contains(&sector_size) {
+        return Err(invalid(
+            "sector_size must be a power of two from 512 to 4096",
+        ));
+    }
+    let payload_len = match string(segment, "size")? {
+        "dynamic" => None,
+        _ => Some(decimal(segment, "size")?),
+    };
+
+    // The digest bound to the segment decides which keyslots may open it.
+    let digest = object(field(metadata, "digests")?)?
+        .iter()
+        .map(|(_, digest)| digest)
+        .find(|digest| list(digest, "segments").is_ok_and(|ids| ids.contains(&segment_id.as_str())))
+        .ok_or_else(|| invalid("no digest for the segment"))?;
+    if string(digest, "type")? != "pbkdf2" {
+        return Err(invalid("unsupported digest type"));
+    }
+    let slot_ids = list(digest, "keyslots")?;
+    let mut keyslots = Vec::new();
+    for (id, keyslot) in object(field(metadata, "keyslots")?)? {
+        if slot_ids.contains(&id.as_str()) {
+            keyslots.push(parse_keyslot(id, keyslot)?);
+        }
+    }
+    let key_size = keyslots.first().map_or(0, |keyslot| keyslot.key_size);
+    if keyslots.iter().any(|keyslot| keyslot.key_size != key_size) {
+        return Err(invalid("keyslots disagree on the key size"));
+    }
+
+    // cryptsetup stores a full-length PBKDF2 output.
+    let digest = Digest {
+        hash: HashAlgorithm::parse(string(digest, "hash")?)?,
+        iterations: number_u32(digest, "iterations")?,
+        salt: base64(digest, "salt")?,
+        value: base64(digest, "digest")?,
+    };
+    if digest.value.len() != digest.hash.output_size() {
+        return Err(invalid("bad digest length"));
+    }
+    check_digest_iterations(digest.iterations)?;
+
+    Ok(Header {
+        version: 2,
+        uuid,
+        key_size,
+        keyslots,
+        payload_offset: decimal(segment, "offset")?,
+        payload_len,
+        sector_size,
+        iv_tweak: decimal(segment, "iv_tweak")?,
+        digest,
+    })
+}
+
+fn parse_keyslot(id: &str, keyslot: &Value) -> Result<Keyslot, Error> {
+    if string(keyslot, "type")? != "luks2" {
+        return Err(invalid(&format!("keyslot {} has an unsupported type", id)));
+    }
+    let af = field(keyslot, "af")?;
+    if string(af, "type")? != "luks1" {
+        return Err(invalid(&format!("keyslot {} has an unsupported AF", id)));
+    }
+    check_stripes(number_u32(af, "stripes")?)?;
+    let area = field(keyslot, "area")?;
+    if string(area, "type")? != "raw" {
+        return Err(invalid(&format!("keyslot {} has an unsupported area", id)));
+    }
+    check_encryption(area)?;
+    let key_size = number(keyslot, "key_size")? as usize;
+    check_key_size(key_size)?;
+    if number(area, "key_size")? != key_size as u64
+        || decimal(area, "size")? < (key_size * STRIPES as usize) as u64
+    {
+        return Err(invalid(&format!("keyslot {} has a bad area", id)));
+    }
+
+    let kdf_params = field(keyslot, "kdf")?;
+    let kdf = match string(kdf_params, "type")? {
+        "pbkdf2" => Kdf::Pbkdf2 {
+            hash: HashAlgorithm::parse(string(kdf_params, "hash")?)?,
+            iterations: number_u32(kdf_params, "iterations")?,
+        },
+        name @ ("argon2i" | "argon2id") => Kdf::Argon2(argon2::Params {
+            variant: argon2::Variant::parse(name).map_err(|error| invalid(&error))?,
+            memory: number_u32(kdf_params, "memory")?,
+            iterations: number_u32(kdf_params, "time")?,
+            lanes: number_u32(kdf_params, "cpus")?,
+        }),
+        name => return Err(invalid(&format!("unsupported KDF {}", name))),
+    };
+    Ok(Keyslot {
+        index: id
+            .parse()
+            .map_err(|_| invalid(&format!("bad keyslot id {}", id)))?,
+        kdf,
+        salt: base64(kdf_params, "salt")?,
+        key_size,
+        offset: decimal(area, "offset")?,
+        af_hash: HashAlgorithm::parse(string(af, "hash")?)?,
+    })
+}
+
+// Undoes the anti-forensic split: every stripe but the last is folded in
+// through the diffusion function and the last one is XORed with the result.
+fn af_merge(material: &[u8], key_size: usize, hash: HashAlgorithm) -> Vec<u8> {
+    let mut stripes = material.chunks(key_size);
+    let last = stripes.next_back().unwrap_or_default();
+    let mut key = vec![0; key_size];
+    for stripe in stripes {
+        xor(&mut key, stripe);
+        key = diffuse(&key, hash);
+    }
+    xor(&mut key, last);
+    key
+}
+
+// Hashes each digest-sized chunk, prefixed with its big-endian index; the
+// last chunk may be shorter and keeps only that much of its hash.
+fn diffuse(data: &[u8], hash: HashAlgorithm) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    for (index, chunk) in data.chunks(hash.output_size()).enumerate() {
+        let digest = hash.digest(&[&(index as u32).to_be_bytes(), chunk].concat());
+        output.extend_from_slice(&digest[..chunk.len()]);
+    }
+    output
+}
+
+fn xor(target: &mut [u8], other: &[u8]) {
+    for (byte, other) in target.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+fn check_stripes(stripes: u32) -> Result<(), Error> {
+    if stripes != STRIPES {
+        return Err(invalid(&format!("unsupported stripe count {}", stripes)));
+    }
+    Ok(())
+}
+
+// Every unlock attempt pays for the digest, so an oversized count would make
+// the whole header unusable anyway.
+fn check_digest_iterations(iterations: u32) -> Result<(), Error> {
+    if iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(invalid(&format!(
+            "digest asks for {} PBKDF2 iterations, more than {}",
+            iterations, MAX_PBKDF2_ITERATIONS
+        )));
+    }
+    Ok(())
+}
+
+// AES-128 or AES-256 in XTS mode.
+fn check_key_size(key_size: usize) -> Result<(), Error> {
+    if key_size != 32 && key_size != 64 {
+        return Err(invalid(&format!("unsupported key size {}", key_size)));
+    }
+    Ok(())
+}
+
+fn check_encryption(value: &Value) -> Result<(), Error> {
+    match string(value, "encryption")? {
+        "aes-xts-plain64" => Ok(()),
+        other => Err(invalid(&format!("unsupported encryption {}", other))),
+    }
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, Error> {
+    value
+        .get(name)
+        .ok_or_else(|| invalid(&format!("missing {:?}", name)))
+}
+
+fn object(value: &Value) -> Result<&[(String, Value)], Error> {
+    match value {
+        Value::Object(fields) => Ok(fields),
+        _ => Err(invalid("expected an object")),
+    }
+}
+
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str, Error> {
+    field(value, name)?
+        .as_str()
+        .ok_or_else(|| invalid(&format!("{:?} is not a string", name)))
+}
+
+fn number(value: &Value, name: &str) -> Result<u64, Error> {
+    field(value, name)?
+        .as_u64()
+        .ok_or_else(|| invalid(&format!("{:?} is not a number", name)))
+}
+
+fn number_u32(value: &Value, name: &str) -> Result<u32, Error> {
+    u32::try_from(number(value, name)?).map_err(|_| invalid(&format!("{:?} is too large", name)))
+}
+
+// Offsets and sizes are decimal strings, as JSON numbers may not hold 64 bits.
+fn decimal(value: &Value, name: &str) -> Result<u64, Error> {
+    let text = string(value, name)?;
+    if !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid(&format!("{:?} is not a decimal string", name)));
+    }
+    text.parse()
+        .map_err(|_| invalid(&format!("{:?} is not a decimal string", name)))
+}
+
+fn base64(value: &Value, name: &str) -> Result<Vec<u8>, Error> {
+    from_base64(string(value, name)?).map_err(|error| invalid(&error))
+}
+
+fn list<'a>(value: &'a Value, name: &str) -> Result<Vec<&'a str>, Error> {
+    match field(value, name)? {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().ok_or_else(|| invalid("expected a string")))
+            .collect(),
+        _ => Err(invalid(&format!("{:?} is not a list", name))),
+    }
+}
+
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut buffer = vec![0; len];
+    reader
+        .read_exact(&mut buffer)
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => invalid("image is truncated"),
+            _ => error.into(),
+        })?;
+    Ok(buffer)
+}
+
+fn c_string(bytes: &[u8]) -> Result<String, Error> {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8(bytes[..end].to_vec()).map_err(|_| invalid("bad header string"))
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(word)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("LUKS: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const LUKS1: &[u8] = include_bytes!("../../fixtures/luks/luks1.img");
+    const LUKS2: &[u8] = include_bytes!("../../fixtures/luks/luks2.img");
+    const PAYLOAD: &[u8] = include_bytes!("../../fixtures/luks/payload.txt");
+
+    fn read_all(volume: &mut Volume<Cursor<Vec<u8>>>) -> Vec<u8> {
+        (0..volume.sectors())
+            .flat_map(|index| volume.read_sector(index).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn luks1() {
+        let mut image = Cursor::new(LUKS1.to_vec());
+        let header = Header::read(&mut image).unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(header.uuid, "2f3a2c2e-1d1b-4a47-8e6b-5c0a1d6f7e01");
+        assert_eq!(header.key_size, 32);
+        assert_eq!(header.keyslots.len(), 1);
+        assert_eq!(header.payload_offset, 264 * 512);
+        assert_eq!(
+            header.unlock(&mut image, b"wrong horse"),
+            Err(Error::WrongKey)
+        );
+
+        let mut volume = Volume::open(image, b"correct horse").unwrap();
+        assert_eq!((volume.sector_size(), volume.sectors()), (512, 4));
+        assert_eq!(read_all(&mut volume), &PAYLOAD[..2048]);
+        assert!(volume.read_sector(4).is_err());
+    }
+
+    #[test]
+    fn luks2() {
+        let mut image = Cursor::new(LUKS2.to_vec());
+        let header = Header::read(&mut image).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.uuid, "6b1f0d2a-3c4e-4f58-9a7b-8c9d0e1f2a3b");
+        assert_eq!(header.sector_size, 4096);
+        assert_eq!(
+            header.keyslots[1].kdf,
+            Kdf::Argon2(argon2::Params {
+                variant: argon2::Variant::Argon2id,
+                memory: 64,
+                iterations: 2,
+                lanes: 1,
+            })
+        );
+        let pbkdf2_key = header.unlock(&mut image, b"correct horse").unwrap();
+        let argon2_key = header.unlock(&mut image, b"battery staple").unwrap();
+        assert_eq!(pbkdf2_key, argon2_key);
+        assert_eq!(header.unlock(&mut image, b"wrong"), Err(Error::WrongKey));
+
+        let mut volume = Volume::with_key(image, &header, &pbkdf2_key).unwrap();
+        assert_eq!((volume.sector_size(), volume.sectors()), (4096, 2));
+        assert_eq!(read_all(&mut volume), PAYLOAD);
+    }
+
+    #[test]
+    fn unlock_skips_keyslots_that_fail() {
+        let mut image = Cursor::new(LUKS2.to_vec());
+        let mut header = Header::read(&mut image).unwrap();
+        header.keyslots.swap(0, 1);
+        header.keyslots[0].kdf = Kdf::Argon2(argon2::Params {
+            variant: argon2::Variant::Argon2id,
+            memory: u32::MAX,
+            iterations: 1,
+            lanes: 1,
+        });
+        assert!(header.unlock(&mut image, b"correct horse").is_ok());
+        assert!(matches!(
+            header.unlock(&mut image, b"battery staple"),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_excessive_iterations() {
+        let mut image = Cursor::new(LUKS2.to_vec());
+        let header = Header::read(&mut image).unwrap();
+        let pbkdf2 = Kdf::Pbkdf2 {
+            hash: HashAlgorithm::Sha256,
+            iterations: MAX_PBKDF2_ITERATIONS + 1,
+        };
+        let argon2 = Kdf::Argon2(argon2::Params {
+            variant: argon2::Variant::Argon2id,
+            memory: 64,
+            iterations: MAX_ARGON2_TIME + 1,
+            lanes: 1,
+        });
+        for (index, kdf, password) in [
+            (0, pbkdf2, &b"correct horse"[..]),
+            (1, argon2, b"battery staple"),
+        ] {
+            let mut header = header.clone();
+            header.keyslots[index].kdf = kdf;
+            assert!(matches!(
+                header.unlock(&mut image, password),
+                Err(Error::InvalidInput(message)) if message.contains("more than")
+            ));
+        }
+
+        let mut luks1 = LUKS1.to_vec();
+        luks1[164..168].copy_from_slice(&(MAX_PBKDF2_ITERATIONS + 1).to_be_bytes());
+        assert!(matches!(
+            Header::read(&mut Cursor::new(luks1)),
+            Err(Error::InvalidInput(message)) if message.contains("more than")
+        ));
+    }
+
+    #[test]
+    fn luks2_falls_back_to_the_secondary_header() {
+        let mut image = LUKS2.to_vec();
+        image[LUKS2_BINARY_LEN + 10] ^= 1;
+        let header = Header::read(&mut Cursor::new(&image)).unwrap();
+        assert_eq!(header.keyslots.len(), 2);
+
+        image[0x4000 + LUKS2_BINARY_LEN + 10] ^= 1;
+        assert!(matches!(
+            Header::read(&mut Cursor::new(&image)),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_images() {
+        let mut magic = LUKS1.to_vec();
+        magic[0] = b'X';
+        let mut cipher = LUKS1.to_vec();
+        cipher[40..52].copy_from_slice(b"cbc-essiv:sh");
+        for image in [magic, cipher, LUKS1[..300].to_vec()] {
+            assert!(matches!(
+                Header::read(&mut Cursor::new(image)),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn diffuse_truncates_the_last_chunk() {
+        let data: Vec<u8> = (0..32).collect();
+        let output = diffuse(&data, HashAlgorithm::Sha1);
+        let tail = Sha1::digest(&[&[0, 0, 0, 1], &data[20..]].concat());
+        assert_eq!(&output[20..], &tail[..12]);
+        assert_eq!(af_merge(&data, 32, HashAlgorithm::Sha1), data);
+    }
+}
@@ -0,0 +1,8 @@
+// Readers and writers for file formats produced by other tools.
+pub mod ansible;
+pub mod fernet;
+mod json;
+pub mod jwe;
+pub mod luks;
+pub mod openssl;
+pub mod zip;
@@ -0,0 +1,344 @@
+use std::io::{Read, Write};
+
+use crate::aes::{AesSize, BlockCipher, Key};
+use crate::error::{Error, HeaderError};
+use crate::hash::{Md5, Sha256};
+use crate::io::{DecryptReader, EncryptWriter};
+use crate::kdf::{evp, pbkdf2};
+use crate::padding::Pkcs7Padding;
+use crate::rng::RngSource;
+
+// Layout of `openssl enc` with a password: "Salted__" | salt | ciphertext.
+// Nothing else is recorded, so the reader must know the cipher and KDF.
+pub const MAGIC: &[u8; 8] = b"Salted__";
+pub const SALT_LEN: usize = 8;
+pub const IV_LEN: usize = 16;
+// What `openssl enc -pbkdf2` uses without -iter.
+pub const DEFAULT_ITERATIONS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // PKCS#7 padded.
+    Cbc,
+    // The IV is the initial 128-bit big-endian counter.
+    Ctr,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Result<Mode, String> {
+        match name {
+            "cbc" => Ok(Mode::Cbc),
+            "ctr" => Ok(Mode::Ctr),
+            _ => Err(format!("Invalid OpenSSL mode: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    // EVP_BytesToKey with one round, `-md md5`; the default before 1.1.0.
+    BytesToKeyMd5,
+    // EVP_BytesToKey with `-md sha256`, the default since 1.1.0.
+    BytesToKeySha256,
+    // `-pbkdf2 -iter n` with the default SHA-256 digest.
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Params {
+    pub size: AesSize,
+    pub mode: Mode,
+    pub kdf: Kdf,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            size: AesSize::S128,
+            mode: Mode::Cbc,
+            kdf: Kdf::Pbkdf2Sha256 {
+                iterations: DEFAULT_ITERATIONS,
+            },
+        }
+    }
+}
+
+pub fn is_salted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// Derives the key and IV the way `openssl enc` does from a password.
+pub fn derive(password: &str, salt: &[u8], params: &Params) -> (Key, [u8; IV_LEN]) {
+    let key_len = params.size.key_len();
+    let (key, iv_bytes) = match params.kdf {
+        Kdf::BytesToKeyMd5 => {
+            evp::bytes_to_key::<Md5>(password.as_bytes(), salt, 1, key_len, IV_LEN)
+        }
+        Kdf::BytesToKeySha256 => {
+            evp::bytes_to_key::<Sha256>(password.as_bytes(), salt, 1, key_len, IV_LEN)
+        }
+        Kdf::Pbkdf2Sha256 { iterations } => {
+            let mut bytes =
+                pbkdf2::derive::<Sha256>(password.as_bytes(), salt, iterations, key_len + IV_LEN);
+            let iv = bytes.split_off(key_len);
+            (bytes, iv)
+        }
+    };
+    let mut iv = [0; IV_LEN];
+    iv.copy_from_slice(&iv_bytes);
+    (Key::from_bytes(&key), iv)
+}
+
+pub fn encrypt(
+    password: &str,
+    params: &Params,
+    rng: &mut dyn RngSource,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut encrypted = Vec::new();
+    encrypt_stream(password, params, rng, &mut &plaintext[..], &mut encrypted)?;
+    Ok(encrypted)
+}
+
+pub fn decrypt(password: &str, params: &Params, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut plaintext = Vec::new();
+    decrypt_stream(password, params, &mut &data[..], &mut plaintext)?;
+    Ok(plaintext)
+}
+
+pub fn encrypt_stream<R: Read, W: Write>(
+    password: &str,
+    params: &Params,
+    rng: &mut dyn RngSource,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    check_iterations(params)?;
+    let salt = rng.random_vec(SALT_LEN)?;
+    encrypt_salted(password, params, &salt, reader, writer)
+}
+
+// The format has no integrity check: a wrong password usually fails the
+// CBC padding check and goes unnoticed in CTR mode. Output written before an
+// error must be discarded.
+pub fn decrypt_stream<R: Read, W: Write>(
+    password: &str,
+    params: &Params,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    check_iterations(params)?;
+    let mut header = [0; MAGIC.len() + SALT_LEN];
+    reader.read_exact(&mut header).map_err(|error| {
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            HeaderError::Truncated.into()
+        } else {
+            Error::from(error)
+        }
+    })?;
+    if !is_salted(&header) {
+        return Err(HeaderError::BadMagic.into());
+    }
+    let (key, iv) = derive(password, &header[MAGIC.len()..], params);
+    decrypt_with_key(&key, &iv, params.mode, reader, writer)
+}
+
+// Like `openssl enc -K <key> -iv <iv>`: no header and no salt.
+pub fn encrypt_with_key<R: Read, W: Write>(
+    key: &Key,
+    iv: &[u8; IV_LEN],
+    mode: Mode,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let cipher = BlockCipher::new(key);
+    let mut encryptor = match mode {
+        Mode::Cbc => EncryptWriter::cbc(writer, cipher, iv, Box::new(Pkcs7Padding)),
+        Mode::Ctr => EncryptWriter::ctr(writer, cipher, iv),
+    };
+    std::io::copy(reader, &mut encryptor)?;
+    encryptor.finish()?;
+    Ok(())
+}
+
+pub fn decrypt_with_key<R: Read, W: Write>(
+    key: &Key,
+    iv: &[u8; IV_LEN],
+    mode: Mode,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let cipher = BlockCipher::new(key);
+    let mut decryptor = match mode {
+        Mode::Cbc => DecryptReader::cbc(reader, cipher, iv, Box::new(Pkcs7Padding)),
+        Mode::Ctr => DecryptReader::ctr(reader, cipher, iv),
+    };
+    std::io::copy(&mut decryptor, writer)?;
+    Ok(())
+}
+
+fn encrypt_salted<R: Read, W: Write>(
+    password: &str,
+    params: &Params,
+    salt: &[u8],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), Error> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(salt)?;
+    let (key, iv) = derive(password, salt, params);
+    encrypt_with_key(&key, &iv, params.mode, reader, writer)
+}
+
+fn check_iterations(params: &Params) -> Result<(), Error> {
+    match params.kdf {
+        Kdf::Pbkdf2Sha256 { iterations: 0 } => Err(Error::InvalidInput(
+            "PBKDF2 needs at least one iteration".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+    use crate::rng::SeededRng;
+
+    const PASSWORD: &str = "correct horse";
+    const PLAINTEXT: &[u8] = include_bytes!("../../fixtures/openssl/plaintext.txt");
+
+    fn params(mode: Mode, kdf: Kdf) -> Params {
+        Params {
+            size: AesSize::S128,
+            mode,
+            kdf,
+        }
+    }
+
+    fn sized(size: AesSize, mode: Mode, kdf: Kdf) -> Params {
+        Params { size, mode, kdf }
+    }
+
+    fn aes256(kdf: Kdf) -> Params {
+        sized(AesSize::S256, Mode::Cbc, kdf)
+    }
+
+    fn fixtures() -> [(Params, &'static [u8]); 9] {
+        [
+            (
+                params(Mode::Cbc, Kdf::BytesToKeyMd5),
+                include_bytes!("../../fixtures/openssl/md5-aes-128-cbc.bin"),
+            ),
+            (
+                params(Mode::Cbc, Kdf::BytesToKeySha256),
+                include_bytes!("../../fixtures/openssl/sha256-aes-128-cbc.bin"),
+            ),
+            (
+                params(Mode::Cbc, Kdf::Pbkdf2Sha256 { iterations: 1000 }),
+                include_bytes!("../../fixtures/openssl/pbkdf2-aes-128-cbc.bin"),
+            ),
+            (
+                params(Mode::Ctr, Kdf::Pbkdf2Sha256 { iterations: 10_000 }),
+                include_bytes!("../../fixtures/openssl/pbkdf2-aes-128-ctr.bin"),
+            ),
+            (
+                aes256(Kdf::BytesToKeySha256),
+                include_bytes!("../../fixtures/openssl/sha256-aes-256-cbc.bin"),
+            ),
+            (
+                aes256(Kdf::Pbkdf2Sha256 {
+                    iterations: DEFAULT_ITERATIONS,
+                }),
+                include_bytes!("../../fixtures/openssl/pbkdf2-aes-256-cbc.bin"),
+            ),
+            (
+                aes256(Kdf::BytesToKeyMd5),
+                include_bytes!("../../fixtures/openssl/md5-aes-256-cbc.bin"),
+            ),
+            (
+                sized(
+                    AesSize::S256,
+                    Mode::Ctr,
+                    Kdf::Pbkdf2Sha256 {
+                        iterations: DEFAULT_ITERATIONS,
+                    },
+                ),
+                include_bytes!("../../fixtures/openssl/pbkdf2-aes-256-ctr.bin"),
+            ),
+            (
+                sized(
+                    AesSize::S192,
+                    Mode::Cbc,
+                    Kdf::Pbkdf2Sha256 { iterations: 10_000 },
+                ),
+                include_bytes!("../../fixtures/openssl/pbkdf2-aes-192-cbc.bin"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn reads_openssl_files() {
+        for (params, fixture) in fixtures() {
+            assert!(is_salted(fixture));
+            assert_eq!(decrypt(PASSWORD, &params, fixture).unwrap(), PLAINTEXT);
+        }
+    }
+
+    #[test]
+    fn writes_openssl_files() {
+        for (params, fixture) in fixtures() {
+            let salt = &fixture[MAGIC.len()..MAGIC.len() + SALT_LEN];
+            let mut encrypted = Vec::new();
+            encrypt_salted(PASSWORD, &params, salt, &mut &PLAINTEXT[..], &mut encrypted).unwrap();
+            assert_eq!(encrypted, fixture, "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn raw_key_and_iv() {
+        let fixture = include_bytes!("../../fixtures/openssl/raw-aes-128-cbc.bin");
+        let key = Key::from_bytes(&from_hex("000102030405060708090a0b0c0d0e0f").unwrap());
+        let mut iv = [0; IV_LEN];
+        iv.copy_from_slice(&from_hex("0f0e0d0c0b0a09080706050403020100").unwrap());
+        let mut plaintext = Vec::new();
+        decrypt_with_key(&key, &iv, Mode::Cbc, &mut &fixture[..], &mut plaintext).unwrap();
+        assert_eq!(plaintext, PLAINTEXT);
+        let mut encrypted = Vec::new();
+        encrypt_with_key(&key, &iv, Mode::Cbc, &mut &PLAINTEXT[..], &mut encrypted).unwrap();
+        assert_eq!(encrypted, fixture);
+    }
+
+    #[test]
+    fn raw_key_and_iv_aes256() {
+        let fixture = include_bytes!("../../fixtures/openssl/raw-aes-256-cbc.bin");
+        let key = Key::from_bytes(&(0..32).collect::<Vec<u8>>());
+        let mut iv = [0; IV_LEN];
+        iv.copy_from_slice(&from_hex("0f0e0d0c0b0a09080706050403020100").unwrap());
+        let mut plaintext = Vec::new();
+        decrypt_with_key(&key, &iv, Mode::Cbc, &mut &fixture[..], &mut plaintext).unwrap();
+        assert_eq!(plaintext, PLAINTEXT);
+        let mut encrypted = Vec::new();
+        encrypt_with_key(&key, &iv, Mode::Cbc, &mut &PLAINTEXT[..], &mut encrypted).unwrap();
+        assert_eq!(encrypted, fixture);
+    }
+
+    #[test]
+    fn round_trip_and_errors() {
+        let mut rng = SeededRng::from_u64(1);
+        let params = params(Mode::Cbc, Kdf::Pbkdf2Sha256 { iterations: 10 });
+        let encrypted = encrypt("pw", &params, &mut rng, b"").unwrap();
+        assert_eq!(encrypted.len(), MAGIC.len() + SALT_LEN + 16);
+        assert_eq!(decrypt("pw", &params, &encrypted).unwrap(), b"");
+        assert_eq!(
+            decrypt("pw", &params, &encrypted[..12]),
+            Err(HeaderError::Truncated.into())
+        );
+        assert_eq!(
+            decrypt("pw", &params, &[0; 32]),
+            Err(HeaderError::BadMagic.into())
+        );
+        let (md5, fixture) = &fixtures()[0];
+        assert_eq!(decrypt("wrong", md5, fixture), Err(Error::InvalidPadding));
+    }
+}
@@ -0,0 +1,586 @@
+use crate::aes::{AesSize, BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha1};
+use crate::kdf::pbkdf2;
+use crate::rng::RngSource;
+
+// ZIP archives (PKWARE APPNOTE.TXT) with WinZip AES encryption. An AES
+// entry has compression method 99, a 0x9901 extra field giving the key size
+// and the real method, and data laid out as
+//   salt | password verifier | AES-CTR ciphertext | HMAC-SHA1-80
+// Only stored (uncompressed) entries are read and written, without ZIP64.
+pub const AES_METHOD: u16 = 99;
+pub const ITERATIONS: u32 = 1000;
+const STORED: u16 = 0;
+const AES_EXTRA_ID: u16 = 0x9901;
+const VERIFIER_LEN: usize = 2;
+const AUTH_LEN: usize = 10;
+const LOCAL_MAGIC: u32 = 0x0403_4b50;
+const CENTRAL_MAGIC: u32 = 0x0201_4b50;
+const END_MAGIC: u32 = 0x0605_4b50;
+const END_LEN: usize = 22;
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_UTF8: u16 = 1 << 11;
+// 1980-01-01 00:00, the earliest DOS date.
+const DOS_DATE: u16 = 0x21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    // Keeps the CRC-32 of the plaintext.
+    Ae1,
+    // Writes a zero CRC-32, which says nothing about short plaintexts; the
+    // HMAC is the only check.
+    Ae2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    pub version: Version,
+    pub size: AesSize,
+}
+
+impl Default for Encryption {
+    fn default() -> Self {
+        Encryption {
+            version: Version::Ae2,
+            size: AesSize::S256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub size: u32,
+    pub encryption: Option<Encryption>,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: u32,
+    offset: u32,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+pub struct Archive<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry>,
+}
+
+impl<'a> Archive<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Archive<'a>, Error> {
+        let end = find_end(data)?;
+        let mut fields = Fields::at(data, end + 10)?;
+        let count = fields.u16()?;
+        let _size = fields.u32()?;
+        let offset = fields.u32()?;
+        if count == u16::MAX || offset == u32::MAX {
+            return Err(invalid("ZIP64 archives are not supported"));
+        }
+        let mut fields = Fields::at(data, offset as usize)?;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            entries.push(central_entry(&mut fields)?);
+        }
+        Ok(Archive { data, entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    // A wrong password is caught by the verifier with Error::WrongKey, or,
+    // one time in 65536, by the HMAC with Error::Authentication.
+    pub fn read(&self, entry: &Entry, password: Option<&str>) -> Result<Vec<u8>, Error> {
+        let mut local = Fields::at(self.data, entry.offset as usize)?;
+        if local.u32()? != LOCAL_MAGIC {
+            return Err(invalid("bad local header"));
+        }
+        local.skip(22)?;
+        let name_len = local.u16()? as usize;
+        let extra_len = local.u16()? as usize;
+        local.skip(name_len + extra_len)?;
+        let data = local.bytes(entry.compressed_size as usize)?;
+
+        if entry.method != STORED {
+            return Err(invalid(&format!(
+                "{}: compression method {} is not supported",
+                entry.name, entry.method
+            )));
+        }
+        let (plaintext, check_crc) = match &entry.encryption {
+            Some(encryption) => {
+                let password = password.ok_or_else(|| {
+                    invalid(&format!(
+                        "{} is encrypted; a password is needed",
+                        entry.name
+                    ))
+                })?;
+                let plaintext = decrypt(password, &encryption.size, data)?;
+                (plaintext, encryption.version == Version::Ae1)
+            }
+            None if entry.flags & FLAG_ENCRYPTED != 0 => {
+                return Err(invalid(&format!(
+                    "{}: traditional ZIP encryption is not supported",
+                    entry.name
+                )));
+            }
+            None => (data.to_vec(), true),
+        };
+        if plaintext.len() != entry.size as usize || (check_crc && crc32(&plaintext) != entry.crc) {
+            return Err(invalid(&format!("{}: size or CRC-32 mismatch", entry.name)));
+        }
+        Ok(plaintext)
+    }
+}
+
+// Builds an archive in memory. Entries are stored, and encrypted when a
+// password is given.
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn add(
+        &mut self,
+        name: &str,
+        contents: &[u8],
+        password: Option<(&str, &Encryption)>,
+        rng: &mut dyn RngSource,
+    ) -> Result<(), Error> {
+        let salt = match password {
+            Some((_, encryption)) => rng.random_vec(salt_len(&encryption.size))?,
+            None => Vec::new(),
+        };
+        self.add_with_salt(name, contents, password, &salt)
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.central.len() as u32;
+        self.data.append(&mut self.central);
+        put_u32(&mut self.data, END_MAGIC);
+        put_u16(&mut self.data, 0);
+        put_u16(&mut self.data, 0);
+        put_u16(&mut self.data, self.count);
+        put_u16(&mut self.data, self.count);
+        put_u32(&mut self.data, size);
+        put_u32(&mut self.data, offset);
+        put_u16(&mut self.data, 0);
+        self.data
+    }
+
+    fn add_with_salt(
+        &mut self,
+        name: &str,
+        contents: &[u8],
+        password: Option<(&str, &Encryption)>,
+        salt: &[u8],
+    ) -> Result<(), Error> {
+        if self.count == u16::MAX || name.len() > u16::MAX as usize {
+            return Err(invalid("too many entries or name too long"));
+        }
+        let crc = crc32(contents);
+        let (data, method, flags, crc, extra, version_needed) = match password {
+            Some((password, encryption)) => {
+                let data = encrypt(password, &encryption.size, salt, contents);
+                let crc = match encryption.version {
+                    Version::Ae1 => crc,
+                    Version::Ae2 => 0,
+                };
+                let mut extra = Vec::new();
+                put_u16(&mut extra, AES_EXTRA_ID);
+                put_u16(&mut extra, 7);
+                put_u16(
+                    &mut extra,
+                    match encryption.version {
+                        Version::Ae1 => 1,
+                        Version::Ae2 => 2,
+                    },
+                );
+                extra.extend_from_slice(b"AE");
+                extra.push(strength(&encryption.size));
+                put_u16(&mut extra, STORED);
+                (data, AES_METHOD, FLAG_UTF8 | FLAG_ENCRYPTED, crc, extra, 51)
+            }
+            None => (contents.to_vec(), STORED, FLAG_UTF8, crc, Vec::new(), 10),
+        };
+        let too_large = |_| invalid("ZIP64 archives are not supported");
+        let offset = u32::try_from(self.data.len()).map_err(too_large)?;
+        let size = u32::try_from(contents.len()).map_err(too_large)?;
+        let compressed_size = u32::try_from(data.len()).map_err(too_large)?;
+
+        let mut header = Vec::new();
+        put_u16(&mut header, version_needed);
+        put_u16(&mut header, flags);
+        put_u16(&mut header, method);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, DOS_DATE);
+        put_u32(&mut header, crc);
+        put_u32(&mut header, compressed_size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+
+        put_u32(&mut self.data, LOCAL_MAGIC);
+        self.data.extend_from_slice(&header);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&extra);
+        self.data.extend_from_slice(&data);
+
+        put_u32(&mut self.central, CENTRAL_MAGIC);
+        put_u16(&mut self.central, version_needed);
+        self.central.extend_from_slice(&header);
+        // Comment length, disk number, internal and external attributes.
+        self.central.extend_from_slice(&[0; 10]);
+        put_u32(&mut self.central, offset);
+        self.central.extend_from_slice(name.as_bytes());
+        self.central.extend_from_slice(&extra);
+        self.count += 1;
+        Ok(())
+    }
+}
+
+fn encrypt(password: &str, size: &AesSize, salt: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let (cipher, mac_key, verifier) = derive(password, size, salt);
+    let ciphertext = ctr_le(&cipher, plaintext);
+    let mac = Hmac::<Sha1>::mac(&mac_key, &ciphertext);
+    [salt, &verifier, &ciphertext, &mac[..AUTH_LEN]].concat()
+}
+
+fn decrypt(password: &str, size: &AesSize, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let salt_len = salt_len(size);
+    if data.len() < salt_len + VERIFIER_LEN + AUTH_LEN {
+        return Err(invalid("encrypted entry is truncated"));
+    }
+    let (salt, rest) = data.split_at(salt_len);
+    let (verifier, rest) = rest.split_at(VERIFIER_LEN);
+    let (ciphertext, mac) = rest.split_at(rest.len() - AUTH_LEN);
+    let (cipher, mac_key, expected) = derive(password, size, salt);
+    if !constant_time_eq(verifier, &expected) {
+        return Err(Error::WrongKey);
+    }
+    if !constant_time_eq(&Hmac::<Sha1>::mac(&mac_key, ciphertext)[..AUTH_LEN], mac) {
+        return Err(Error::Authentication);
+    }
+    Ok(ctr_le(&cipher, ciphertext))
+}
+
+// PBKDF2-HMAC-SHA1 gives the AES key, the HMAC key and the verifier.
+fn derive(password: &str, size: &AesSize, salt: &[u8]) -> (BlockCipher, Vec<u8>, Vec<u8>) {
+    let key_len = size.key_len();
+    let bytes = pbkdf2::derive::<Sha1>(
+        password.as_bytes(),
+        salt,
+        ITERATIONS,
+        2 * key_len + VERIFIER_LEN,
+    );
+    let cipher = BlockCipher::new(&Key::from_bytes(&bytes[..key_len]));
+    (
+        cipher,
+        bytes[key_len..2 * key_len].to_vec(),
+        bytes[2 * key_len..].to_vec(),
+    )
+}
+
+// Unlike NIST CTR, the counter is little-endian and starts at 1.
+fn ctr_le(cipher: &BlockCipher, data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    for (index, chunk) in data.chunks(16).enumerate() {
+        let keystream = cipher.encrypt_block(&(index as u128 + 1).to_le_bytes());
+        output.extend(chunk.iter().zip(keystream.iter()).map(|(a, b)| a ^ b));
+    }
+    output
+}
+
+fn salt_len(size: &AesSize) -> usize {
+    size.key_len() / 2
+}
+
+fn strength(size: &AesSize) -> u8 {
+    match size {
+        AesSize::S128 => 1,
+        AesSize::S192 => 2,
+        AesSize::S256 => 3,
+    }
+}
+
+fn central_entry(fields: &mut Fields) -> Result<Entry, Error> {
+    if fields.u32()? != CENTRAL_MAGIC {
+        return Err(invalid("bad central directory"));
+    }
+    fields.skip(4)?;
+    let flags = fields.u16()?;
+    let mut method = fields.u16()?;
+    fields.skip(4)?;
+    let crc = fields.u32()?;
+    let compressed_size = fields.u32()?;
+    let size = fields.u32()?;
+    let name_len = fields.u16()? as usize;
+    let extra_len = fields.u16()? as usize;
+    let comment_len = fields.u16()? as usize;
+    fields.skip(8)?;
+    let offset = fields.u32()?;
+    let name = String::from_utf8_lossy(fields.bytes(name_len)?).into_owned();
+    let extra = fields.bytes(extra_len)?;
+    fields.skip(comment_len)?;
+    if [compressed_size, size, offset].contains(&u32::MAX) {
+        return Err(invalid("ZIP64 archives are not supported"));
+    }
+
+    let mut encryption = None;
+    if method == AES_METHOD {
+        let field = extra_field(extra, AES_EXTRA_ID)?
+            .filter(|field| field.len() == 7 && &field[2..4] == b"AE")
+            .ok_or_else(|| invalid(&format!("{}: missing AES extra field", name)))?;
+        let version = match u16::from_le_bytes([field[0], field[1]]) {
+            1 => Version::Ae1,
+            2 => Version::Ae2,
+            other => return Err(invalid(&format!("unknown AE version {}", other))),
+        };
+        let size = match field[4] {
+            1 => AesSize::S128,
+            2 => AesSize::S192,
+            3 => AesSize::S256,
+            other => return Err(invalid(&format!("unknown AES strength {}", other))),
+        };
+        encryption = Some(Encryption { version, size });
+        method = u16::from_le_bytes([field[5], field[6]]);
+    }
+    Ok(Entry {
+        name,
+        size,
+        encryption,
+        method,
+        flags,
+        crc,
+        compressed_size,
+        offset,
+    })
+}
+
+fn extra_field(extra: &[u8], id: u16) -> Result<Option<&[u8]>, Error> {
+    let mut fields = Fields::at(extra, 0)?;
+    while fields.position < extra.len() {
+        let field_id = fields.u16()?;
+        let len = fields.u16()? as usize;
+        let data = fields.bytes(len)?;
+        if field_id == id {
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+// The end of central directory record, searched for backwards past any
+// archive comment.
+fn find_end(data: &[u8]) -> Result<usize, Error> {
+    let last = data
+        .len()
+        .checked_sub(END_LEN)
+        .ok_or_else(|| invalid("not a ZIP archive"))?;
+    (last.saturating_sub(u16::MAX as usize)..=last)
+        .rev()
+        .find(|&position| data[position..position + 4] == END_MAGIC.to_le_bytes())
+        .ok_or_else(|| invalid("not a ZIP archive"))
+}
+
+struct Fields<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn at(data: &'a [u8], position: usize) -> Result<Fields<'a>, Error> {
+        if position > data.len() {
+            return Err(invalid("truncated archive"));
+        }
+        Ok(Fields { data, position })
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or_else(|| invalid("truncated archive"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Error> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+// CRC-32 as in ISO 3309 and PNG, bit-reversed with polynomial 0xedb88320.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("zip: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    const PASSWORD: &str = "correct horse";
+    const PLAINTEXT: &[u8] = include_bytes!("../../fixtures/zip/secrets.txt");
+
+    fn read_only_entry(zip: &[u8], password: Option<&str>) -> Result<Vec<u8>, Error> {
+        let archive = Archive::parse(zip)?;
+        assert_eq!(archive.entries().len(), 1);
+        let entry = archive.find("secrets.txt").unwrap();
+        assert_eq!(entry.size as usize, PLAINTEXT.len());
+        archive.read(entry, password)
+    }
+
+    #[test]
+    fn crc32_check() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn reads_libarchive_fixtures() {
+        for (fixture, size) in [
+            (
+                &include_bytes!("../../fixtures/zip/aes128-stored.zip")[..],
+                AesSize::S128,
+            ),
+            (
+                &include_bytes!("../../fixtures/zip/aes256-stored.zip")[..],
+                AesSize::S256,
+            ),
+        ] {
+            let archive = Archive::parse(fixture).unwrap();
+            // libarchive writes AE-1 for entries of 20 bytes or more.
+            let encryption = Encryption {
+                version: Version::Ae1,
+                size,
+            };
+            assert_eq!(archive.entries()[0].encryption, Some(encryption));
+            assert_eq!(read_only_entry(fixture, Some(PASSWORD)).unwrap(), PLAINTEXT);
+            assert_eq!(
+                read_only_entry(fixture, Some("wrong")),
+                Err(Error::WrongKey)
+            );
+            assert!(matches!(
+                read_only_entry(fixture, None),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        let short = include_bytes!("../../fixtures/zip/aes256-short.zip");
+        let archive = Archive::parse(short).unwrap();
+        let entry = archive.find("short.txt").unwrap();
+        assert_eq!(entry.encryption.as_ref().unwrap().version, Version::Ae2);
+        assert_eq!(entry.crc, 0);
+        assert_eq!(
+            archive.read(entry, Some(PASSWORD)).unwrap(),
+            include_bytes!("../../fixtures/zip/short.txt")
+        );
+
+        let plain = include_bytes!("../../fixtures/zip/plain-stored.zip");
+        assert_eq!(read_only_entry(plain, None).unwrap(), PLAINTEXT);
+        let deflated = include_bytes!("../../fixtures/zip/aes256-deflated.zip");
+        assert!(matches!(
+            read_only_entry(deflated, Some(PASSWORD)),
+            Err(Error::InvalidInput(message)) if message.contains("compression method 8")
+        ));
+    }
+
+    #[test]
+    fn writes_and_reads_back() {
+        let mut rng = SeededRng::from_u64(1);
+        let mut writer = Writer::new();
+        let sizes = [AesSize::S128, AesSize::S192, AesSize::S256];
+        for (index, size) in sizes.iter().enumerate() {
+            for version in [Version::Ae1, Version::Ae2] {
+                let encryption = Encryption {
+                    version,
+                    size: size.clone(),
+                };
+                let name = format!("{}-{:?}.txt", index, version);
+                writer
+                    .add(&name, PLAINTEXT, Some((PASSWORD, &encryption)), &mut rng)
+                    .unwrap();
+            }
+        }
+        writer.add("dir/plain.txt", b"", None, &mut rng).unwrap();
+        let zip = writer.finish();
+        let archive = Archive::parse(&zip).unwrap();
+        assert_eq!(archive.entries().len(), 7);
+        for entry in &archive.entries()[..6] {
+            assert_eq!(archive.read(entry, Some(PASSWORD)).unwrap(), PLAINTEXT);
+            let ae1 = entry.encryption.as_ref().unwrap().version == Version::Ae1;
+            assert_eq!(entry.crc != 0, ae1);
+        }
+        let plain = archive.find("dir/plain.txt").unwrap();
+        assert_eq!(archive.read(plain, None).unwrap(), b"");
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let mut writer = Writer::new();
+        let encryption = Encryption {
+            version: Version::Ae1,
+            size: AesSize::S128,
+        };
+        let salt = [7; 8];
+        writer
+            .add_with_salt("a", b"attack at dawn", Some(("pw", &encryption)), &salt)
+            .unwrap();
+        let zip = writer.finish();
+        // Local header, name, AES extra field, salt and verifier.
+        let ciphertext = 30 + 1 + 11 + 8 + 2;
+        let mut tampered = zip.clone();
+        tampered[ciphertext] ^= 1;
+        let archive = Archive::parse(&tampered).unwrap();
+        let entry = &archive.entries()[0];
+        assert_eq!(archive.read(entry, Some("pw")), Err(Error::Authentication));
+        assert!(Archive::parse(&zip[..zip.len() - 1]).is_err());
+        assert!(Archive::parse(b"PK").is_err());
+    }
+}
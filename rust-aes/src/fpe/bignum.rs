@@ -0,0 +1,94 @@
+// Just enough unsigned arithmetic for the Feistel rounds of SP 800-38G.
+// Limbs are little-endian u32.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn from_be_bytes(bytes: &[u8]) -> Self {
+        let mut value = BigUint { limbs: Vec::new() };
+        for &byte in bytes {
+            value.mul_add_small(256, byte as u32);
+        }
+        value
+    }
+
+    // Numerals are given most significant first.
+    pub fn from_numerals(numerals: impl Iterator<Item = u16>, radix: u32) -> Self {
+        let mut value = BigUint { limbs: Vec::new() };
+        for numeral in numerals {
+            value.mul_add_small(radix, numeral as u32);
+        }
+        value
+    }
+
+    pub fn mul_add_small(&mut self, mul: u32, add: u32) {
+        let mut carry = add as u64;
+        for limb in self.limbs.iter_mut() {
+            let product = *limb as u64 * mul as u64 + carry;
+            *limb = product as u32;
+            carry = product >> 32;
+        }
+        if carry != 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    pub fn div_rem_small(&mut self, div: u32) -> u32 {
+        let mut remainder = 0_u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / div as u64) as u32;
+            remainder = current % div as u64;
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        remainder as u32
+    }
+
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    // Big-endian, keeping only the low `len` bytes.
+    pub fn to_be_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        for (i, byte) in bytes.iter_mut().rev().enumerate() {
+            if let Some(limb) = self.limbs.get(i / 4) {
+                *byte = (limb >> (8 * (i % 4))) as u8;
+            }
+        }
+        bytes
+    }
+
+    // The low `count` base-`radix` digits, least significant first.
+    pub fn low_numerals(&self, radix: u32, count: usize) -> Vec<u16> {
+        let mut value = self.clone();
+        (0..count)
+            .map(|_| value.div_rem_small(radix) as u16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bytes = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x10];
+        let value = BigUint::from_be_bytes(&bytes);
+        assert_eq!(value.bits(), 65);
+        assert_eq!(value.to_be_bytes(9), bytes);
+        assert_eq!(value.to_be_bytes(2), [0xef, 0x10]);
+
+        let decimal = BigUint::from_numerals([1, 2, 3, 4, 5].into_iter(), 10);
+        assert_eq!(decimal.low_numerals(10, 7), [5, 4, 3, 2, 1, 0, 0]);
+        assert_eq!(decimal.to_be_bytes(2), 12345_u16.to_be_bytes());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+use crate::modes::xor_block;
+
+use super::bignum::BigUint;
+use super::{add_mod, check_numerals, check_radix, sub_mod, FormatPreserving};
+
+const ROUNDS: u8 = 10;
+
+// FF1, NIST SP 800-38G section 5.1
+pub struct Ff1 {
+    cipher: BlockCipher,
+    radix: u32,
+}
+
+impl Ff1 {
+    pub fn new(key: &Key, radix: u32) -> Result<Self, Error> {
+        check_radix(radix)?;
+        Ok(Ff1 {
+            cipher: BlockCipher::new(key),
+            radix,
+        })
+    }
+
+    fn prf(&self, data: &[u8]) -> [u8; 16] {
+        let mut mac = [0; 16];
+        for chunk in data.chunks(16) {
+            let mut block = [0; 16];
+            block.copy_from_slice(chunk);
+            mac = self.cipher.encrypt_block(&xor_block(&mac, &block));
+        }
+        mac
+    }
+
+    fn round(&self, tweak: &[u8], n: usize, round: u8, b: usize, half: &[u16]) -> BigUint {
+        let u = n / 2;
+        let d = 4 * b.div_ceil(4) + 4;
+
+        let mut input = vec![1, 2, 1];
+        input.extend_from_slice(&self.radix.to_be_bytes()[1..]);
+        input.extend_from_slice(&[10, u as u8]);
+        input.extend_from_slice(&(n as u32).to_be_bytes());
+        input.extend_from_slice(&(tweak.len() as u32).to_be_bytes());
+        input.extend_from_slice(tweak);
+        let zeros = (16 - (tweak.len() + b + 1) % 16) % 16;
+        input.resize(input.len() + zeros, 0);
+        input.push(round);
+        let number = BigUint::from_numerals(half.iter().copied(), self.radix);
+        input.extend(number.to_be_bytes(b));
+
+        let r = self.prf(&input);
+        let mut s = r.to_vec();
+        let mut j = 1_u128;
+        while s.len() < d {
+            s.extend(self.cipher.encrypt_block(&xor_block(&r, &j.to_be_bytes())));
+            j += 1;
+        }
+        BigUint::from_be_bytes(&s[..d])
+    }
+
+    // Byte length of NUM_radix(B): ceil(ceil(v * log2(radix)) / 8)
+    fn byte_len(&self, v: usize) -> usize {
+        let bits = if self.radix.is_power_of_two() {
+            v * self.radix.trailing_zeros() as usize
+        } else {
+            let mut domain = BigUint::from_be_bytes(&[1]);
+            for _ in 0..v {
+                domain.mul_add_small(self.radix, 0);
+            }
+            domain.bits()
+        };
+        bits.div_ceil(8)
+    }
+
+    fn check(&self, tweak: &[u8], numerals: &[u16]) -> Result<(), Error> {
+        check_numerals(self.radix, numerals, u32::MAX as usize)?;
+        if tweak.len() > u32::MAX as usize {
+            return Err(Error::InvalidLength("tweak is too long".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl FormatPreserving for Ff1 {
+    fn radix(&self) -> u32 {
+        self.radix
+    }
+
+    fn encrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error> {
+        self.check(tweak, numerals)?;
+        let n = numerals.len();
+        let b = self.byte_len(n - n / 2);
+        let (a, b_half) = numerals.split_at(n / 2);
+        let (mut a, mut b_half) = (a.to_vec(), b_half.to_vec());
+        for i in 0..ROUNDS {
+            let y = self.round(tweak, n, i, b, &b_half);
+            let a_le: Vec<u16> = a.iter().rev().copied().collect();
+            let mut c = add_mod(&a_le, &y, self.radix);
+            c.reverse();
+            a = b_half;
+            b_half = c;
+        }
+        a.extend(b_half);
+        Ok(a)
+    }
+
+    fn decrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error> {
+        self.check(tweak, numerals)?;
+        let n = numerals.len();
+        let b = self.byte_len(n - n / 2);
+        let (a, b_half) = numerals.split_at(n / 2);
+        let (mut a, mut b_half) = (a.to_vec(), b_half.to_vec());
+        for i in (0..ROUNDS).rev() {
+            let y = self.round(tweak, n, i, b, &a);
+            let b_le: Vec<u16> = b_half.iter().rev().copied().collect();
+            let mut c = sub_mod(&b_le, &y, self.radix);
+            c.reverse();
+            b_half = a;
+            a = c;
+        }
+        a.extend(b_half);
+        Ok(a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+    use crate::fpe::{Alphabet, MAX_RADIX};
+
+    const KEY128: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const KEY192: &str = "2b7e151628aed2a6abf7158809cf4f3cef4359d8d580aa4f";
+    const KEY256: &str = "2b7e151628aed2a6abf7158809cf4f3cef4359d8d580aa4f7f036d6f04fc6a94";
+
+    fn check(key: &str, radix_chars: &str, tweak: &str, plaintext: &str, ciphertext: &str) {
+        let key = Key::from_bytes(&from_hex(key).unwrap());
+        let alphabet = Alphabet::new(radix_chars).unwrap();
+        let ff1 = Ff1::new(&key, alphabet.radix()).unwrap();
+        let tweak = from_hex(tweak).unwrap();
+        assert_eq!(
+            ff1.encrypt_str(&alphabet, &tweak, plaintext).unwrap(),
+            ciphertext
+        );
+        assert_eq!(
+            ff1.decrypt_str(&alphabet, &tweak, ciphertext).unwrap(),
+            plaintext
+        );
+    }
+
+    // NIST SP 800-38G FF1 samples 1-3 (AES-128)
+    #[test]
+    fn nist_samples() {
+        check(KEY128, "0123456789", "", "0123456789", "2433477484");
+        check(
+            KEY128,
+            "0123456789",
+            "39383736353433323130",
+            "0123456789",
+            "6124200773",
+        );
+        check(
+            KEY128,
+            "0123456789abcdefghijklmnopqrstuvwxyz",
+            "3737373770717273373737",
+            "0123456789abcdefghi",
+            "a9tv40mll9kdu509eum",
+        );
+    }
+
+    // Samples 4-6 (AES-192)
+    #[test]
+    fn nist_samples_aes192() {
+        check(KEY192, "0123456789", "", "0123456789", "2830668132");
+        check(
+            KEY192,
+            "0123456789",
+            "39383736353433323130",
+            "0123456789",
+            "2496655549",
+        );
+        check(
+            KEY192,
+            "0123456789abcdefghijklmnopqrstuvwxyz",
+            "3737373770717273373737",
+            "0123456789abcdefghi",
+            "xbj3kv35jrawxv32ysr",
+        );
+    }
+
+    // Samples 7-9 (AES-256)
+    #[test]
+    fn nist_samples_aes256() {
+        check(KEY256, "0123456789", "", "0123456789", "6657667009");
+        check(
+            KEY256,
+            "0123456789",
+            "39383736353433323130",
+            "0123456789",
+            "1001623463",
+        );
+        check(
+            KEY256,
+            "0123456789abcdefghijklmnopqrstuvwxyz",
+            "3737373770717273373737",
+            "0123456789abcdefghi",
+            "xs8a0azh2avyalyzuwd",
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let key = Key::from_bytes(&[7; 16]);
+        assert!(Ff1::new(&key, 1).is_err());
+        assert!(Ff1::new(&key, MAX_RADIX + 1).is_err());
+
+        let ff1 = Ff1::new(&key, 10).unwrap();
+        assert!(matches!(
+            ff1.encrypt(b"", &[1, 2, 3, 4, 5]),
+            Err(Error::InvalidLength(_))
+        ));
+        assert!(ff1.encrypt(b"", &[1, 2, 3, 4, 5, 10]).is_err());
+
+        let wide = Ff1::new(&key, MAX_RADIX).unwrap();
+        let plaintext = [0, 65535, 1234, 42, 7];
+        let ciphertext = wide.encrypt(b"tweak", &plaintext).unwrap();
+        assert_ne!(ciphertext, plaintext);
+        assert_eq!(wide.decrypt(b"tweak", &ciphertext).unwrap(), plaintext);
+        assert_ne!(wide.encrypt(b"other", &plaintext).unwrap(), ciphertext);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+
+use super::bignum::BigUint;
+use super::{add_mod, check_numerals, check_radix, sub_mod, FormatPreserving};
+
+const ROUNDS: u8 = 8;
+pub const TWEAK_LEN: usize = 7;
+
+// FF3-1, NIST SP 800-38G Rev. 1 section 5.2. The spec's REV/REVB steps are
+// folded into storing each half least significant numeral first.
+pub struct Ff31 {
+    cipher: BlockCipher,
+    radix: u32,
+    max_len: usize,
+}
+
+impl Ff31 {
+    pub fn new(key: &Key, radix: u32) -> Result<Self, Error> {
+        check_radix(radix)?;
+        let mut reversed = key.to_bytes();
+        reversed.reverse();
+
+        // 2 * floor(log_radix(2^96))
+        let mut domain = radix as u128;
+        let mut max_len = 0;
+        while domain <= 1 << 96 {
+            domain *= radix as u128;
+            max_len += 2;
+        }
+        Ok(Ff31 {
+            cipher: BlockCipher::new(&Key::from_bytes(&reversed)),
+            radix,
+            max_len,
+        })
+    }
+
+    fn round(&self, w: &[u8; 4], round: u8, half: &[u16]) -> BigUint {
+        let mut p = [0; 16];
+        p[..4].copy_from_slice(w);
+        p[3] ^= round;
+        let number = BigUint::from_numerals(half.iter().rev().copied(), self.radix);
+        p[4..].copy_from_slice(&number.to_be_bytes(12));
+        p.reverse();
+        let mut s = self.cipher.encrypt_block(&p);
+        s.reverse();
+        BigUint::from_be_bytes(&s)
+    }
+
+    fn split_tweak(tweak: &[u8]) -> Result<([u8; 4], [u8; 4]), Error> {
+        if tweak.len() != TWEAK_LEN {
+            return Err(Error::InvalidLength(format!(
+                "FF3-1 tweak must be {} bytes",
+                TWEAK_LEN
+            )));
+        }
+        let left = [tweak[0], tweak[1], tweak[2], tweak[3] & 0xf0];
+        let right = [tweak[4], tweak[5], tweak[6], tweak[3] << 4];
+        Ok((left, right))
+    }
+
+    fn encrypt_halves(&self, left: &[u8; 4], right: &[u8; 4], numerals: &[u16]) -> Vec<u16> {
+        let (a, b) = numerals.split_at(numerals.len().div_ceil(2));
+        let (mut a, mut b) = (a.to_vec(), b.to_vec());
+        for i in 0..ROUNDS {
+            let w = if i % 2 == 0 { right } else { left };
+            let y = self.round(w, i, &b);
+            let c = add_mod(&a, &y, self.radix);
+            a = b;
+            b = c;
+        }
+        a.extend(b);
+        a
+    }
+
+    fn decrypt_halves(&self, left: &[u8; 4], right: &[u8; 4], numerals: &[u16]) -> Vec<u16> {
+        let (a, b) = numerals.split_at(numerals.len().div_ceil(2));
+        let (mut a, mut b) = (a.to_vec(), b.to_vec());
+        for i in (0..ROUNDS).rev() {
+            let w = if i % 2 == 0 { right } else { left };
+            let y = self.round(w, i, &a);
+            let c = sub_mod(&b, &y, self.radix);
+            b = a;
+            a = c;
+        }
+        a.extend(b);
+        a
+    }
+}
+
+impl FormatPreserving for Ff31 {
+    fn radix(&self) -> u32 {
+        self.radix
+    }
+
+    fn encrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error> {
+        let (left, right) = Ff31::split_tweak(tweak)?;
+        check_numerals(self.radix, numerals, self.max_len)?;
+        Ok(self.encrypt_halves(&left, &right, numerals))
+    }
+
+    fn decrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error> {
+        let (left, right) = Ff31::split_tweak(tweak)?;
+        check_numerals(self.radix, numerals, self.max_len)?;
+        Ok(self.decrypt_halves(&left, &right, numerals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+    use crate::fpe::Alphabet;
+
+    const BASE36: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+    const KEY128: &str = "ef4359d8d580aa4f7f036d6f04fc6a94";
+    const KEY192: &str = "ef4359d8d580aa4f7f036d6f04fc6a942b7e151628aed2a6";
+    const KEY256: &str = "ef4359d8d580aa4f7f036d6f04fc6a942b7e151628aed2a6abf7158809cf4f3c";
+
+    // The original FF3 samples use a 64-bit tweak split straight down the
+    // middle; FF3-1 only changes how the tweak is split, so they still pin
+    // down the Feistel rounds.
+    fn check_ff3(key: &str, ciphertexts: [&str; 5]) {
+        let key = Key::from_bytes(&from_hex(key).unwrap());
+        let samples = [
+            (10, "d8e7920afa330a73", "890121234567890000"),
+            (10, "9a768a92f60e12d8", "890121234567890000"),
+            (10, "d8e7920afa330a73", "89012123456789000000789000000"),
+            (10, "0000000000000000", "89012123456789000000789000000"),
+            (26, "9a768a92f60e12d8", "0123456789abcdefghi"),
+        ];
+        for ((radix, tweak, plaintext), ciphertext) in samples.into_iter().zip(ciphertexts) {
+            let alphabet = Alphabet::new(&BASE36[..radix]).unwrap();
+            let ff3 = Ff31::new(&key, radix as u32).unwrap();
+            let tweak = from_hex(tweak).unwrap();
+            let left = [tweak[0], tweak[1], tweak[2], tweak[3]];
+            let right = [tweak[4], tweak[5], tweak[6], tweak[7]];
+            let numerals = alphabet.to_numerals(plaintext).unwrap();
+            let encrypted = ff3.encrypt_halves(&left, &right, &numerals);
+            assert_eq!(alphabet.to_text(&encrypted), ciphertext);
+            assert_eq!(ff3.decrypt_halves(&left, &right, &encrypted), numerals);
+        }
+    }
+
+    // NIST FF3 samples 1-5 (AES-128), 6-10 (AES-192) and 11-15 (AES-256)
+    #[test]
+    fn nist_ff3_samples() {
+        check_ff3(
+            KEY128,
+            [
+                "750918814058654607",
+                "018989839189395384",
+                "48598367162252569629397416226",
+                "34695224821734535122613701434",
+                "g2pk40i992fn20cjakb",
+            ],
+        );
+        check_ff3(
+            KEY192,
+            [
+                "646965393875028755",
+                "961610514491424446",
+                "53048884065350204541786380807",
+                "98083802678820389295041483512",
+                "i0ihe2jfj7a9opf9p88",
+            ],
+        );
+        check_ff3(
+            KEY256,
+            [
+                "922011205562777495",
+                "504149865578056140",
+                "04344343235792599165734622699",
+                "30859239999374053872365555822",
+                "p0b2godfja9bhb7bk38",
+            ],
+        );
+    }
+
+    fn check_ff3_1(key: &str, plaintext: &str, ciphertext: &str) {
+        let key = Key::from_bytes(&from_hex(key).unwrap());
+        let alphabet = Alphabet::new("0123456789").unwrap();
+        let ff3 = Ff31::new(&key, 10).unwrap();
+        let tweak = from_hex("cbd09280979564").unwrap();
+        assert_eq!(
+            ff3.encrypt_str(&alphabet, &tweak, plaintext).unwrap(),
+            ciphertext
+        );
+        assert_eq!(
+            ff3.decrypt_str(&alphabet, &tweak, ciphertext).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn ff3_1_sample() {
+        check_ff3_1(
+            "2de79d232df5585d68ce47882ae256d6",
+            "3992520240",
+            "8901801106",
+        );
+    }
+
+    // The 56-bit tweak under the FF3 sample keys, checked against an
+    // independent implementation.
+    #[test]
+    fn ff3_1_longer_keys() {
+        check_ff3_1(KEY192, "3992520240", "9588975085");
+        check_ff3_1(KEY256, "3992520240", "3362315583");
+    }
+
+    #[test]
+    fn limits() {
+        let key = Key::from_bytes(&[7; 16]);
+        let ff3 = Ff31::new(&key, 10).unwrap();
+        assert_eq!(ff3.max_len, 56);
+        assert!(ff3.encrypt(&[0; 8], &[0; 10]).is_err());
+        assert!(ff3.encrypt(&[0; 7], &[0; 57]).is_err());
+        assert!(ff3.encrypt(&[0; 7], &[0; 56]).is_ok());
+
+        let wide = Ff31::new(&key, 1 << 16).unwrap();
+        assert_eq!(wide.max_len, 12);
+        let plaintext = [65535, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 65534];
+        let ciphertext = wide.encrypt(b"7 bytes", &plaintext).unwrap();
+        assert_eq!(wide.decrypt(b"7 bytes", &ciphertext).unwrap(), plaintext);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use crate::error::Error;
+
+pub use self::ff1::Ff1;
+pub use self::ff3_1::Ff31;
+
+mod bignum;
+mod ff1;
+mod ff3_1;
+
+use self::bignum::BigUint;
+
+pub const MAX_RADIX: u32 = 1 << 16;
+
+pub trait FormatPreserving {
+    fn radix(&self) -> u32;
+    fn encrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error>;
+    fn decrypt(&self, tweak: &[u8], numerals: &[u16]) -> Result<Vec<u16>, Error>;
+
+    fn encrypt_str(&self, alphabet: &Alphabet, tweak: &[u8], text: &str) -> Result<String, Error> {
+        check_alphabet(self.radix(), alphabet)?;
+        let numerals = self.encrypt(tweak, &alphabet.to_numerals(text)?)?;
+        Ok(alphabet.to_text(&numerals))
+    }
+
+    fn decrypt_str(&self, alphabet: &Alphabet, tweak: &[u8], text: &str) -> Result<String, Error> {
+        check_alphabet(self.radix(), alphabet)?;
+        let numerals = self.decrypt(tweak, &alphabet.to_numerals(text)?)?;
+        Ok(alphabet.to_text(&numerals))
+    }
+}
+
+pub struct Alphabet {
+    chars: Vec<char>,
+    index: HashMap<char, u16>,
+}
+
+impl Alphabet {
+    pub fn new(chars: &str) -> Result<Self, Error> {
+        let chars: Vec<char> = chars.chars().collect();
+        if chars.len() < 2 || chars.len() > MAX_RADIX as usize {
+            return Err(Error::InvalidInput(format!(
+                "alphabet must have between 2 and {} characters",
+                MAX_RADIX
+            )));
+        }
+        let mut index = HashMap::with_capacity(chars.len());
+        for (i, &c) in chars.iter().enumerate() {
+            if index.insert(c, i as u16).is_some() {
+                return Err(Error::InvalidInput(format!(
+                    "alphabet contains '{}' twice",
+                    c
+                )));
+            }
+        }
+        Ok(Alphabet { chars, index })
+    }
+
+    pub fn radix(&self) -> u32 {
+        self.chars.len() as u32
+    }
+
+    pub fn to_numerals(&self, text: &str) -> Result<Vec<u16>, Error> {
+        text.chars()
+            .map(|c| {
+                self.index
+                    .get(&c)
+                    .copied()
+                    .ok_or_else(|| Error::InvalidInput(format!("'{}' is not in the alphabet", c)))
+            })
+            .collect()
+    }
+
+    pub fn to_text(&self, numerals: &[u16]) -> String {
+        numerals.iter().map(|&n| self.chars[n as usize]).collect()
+    }
+}
+
+fn check_alphabet(radix: u32, alphabet: &Alphabet) -> Result<(), Error> {
+    if alphabet.radix() != radix {
+        return Err(Error::InvalidInput(format!(
+            "alphabet has radix {}, cipher expects {}",
+            alphabet.radix(),
+            radix
+        )));
+    }
+    Ok(())
+}
+
+fn check_radix(radix: u32) -> Result<(), Error> {
+    if !(2..=MAX_RADIX).contains(&radix) {
+        return Err(Error::InvalidInput(format!(
+            "radix must be between 2 and {}",
+            MAX_RADIX
+        )));
+    }
+    Ok(())
+}
+
+// SP 800-38G requires radix^minlen >= 1_000_000.
+fn min_len(radix: u32) -> usize {
+    let mut domain = 1_u64;
+    let mut len = 0;
+    while domain < 1_000_000 {
+        domain *= radix as u64;
+        len += 1;
+    }
+    len.max(2)
+}
+
+fn check_numerals(radix: u32, numerals: &[u16], max_len: usize) -> Result<(), Error> {
+    let min_len = min_len(radix);
+    if numerals.len() < min_len || numerals.len() > max_len {
+        return Err(Error::InvalidLength(format!(
+            "radix {} needs between {} and {} numerals, got {}",
+            radix,
+            min_len,
+            max_len,
+            numerals.len()
+        )));
+    }
+    if numerals.iter().any(|&n| n as u32 >= radix) {
+        return Err(Error::InvalidInput(format!(
+            "numeral out of range for radix {}",
+            radix
+        )));
+    }
+    Ok(())
+}
+
+// (x + y) mod radix^m and (x - y) mod radix^m on numeral strings stored
+// least significant first, so no big division is needed.
+fn add_mod(x: &[u16], y: &BigUint, radix: u32) -> Vec<u16> {
+    let mut carry = 0;
+    x.iter()
+        .zip(y.low_numerals(radix, x.len()))
+        .map(|(&a, b)| {
+            let sum = a as u32 + b as u32 + carry;
+            carry = (sum >= radix) as u32;
+            (sum % radix) as u16
+        })
+        .collect()
+}
+
+fn sub_mod(x: &[u16], y: &BigUint, radix: u32) -> Vec<u16> {
+    let mut borrow = 0;
+    x.iter()
+        .zip(y.low_numerals(radix, x.len()))
+        .map(|(&a, b)| {
+            let difference = a as i64 - b as i64 - borrow;
+            borrow = (difference < 0) as i64;
+            (difference + borrow * radix as i64) as u16
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alphabet() {
+        let hex = Alphabet::new("0123456789abcdef").unwrap();
+        assert_eq!(hex.radix(), 16);
+        assert_eq!(hex.to_numerals("c0ffee").unwrap(), [12, 0, 15, 15, 14, 14]);
+        assert_eq!(hex.to_text(&[12, 0, 15, 15, 14, 14]), "c0ffee");
+        assert!(hex.to_numerals("coffee").is_err());
+        assert!(Alphabet::new("0120").is_err());
+        assert!(Alphabet::new("0").is_err());
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        let y = BigUint::from_be_bytes(&[0x03, 0xe8]);
+        // 9995 + 1000 = 10995 -> 995 mod 10^4, stored least significant first
+        assert_eq!(add_mod(&[5, 9, 9, 9], &y, 10), [5, 9, 9, 0]);
+        assert_eq!(add_mod(&[5, 9, 9, 0], &y, 10), [5, 9, 9, 1]);
+        // 5 - 1000 mod 10^4 = 9005
+        assert_eq!(sub_mod(&[5, 0, 0, 0], &y, 10), [5, 0, 0, 9]);
+        assert_eq!(min_len(10), 6);
+        assert_eq!(min_len(36), 4);
+        assert_eq!(min_len(MAX_RADIX), 2);
+    }
+}
@@ -0,0 +1,165 @@
+use super::Hash;
+
+// BLAKE2b, RFC 7693, unkeyed. Argon2 needs it with output lengths other than
+// 64 bytes, hence with_len.
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+#[derive(Clone)]
+pub struct Blake2b {
+    state: [u64; 8],
+    buffer: Vec<u8>,
+    length: u128,
+    output_len: usize,
+}
+
+impl Blake2b {
+    pub fn with_len(output_len: usize) -> Self {
+        assert!(
+            (1..=64).contains(&output_len),
+            "BLAKE2b outputs 1 to 64 bytes"
+        );
+        let mut state = IV;
+        state[0] ^= 0x0101_0000 ^ output_len as u64;
+        Blake2b {
+            state,
+            buffer: Vec::with_capacity(128),
+            length: 0,
+            output_len,
+        }
+    }
+
+    fn compress(&mut self, block: &[u8], last: bool) {
+        let mut m = [0u64; 16];
+        for (i, word) in block.chunks(8).enumerate() {
+            m[i] = u64::from_le_bytes(word.try_into().expect("8-byte chunk"));
+        }
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.state);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.length as u64;
+        v[13] ^= (self.length >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+        for round in 0..12 {
+            let s = &SIGMA[round % 10];
+            mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 {
+            self.state[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+impl Hash for Blake2b {
+    const BLOCK_SIZE: usize = 128;
+    const OUTPUT_SIZE: usize = 64;
+
+    fn new() -> Self {
+        Blake2b::with_len(64)
+    }
+
+    // The final block is compressed differently, so a full buffer is only
+    // compressed once more data arrives.
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.buffer.len() == 128 {
+                self.length += 128;
+                let block = std::mem::take(&mut self.buffer);
+                self.compress(&block, false);
+            }
+            let take = data.len().min(128 - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        self.length += self.buffer.len() as u128;
+        let mut block = std::mem::take(&mut self.buffer);
+        block.resize(128, 0);
+        self.compress(&block, true);
+        let mut output: Vec<u8> = self
+            .state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        output.truncate(self.output_len);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+
+    // RFC 7693 appendix A
+    #[test]
+    fn abc() {
+        assert_eq!(
+            to_hex(&Blake2b::digest(b"abc")),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+    }
+
+    // Checked against Python's hashlib.blake2b.
+    #[test]
+    fn empty_and_truncated() {
+        assert_eq!(
+            to_hex(&Blake2b::digest(b"")),
+            "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419\
+             d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce"
+        );
+        let data: Vec<u8> = (0..=255).chain(0..=255).collect();
+        let mut hash = Blake2b::with_len(32);
+        for chunk in data.chunks(100) {
+            hash.update(chunk);
+        }
+        assert_eq!(
+            to_hex(&hash.finalize()),
+            "540b20132d8aeae54057cb69c24f95d26a1c472cc700dd450defe9bb796d4f14"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use super::Hash;
+
+#[derive(Clone)]
+pub struct Hmac<H: Hash> {
+    inner: H,
+    outer: H,
+}
+
+impl<H: Hash> Hmac<H> {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block_key = if key.len() > H::BLOCK_SIZE {
+            H::digest(key)
+        } else {
+            key.to_vec()
+        };
+        block_key.resize(H::BLOCK_SIZE, 0);
+
+        let mut inner = H::new();
+        inner.update(
+            &block_key
+                .iter()
+                .map(|byte| byte ^ 0x36)
+                .collect::<Vec<u8>>(),
+        );
+        let mut outer = H::new();
+        outer.update(
+            &block_key
+                .iter()
+                .map(|byte| byte ^ 0x5c)
+                .collect::<Vec<u8>>(),
+        );
+        Hmac { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+
+    pub fn mac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut hmac = Self::new(key);
+        hmac.update(data);
+        hmac.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+    use crate::hash::Sha256;
+
+    // RFC 4231 test cases 1, 2, 4, 6 and 7
+    #[test]
+    fn rfc4231() {
+        let cases = [
+            (
+                "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+                "4869205468657265",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                "4a656665",
+                "7768617420646f2079612077616e7420666f72206e6f7468696e673f",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                "0102030405060708090a0b0c0d0e0f10111213141516171819",
+                &"cd".repeat(50),
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &"aa".repeat(131),
+                "54657374205573696e67204c6172676572205468616e20426c6f636b2d53697a\
+                 65204b6579202d2048617368204b6579204669727374",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &"aa".repeat(131),
+                "5468697320697320612074657374207573696e672061206c6172676572207468\
+                 616e20626c6f636b2d73697a65206b657920616e642061206c61726765722074\
+                 68616e20626c6f636b2d73697a6520646174612e20546865206b6579206e6565\
+                 647320746f20626520686173686564206265666f7265206265696e6720757365\
+                 642062792074686520484d414320616c676f726974686d2e",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, expected) in cases.iter() {
+            let key = from_hex(key).unwrap();
+            let data = from_hex(data).unwrap();
+            assert_eq!(to_hex(&Hmac::<Sha256>::mac(&key, &data)), *expected);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+pub use self::blake2b::Blake2b;
+pub use self::hmac::Hmac;
+pub use self::md5::Md5;
+pub use self::sha1::Sha1;
+pub use self::sha256::Sha256;
+
+mod blake2b;
+mod hmac;
+mod md5;
+mod sha1;
+mod sha256;
+
+pub trait Hash: Clone {
+    const BLOCK_SIZE: usize;
+    const OUTPUT_SIZE: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Vec<u8>;
+
+    fn digest(data: &[u8]) -> Vec<u8> {
+        let mut hash = Self::new();
+        hash.update(data);
+        hash.finalize()
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    difference == 0
+}
@@ -0,0 +1,451 @@
+use std::io::{ErrorKind, Read};
+
+use crate::aes::BlockCipher;
+
+pub use self::reader::DecryptReader;
+pub use self::writer::EncryptWriter;
+
+mod reader;
+mod writer;
+
+// CTR keystream that can stop and resume mid-block between calls.
+struct CtrState {
+    cipher: BlockCipher,
+    initial: [u8; 16],
+    counter: [u8; 16],
+    keystream: [u8; 16],
+    used: usize,
+}
+
+impl CtrState {
+    fn new(cipher: BlockCipher, counter: &[u8; 16]) -> Self {
+        CtrState {
+            cipher,
+            initial: *counter,
+            counter: *counter,
+            keystream: [0; 16],
+            used: 16,
+        }
+    }
+
+    fn seek(&mut self, offset: u64) {
+        self.counter = crate::modes::ctr::advance(&self.initial, offset / 16);
+        self.used = 16;
+        let mut skipped = vec![0; (offset % 16) as usize];
+        self.apply(&mut skipped);
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            if self.used == 16 {
+                self.keystream = self.cipher.encrypt_block(&self.counter);
+                crate::modes::ctr::increment(&mut self.counter);
+                self.used = 0;
+            }
+            *byte ^= self.keystream[self.used];
+            self.used += 1;
+        }
+    }
+
+    // As apply, with the whole blocks in the middle split across threads.
+    fn apply_threaded(&mut self, data: &mut [u8], threads: usize) {
+        let head = ((16 - self.used) % 16).min(data.len());
+        let (head, rest) = data.split_at_mut(head);
+        self.apply(head);
+        let blocks = rest.len() / 16;
+        let (body, tail) = rest.split_at_mut(blocks * 16);
+        if blocks > 0 {
+            let output = crate::parallel::ctr(&self.cipher, &self.counter, body, threads);
+            body.copy_from_slice(&output);
+            self.counter = crate::modes::ctr::advance(&self.counter, blocks as u64);
+        }
+        self.apply(tail);
+    }
+}
+
+// Splits a reader into fixed-size pieces, reading one byte ahead so the
+// final piece is known before it is processed.
+struct Segments<R: Read> {
+    reader: R,
+    size: usize,
+    buffer: Vec<u8>,
+    current: usize,
+    // Bytes taken from `reader` so far, to locate the start after seeking.
+    consumed: u64,
+}
+
+impl<R: Read> Segments<R> {
+    fn new(reader: R, size: usize) -> Self {
+        Segments {
+            reader,
+            size,
+            buffer: Vec::with_capacity(size + 1),
+            current: 0,
+            consumed: 0,
+        }
+    }
+
+    // Drops buffered input after the underlying reader has been moved.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.current = 0;
+    }
+
+    // Advances to the next piece and reports whether it is the last one.
+    fn next(&mut self) -> std::io::Result<bool> {
+        self.buffer.drain(..self.current);
+        while self.buffer.len() <= self.size {
+            let filled = self.buffer.len();
+            self.buffer.resize(self.size + 1, 0);
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => {
+                    self.buffer.truncate(filled);
+                    break;
+                }
+                Ok(read) => {
+                    self.buffer.truncate(filled + read);
+                    self.consumed += read as u64;
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {
+                    self.buffer.truncate(filled)
+                }
+                Err(error) => {
+                    self.buffer.truncate(filled);
+                    return Err(error);
+                }
+            }
+        }
+        self.current = self.buffer.len().min(self.size);
+        Ok(self.buffer.len() <= self.size)
+    }
+
+    fn current(&self) -> &[u8] {
+        &self.buffer[..self.current]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::error::Error;
+    use crate::modes::gcm::TAG_LEN;
+    use crate::modes::stream::{Stream, NONCE_PREFIX_LEN, SEALED_SEGMENT_SIZE, SEGMENT_SIZE};
+    use crate::modes::{cbc, ctr};
+    use crate::padding::{Padding, Pkcs7Padding, ZeroPadding};
+    use std::io::{Cursor, Seek, SeekFrom, Write};
+
+    fn cipher() -> BlockCipher {
+        BlockCipher::new(&Key::from_bytes(&[0x42; 16]))
+    }
+
+    fn stream() -> Stream {
+        Stream::new(&Key::from_bytes(&[0x42; 16]), &[7; NONCE_PREFIX_LEN]).unwrap()
+    }
+
+    // Writes in uneven pieces to exercise the buffering.
+    fn write_all<W: Write>(mut writer: EncryptWriter<W>, data: &[u8]) -> W {
+        for piece in data.chunks(1000) {
+            let (a, b) = piece.split_at(piece.len() / 3);
+            writer.write_all(a).unwrap();
+            writer.write_all(b).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_all<R: Read>(mut reader: DecryptReader<R>) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    fn encrypt_stream(plaintext: &[u8]) -> Vec<u8> {
+        write_all(
+            EncryptWriter::stream(Vec::new(), stream(), b"aad"),
+            plaintext,
+        )
+    }
+
+    fn decrypt_stream(sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        read_all(DecryptReader::stream(sealed, stream(), b"aad"))
+    }
+
+    #[test]
+    fn segments() {
+        let data: Vec<u8> = (0..10).collect();
+        let mut segments = Segments::new(&data[..], 4);
+        assert!(!segments.next().unwrap());
+        assert_eq!(segments.current(), [0, 1, 2, 3]);
+        assert!(!segments.next().unwrap());
+        assert_eq!(segments.current(), [4, 5, 6, 7]);
+        assert!(segments.next().unwrap());
+        assert_eq!(segments.current(), [8, 9]);
+
+        let mut aligned = Segments::new(&data[..8], 4);
+        assert!(!aligned.next().unwrap());
+        assert!(aligned.next().unwrap());
+        assert_eq!(aligned.current(), [4, 5, 6, 7]);
+
+        let mut empty = Segments::new(&[][..], 4);
+        assert!(empty.next().unwrap());
+        assert!(empty.current().is_empty());
+    }
+
+    #[test]
+    fn ctr_matches_one_shot() {
+        let plaintext: Vec<u8> = (0..5000).map(|i| (i * 7) as u8).collect();
+        let counter = [0xf0; 16];
+        let sealed = write_all(
+            EncryptWriter::ctr(Vec::new(), cipher(), &counter),
+            &plaintext,
+        );
+        assert_eq!(sealed, ctr::apply(&cipher(), &counter, &plaintext));
+        let reader = DecryptReader::ctr(&sealed[..], cipher(), &counter);
+        assert_eq!(read_all(reader).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn cbc_matches_one_shot() {
+        let iv = [3; 16];
+        for length in [0, 1, 15, 16, 17, 4095, 4096, 4097, 9000] {
+            let plaintext: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+            let writer = EncryptWriter::cbc(Vec::new(), cipher(), &iv, Box::new(Pkcs7Padding));
+            let sealed = write_all(writer, &plaintext);
+            let padded = Pkcs7Padding.pad(&plaintext).unwrap();
+            assert_eq!(sealed, cbc::encrypt(&cipher(), &iv, &padded).unwrap());
+            let reader = DecryptReader::cbc(&sealed[..], cipher(), &iv, Box::new(Pkcs7Padding));
+            assert_eq!(read_all(reader).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn ecb_matches_legacy_format() {
+        let key = Key::from_password("Password1234", crate::aes::AesSize::S128);
+        let plaintext = b"Hello, World! This spans several blocks.";
+        let writer = EncryptWriter::ecb(Vec::new(), BlockCipher::new(&key), Box::new(ZeroPadding));
+        let sealed = write_all(writer, plaintext);
+        assert_eq!(
+            sealed,
+            crate::aes::encrypt_with_padding(&key, plaintext, &mut ZeroPadding).unwrap()
+        );
+        let reader = DecryptReader::ecb(&sealed[..], BlockCipher::new(&key), Box::new(ZeroPadding));
+        assert_eq!(read_all(reader).unwrap(), plaintext);
+
+        let reader =
+            DecryptReader::ecb(&sealed[..17], BlockCipher::new(&key), Box::new(ZeroPadding));
+        assert!(matches!(read_all(reader), Err(Error::InvalidLength(_))));
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        for length in [0, 1, SEGMENT_SIZE, SEGMENT_SIZE + 1, 2 * SEGMENT_SIZE] {
+            let plaintext: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let sealed = encrypt_stream(&plaintext);
+            let segments = length.div_ceil(SEGMENT_SIZE).max(1);
+            assert_eq!(sealed.len(), length + segments * TAG_LEN);
+            assert_eq!(decrypt_stream(&sealed).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn stream_truncation_is_detected() {
+        let sealed = encrypt_stream(&vec![1; 2 * SEGMENT_SIZE + 10]);
+        assert_eq!(
+            decrypt_stream(&sealed[..2 * SEALED_SEGMENT_SIZE]),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            decrypt_stream(&sealed[..SEALED_SEGMENT_SIZE]),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            decrypt_stream(&sealed[..sealed.len() - 1]),
+            Err(Error::Authentication)
+        );
+        assert!(decrypt_stream(&[]).is_err());
+    }
+
+    #[test]
+    fn stream_reordering_is_detected() {
+        let sealed = encrypt_stream(&vec![1; 2 * SEGMENT_SIZE + 1]);
+        let (first, rest) = sealed.split_at(SEALED_SEGMENT_SIZE);
+        let (second, third) = rest.split_at(SEALED_SEGMENT_SIZE);
+        let swapped = [second, first, third].concat();
+        assert_eq!(decrypt_stream(&swapped), Err(Error::Authentication));
+    }
+
+    #[test]
+    fn stream_extension_is_detected() {
+        let sealed = encrypt_stream(b"short");
+        let extended = [sealed.as_slice(), &sealed].concat();
+        assert_eq!(decrypt_stream(&extended), Err(Error::Authentication));
+
+        let mut appended = encrypt_stream(&vec![2; SEGMENT_SIZE]);
+        appended.extend(stream().seal_segment(1, true, b"aad", b"more"));
+        assert_eq!(decrypt_stream(&appended), Err(Error::Authentication));
+    }
+
+    #[test]
+    fn failed_reader_stays_failed() {
+        let mut sealed = encrypt_stream(&vec![3; SEGMENT_SIZE + 1]);
+        sealed[0] ^= 1;
+        let mut reader = DecryptReader::stream(&sealed[..], stream(), b"aad");
+        let mut buf = [0; 16];
+        assert!(reader.read(&mut buf).is_err());
+        assert!(reader.read(&mut buf).is_err());
+    }
+
+    // Puts junk in front of the ciphertext, like a container header.
+    fn after_header(ciphertext: &[u8]) -> Cursor<Vec<u8>> {
+        let mut cursor = Cursor::new([b"header".as_slice(), ciphertext].concat());
+        cursor.set_position(6);
+        cursor
+    }
+
+    fn read_at<R: Read + Seek>(
+        reader: &mut DecryptReader<R>,
+        from: SeekFrom,
+        len: usize,
+    ) -> Vec<u8> {
+        reader.seek(from).unwrap();
+        let mut output = Vec::new();
+        reader.take(len as u64).read_to_end(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn ctr_seek() {
+        let plaintext: Vec<u8> = (0..10_000).map(|i| (i % 253) as u8).collect();
+        let counter = [0xff; 16];
+        let sealed = ctr::apply(&cipher(), &counter, &plaintext);
+        let mut reader = DecryptReader::ctr(after_header(&sealed), cipher(), &counter);
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Current(0), 5),
+            &plaintext[..5]
+        );
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(4099), 20),
+            &plaintext[4099..4119]
+        );
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Current(-19), 3),
+            &plaintext[4100..4103]
+        );
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::End(-7), 100),
+            &plaintext[9993..]
+        );
+        assert_eq!(reader.stream_position().unwrap(), 10_000);
+        assert!(read_at(&mut reader, SeekFrom::Start(20_000), 1).is_empty());
+        assert!(reader.seek(SeekFrom::Current(-30_000)).is_err());
+        assert_eq!(read_at(&mut reader, SeekFrom::Start(0), 10_000), plaintext);
+    }
+
+    #[test]
+    fn stream_seek() {
+        let plaintext: Vec<u8> = (0..2 * SEGMENT_SIZE + 300).map(|i| (i / 3) as u8).collect();
+        let sealed = encrypt_stream(&plaintext);
+        let mut reader = DecryptReader::stream(after_header(&sealed), stream(), b"aad");
+        let middle = SEGMENT_SIZE + 17;
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(middle as u64), 40),
+            &plaintext[middle..middle + 40]
+        );
+        let boundary = 2 * SEGMENT_SIZE - 10;
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(boundary as u64), 20),
+            &plaintext[boundary..boundary + 20]
+        );
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::End(-300), 1000),
+            &plaintext[2 * SEGMENT_SIZE..]
+        );
+        assert_eq!(
+            reader.seek(SeekFrom::End(0)).unwrap(),
+            plaintext.len() as u64
+        );
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(3), 4),
+            &plaintext[3..7]
+        );
+    }
+
+    #[test]
+    fn stream_seek_authenticates_target_segment() {
+        let plaintext = vec![9; SEGMENT_SIZE + 50];
+        let mut sealed = encrypt_stream(&plaintext);
+        sealed[5] ^= 1;
+        let mut reader = DecryptReader::stream(after_header(&sealed), stream(), b"aad");
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(SEGMENT_SIZE as u64), 50),
+            &plaintext[SEGMENT_SIZE..]
+        );
+        let error = reader.seek(SeekFrom::Start(10)).unwrap_err();
+        assert_eq!(Error::from(error), Error::Authentication);
+
+        // Cutting the file inside the last segment still fails there.
+        let truncated = &encrypt_stream(&plaintext)[..SEALED_SEGMENT_SIZE + 20];
+        let mut reader = DecryptReader::stream(Cursor::new(truncated), stream(), b"aad");
+        assert!(reader.seek(SeekFrom::Start(SEGMENT_SIZE as u64)).is_err());
+    }
+
+    #[test]
+    fn threads_do_not_change_output() {
+        let mut state = CtrState::new(cipher(), &[0xfe; 16]);
+        let mut data: Vec<u8> = (0..20_000).map(|i| (i % 253) as u8).collect();
+        state.apply(&mut data[..5]);
+        state.apply_threaded(&mut data[5..19_999], 3);
+        state.apply(&mut data[19_999..]);
+        let expected: Vec<u8> = (0..20_000).map(|i| (i % 253) as u8).collect();
+        assert_eq!(data, ctr::apply(&cipher(), &[0xfe; 16], &expected));
+
+        let plaintext: Vec<u8> = (0..SEGMENT_SIZE + 3000).map(|i| (i % 241) as u8).collect();
+        let counter = [1; 16];
+        let threaded = write_all(
+            EncryptWriter::ctr(Vec::new(), cipher(), &counter).with_threads(2),
+            &plaintext,
+        );
+        assert_eq!(threaded, ctr::apply(&cipher(), &counter, &plaintext));
+        let reader = DecryptReader::ctr(&threaded[..], cipher(), &counter).with_threads(2);
+        assert_eq!(read_all(reader).unwrap(), plaintext);
+
+        let threaded = write_all(
+            EncryptWriter::ecb(Vec::new(), cipher(), Box::new(Pkcs7Padding)).with_threads(2),
+            &plaintext,
+        );
+        let sequential = write_all(
+            EncryptWriter::ecb(Vec::new(), cipher(), Box::new(Pkcs7Padding)),
+            &plaintext,
+        );
+        assert_eq!(threaded, sequential);
+        let reader =
+            DecryptReader::ecb(&threaded[..], cipher(), Box::new(Pkcs7Padding)).with_threads(2);
+        assert_eq!(read_all(reader).unwrap(), plaintext);
+
+        let threaded = write_all(
+            EncryptWriter::stream(Vec::new(), stream(), b"aad").with_threads(2),
+            &plaintext,
+        );
+        assert_eq!(threaded, encrypt_stream(&plaintext));
+        let reader = DecryptReader::stream(&threaded[..], stream(), b"aad").with_threads(2);
+        assert_eq!(read_all(reader).unwrap(), plaintext);
+        let mut reader =
+            DecryptReader::stream(after_header(&threaded), stream(), b"aad").with_threads(2);
+        assert_eq!(
+            read_at(&mut reader, SeekFrom::Start(SEGMENT_SIZE as u64 + 7), 100),
+            &plaintext[SEGMENT_SIZE + 7..SEGMENT_SIZE + 107]
+        );
+    }
+
+    #[test]
+    fn block_modes_cannot_seek() {
+        let mut reader = DecryptReader::cbc(
+            Cursor::new(vec![0; 32]),
+            cipher(),
+            &[0; 16],
+            Box::new(Pkcs7Padding),
+        );
+        let error = reader.seek(SeekFrom::Start(16)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,277 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{CtrState, Segments};
+use crate::aes::BlockCipher;
+use crate::error::Error;
+use crate::modes::gcm::TAG_LEN;
+use crate::modes::stream::{Stream, SEALED_SEGMENT_SIZE, SEGMENT_SIZE};
+use crate::modes::xor_block;
+use crate::padding::Padding;
+use crate::parallel::{self, BATCH_SIZE};
+
+const CHUNK_SIZE: usize = 4096;
+
+enum Mode {
+    Ctr(CtrState),
+    // ECB when there is no chaining value.
+    Block {
+        cipher: BlockCipher,
+        chain: Option<[u8; 16]>,
+        padding: Box<dyn Padding>,
+    },
+    Stream {
+        stream: Stream,
+        aad: Vec<u8>,
+        index: u32,
+    },
+}
+
+// Decrypts `inner` as it is read. STREAM segments are authenticated before
+// any of their plaintext is returned, but a later segment can still fail, so
+// callers must not act on the output until reading reaches the end.
+//
+// With a seekable `inner`, CTR and STREAM readers also implement Seek: CTR
+// jumps the counter, STREAM decrypts only the segment holding the target.
+// Offsets are plaintext offsets; `inner` may start partway into a file, as
+// after a container header.
+pub struct DecryptReader<R: Read> {
+    segments: Segments<R>,
+    mode: Mode,
+    output: Vec<u8>,
+    // Plaintext offset of output[0].
+    output_start: u64,
+    position: usize,
+    finished: bool,
+    failed: Option<Error>,
+    // Offset of the ciphertext in `inner`, found on the first seek.
+    start: Option<u64>,
+    threads: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn ctr(inner: R, cipher: BlockCipher, counter: &[u8; 16]) -> Self {
+        DecryptReader::new(inner, CHUNK_SIZE, Mode::Ctr(CtrState::new(cipher, counter)))
+    }
+
+    pub fn cbc(inner: R, cipher: BlockCipher, iv: &[u8; 16], padding: Box<dyn Padding>) -> Self {
+        let mode = Mode::Block {
+            cipher,
+            chain: Some(*iv),
+            padding,
+        };
+        DecryptReader::new(inner, CHUNK_SIZE, mode)
+    }
+
+    pub fn ecb(inner: R, cipher: BlockCipher, padding: Box<dyn Padding>) -> Self {
+        let mode = Mode::Block {
+            cipher,
+            chain: None,
+            padding,
+        };
+        DecryptReader::new(inner, CHUNK_SIZE, mode)
+    }
+
+    pub fn stream(inner: R, stream: Stream, aad: &[u8]) -> Self {
+        let mode = Mode::Stream {
+            stream,
+            aad: aad.to_vec(),
+            index: 0,
+        };
+        DecryptReader::new(inner, SEALED_SEGMENT_SIZE, mode)
+    }
+
+    fn new(inner: R, chunk_size: usize, mode: Mode) -> Self {
+        DecryptReader {
+            segments: Segments::new(inner, chunk_size),
+            mode,
+            output: Vec::new(),
+            output_start: 0,
+            position: 0,
+            finished: false,
+            failed: None,
+            start: None,
+            threads: 1,
+        }
+    }
+
+    // Reads `threads` times as much ahead and decrypts it in parallel; call
+    // before the first read. Output is identical for any thread count.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self.segments.size = match self.mode {
+            Mode::Stream { .. } => self.threads * SEALED_SEGMENT_SIZE,
+            _ if self.threads == 1 => CHUNK_SIZE,
+            _ => self.threads * BATCH_SIZE,
+        };
+        self
+    }
+
+    fn refill(&mut self) -> Result<(), Error> {
+        let last = self.segments.next()?;
+        let input = self.segments.current();
+        self.output_start += self.output.len() as u64;
+        let threads = self.threads;
+        self.output = match &mut self.mode {
+            Mode::Ctr(state) => {
+                let mut output = input.to_vec();
+                state.apply_threaded(&mut output, threads);
+                output
+            }
+            Mode::Block {
+                cipher,
+                chain,
+                padding,
+            } => {
+                if !input.len().is_multiple_of(16) {
+                    return Err(Error::InvalidLength(
+                        "ciphertext is not a whole number of blocks".to_string(),
+                    ));
+                }
+                let output = decrypt_blocks(cipher, chain, input, threads)?;
+                if last {
+                    padding.unpad(output)?
+                } else {
+                    output
+                }
+            }
+            Mode::Stream { stream, aad, index } => {
+                let output = parallel::open_segments(stream, *index, aad, input, last, threads)?;
+                let count = input.len().div_ceil(SEALED_SEGMENT_SIZE).max(1);
+                *index = u32::try_from(count)
+                    .ok()
+                    .and_then(|count| index.checked_add(count))
+                    .ok_or_else(|| Error::InvalidLength("too many STREAM segments".to_string()))?;
+                output
+            }
+        };
+        self.position = 0;
+        self.finished = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if let Some(error) = &self.failed {
+                return Err(error.clone().into());
+            }
+            if self.finished {
+                return Ok(0);
+            }
+            if let Err(error) = self.refill() {
+                // Later segments must not be served once one has failed.
+                self.failed = Some(error.clone());
+                return Err(error.into());
+            }
+        }
+        let available = &self.output[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptReader<R> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        let current = self.output_start + self.position as u64;
+        let target = match target {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.plaintext_len()?.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start"))?;
+        if target == current {
+            return Ok(current);
+        }
+
+        let start = self.start()?;
+        let plaintext_len = self.plaintext_len()?;
+        self.failed = None;
+        self.output.clear();
+        self.position = 0;
+        self.segments.reset();
+        if target >= plaintext_len {
+            self.output_start = target;
+            self.finished = true;
+            return Ok(target);
+        }
+        self.finished = false;
+        match &mut self.mode {
+            Mode::Ctr(state) => {
+                state.seek(target);
+                self.output_start = target;
+                self.segments.reader.seek(SeekFrom::Start(start + target))?;
+            }
+            Mode::Stream { index, .. } => {
+                let segment = target / SEGMENT_SIZE as u64;
+                *index = u32::try_from(segment)
+                    .map_err(|_| Error::InvalidLength("too many STREAM segments".to_string()))?;
+                self.output_start = segment * SEGMENT_SIZE as u64;
+                let offset = segment * SEALED_SEGMENT_SIZE as u64;
+                self.segments.reader.seek(SeekFrom::Start(start + offset))?;
+                if let Err(error) = self.refill() {
+                    self.failed = Some(error.clone());
+                    return Err(error.into());
+                }
+                self.position = (target - self.output_start) as usize;
+            }
+            Mode::Block { .. } => unreachable!("start() rejects block modes"),
+        }
+        Ok(target)
+    }
+}
+
+impl<R: Read + Seek> DecryptReader<R> {
+    fn start(&mut self) -> io::Result<u64> {
+        if let Mode::Block { .. } = self.mode {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "CBC and ECB readers cannot seek",
+            ));
+        }
+        if let Some(start) = self.start {
+            return Ok(start);
+        }
+        let position = self.segments.reader.stream_position()?;
+        let start = position - self.segments.consumed;
+        self.start = Some(start);
+        Ok(start)
+    }
+
+    fn plaintext_len(&mut self) -> io::Result<u64> {
+        let start = self.start()?;
+        let reader = &mut self.segments.reader;
+        let position = reader.stream_position()?;
+        let ciphertext_len = reader.seek(SeekFrom::End(0))? - start;
+        reader.seek(SeekFrom::Start(position))?;
+        Ok(match self.mode {
+            Mode::Stream { .. } => {
+                let segments = ciphertext_len.div_ceil(SEALED_SEGMENT_SIZE as u64).max(1);
+                ciphertext_len.saturating_sub(segments * TAG_LEN as u64)
+            }
+            _ => ciphertext_len,
+        })
+    }
+}
+
+fn decrypt_blocks(
+    cipher: &BlockCipher,
+    chain: &mut Option<[u8; 16]>,
+    data: &[u8],
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    let previous = match chain {
+        Some(previous) => previous,
+        None => return parallel::ecb_decrypt(cipher, data, threads),
+    };
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        output.extend_from_slice(&xor_block(previous, &cipher.decrypt_block(&block)));
+        *previous = block;
+    }
+    Ok(output)
+}
@@ -0,0 +1,189 @@
+use std::io::{self, Write};
+
+use super::CtrState;
+use crate::aes::BlockCipher;
+use crate::error::Error;
+use crate::modes::stream::{Stream, SEGMENT_SIZE};
+use crate::modes::xor_block;
+use crate::padding::Padding;
+use crate::parallel::{self, BATCH_SIZE};
+
+enum Mode {
+    Ctr(CtrState),
+    // ECB when there is no chaining value.
+    Block {
+        cipher: BlockCipher,
+        chain: Option<[u8; 16]>,
+        padding: Box<dyn Padding>,
+    },
+    Stream {
+        stream: Stream,
+        aad: Vec<u8>,
+        index: u32,
+    },
+}
+
+// Encrypts everything written to it into `inner`. Block modes and STREAM
+// hold back their final block or segment, so `finish` must be called to
+// pad and write it; dropping the writer loses that data. With more than
+// one thread CTR buffers as well, and CBC stays sequential.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    mode: Mode,
+    buffer: Vec<u8>,
+    threads: usize,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn ctr(inner: W, cipher: BlockCipher, counter: &[u8; 16]) -> Self {
+        EncryptWriter::new(inner, Mode::Ctr(CtrState::new(cipher, counter)))
+    }
+
+    pub fn cbc(inner: W, cipher: BlockCipher, iv: &[u8; 16], padding: Box<dyn Padding>) -> Self {
+        let mode = Mode::Block {
+            cipher,
+            chain: Some(*iv),
+            padding,
+        };
+        EncryptWriter::new(inner, mode)
+    }
+
+    pub fn ecb(inner: W, cipher: BlockCipher, padding: Box<dyn Padding>) -> Self {
+        let mode = Mode::Block {
+            cipher,
+            chain: None,
+            padding,
+        };
+        EncryptWriter::new(inner, mode)
+    }
+
+    pub fn stream(inner: W, stream: Stream, aad: &[u8]) -> Self {
+        let mode = Mode::Stream {
+            stream,
+            aad: aad.to_vec(),
+            index: 0,
+        };
+        EncryptWriter::new(inner, mode)
+    }
+
+    fn new(inner: W, mode: Mode) -> Self {
+        EncryptWriter {
+            inner,
+            mode,
+            buffer: Vec::new(),
+            threads: 1,
+        }
+    }
+
+    // Output is identical for any thread count.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Input held back before encrypting, so each thread gets a full batch.
+    fn batch(&self) -> usize {
+        match self.mode {
+            Mode::Stream { .. } => self.threads * SEGMENT_SIZE,
+            _ if self.threads == 1 => 0,
+            _ => self.threads * BATCH_SIZE,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        let threads = self.threads;
+        let output = match &mut self.mode {
+            Mode::Ctr(state) => {
+                state.apply_threaded(&mut self.buffer, threads);
+                std::mem::take(&mut self.buffer)
+            }
+            Mode::Block {
+                cipher,
+                chain,
+                padding,
+            } => {
+                let padded = padding.pad(&self.buffer)?;
+                if !padded.len().is_multiple_of(16) {
+                    return Err(Error::InvalidLength(
+                        "padding did not fill the final block".to_string(),
+                    ));
+                }
+                encrypt_blocks(cipher, chain, &padded, threads)?
+            }
+            Mode::Stream { stream, aad, index } => {
+                parallel::seal_segments(stream, *index, aad, &self.buffer, true, threads)?
+            }
+        };
+        self.inner.write_all(&output)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() <= self.batch() {
+            return Ok(data.len());
+        }
+        let threads = self.threads;
+        match &mut self.mode {
+            Mode::Ctr(state) => {
+                state.apply_threaded(&mut self.buffer, threads);
+                self.inner.write_all(&self.buffer)?;
+                self.buffer.clear();
+            }
+            Mode::Block { cipher, chain, .. } => {
+                // Keep at least one byte back for the padding in finish.
+                let ready = self.buffer.len().saturating_sub(1) / 16 * 16;
+                let output = encrypt_blocks(cipher, chain, &self.buffer[..ready], threads)?;
+                self.buffer.drain(..ready);
+                self.inner.write_all(&output)?;
+            }
+            Mode::Stream { stream, aad, index } => {
+                // A full segment is only sealed once more data follows it.
+                let count = (self.buffer.len() - 1) / SEGMENT_SIZE;
+                let ready = count * SEGMENT_SIZE;
+                let sealed = parallel::seal_segments(
+                    stream,
+                    *index,
+                    aad,
+                    &self.buffer[..ready],
+                    false,
+                    threads,
+                )?;
+                self.buffer.drain(..ready);
+                *index = u32::try_from(count)
+                    .ok()
+                    .and_then(|count| index.checked_add(count))
+                    .ok_or_else(|| Error::InvalidLength("too many STREAM segments".to_string()))?;
+                self.inner.write_all(&sealed)?;
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn encrypt_blocks(
+    cipher: &BlockCipher,
+    chain: &mut Option<[u8; 16]>,
+    data: &[u8],
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    let previous = match chain {
+        Some(previous) => previous,
+        None => return parallel::ecb_encrypt(cipher, data, threads),
+    };
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        *previous = cipher.encrypt_block(&xor_block(previous, &block));
+        output.extend_from_slice(previous);
+    }
+    Ok(output)
+}
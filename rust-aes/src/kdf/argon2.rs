@@ -0,0 +1,398 @@
+use crate::error::Error;
+use crate::hash::{Blake2b, Hash};
+
+// Argon2 version 1.3, RFC 9106, computed one lane after another.
+const VERSION: u32 = 0x13;
+const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: u32 = 4;
+
+type Block = [u64; BLOCK_WORDS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Argon2d,
+    Argon2i,
+    Argon2id,
+}
+
+impl Variant {
+    pub fn parse(name: &str) -> Result<Variant, String> {
+        match name {
+            "argon2d" => Ok(Variant::Argon2d),
+            "argon2i" => Ok(Variant::Argon2i),
+            "argon2id" => Ok(Variant::Argon2id),
+            _ => Err(format!("Invalid Argon2 variant: {}", name)),
+        }
+    }
+
+    fn code(&self) -> u32 {
+        match self {
+            Variant::Argon2d => 0,
+            Variant::Argon2i => 1,
+            Variant::Argon2id => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Params {
+    pub variant: Variant,
+    // In KiB, which is also the number of 1 KiB blocks.
+    pub memory: u32,
+    pub iterations: u32,
+    pub lanes: u32,
+}
+
+pub fn derive(
+    password: &[u8],
+    salt: &[u8],
+    params: &Params,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    hash(password, salt, &[], &[], params, length)
+}
+
+fn hash(
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    associated: &[u8],
+    params: &Params,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    let Params {
+        variant,
+        memory,
+        iterations,
+        lanes,
+    } = *params;
+    if !(1..1 << 24).contains(&lanes) || memory < 8 * lanes || iterations == 0 {
+        return Err(Error::InvalidInput(
+            "Argon2 needs 1 to 2^24-1 lanes, 8 KiB per lane and an iteration".to_string(),
+        ));
+    }
+    if length < 4 || u32::try_from(length).is_err() || salt.len() < 8 {
+        return Err(Error::InvalidInput(
+            "Argon2 needs a salt of at least 8 bytes and 4 bytes of output".to_string(),
+        ));
+    }
+
+    let mut h0 = Blake2b::new();
+    for value in [
+        lanes,
+        length as u32,
+        memory,
+        iterations,
+        VERSION,
+        variant.code(),
+    ] {
+        h0.update(&value.to_le_bytes());
+    }
+    for input in [password, salt, secret, associated] {
+        h0.update(&(input.len() as u32).to_le_bytes());
+        h0.update(input);
+    }
+    let h0 = h0.finalize();
+
+    let segment_len = memory / (SYNC_POINTS * lanes);
+    let lane_len = segment_len * SYNC_POINTS;
+    let mut memory = Memory {
+        blocks: vec![[0; BLOCK_WORDS]; (lane_len * lanes) as usize],
+        lane_len,
+        segment_len,
+        lanes,
+    };
+    for lane in 0..lanes {
+        for column in 0..2u32 {
+            let seed = [&h0[..], &column.to_le_bytes(), &lane.to_le_bytes()].concat();
+            let index = memory.index(lane, column);
+            memory.blocks[index] = from_bytes(&long_hash(&seed, 1024));
+        }
+    }
+    for pass in 0..iterations {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                let position = Position {
+                    pass,
+                    lane,
+                    slice,
+                    total_passes: iterations,
+                    variant,
+                };
+                memory.fill_segment(&position);
+            }
+        }
+    }
+
+    let mut last = memory.blocks[memory.index(0, lane_len - 1)];
+    for lane in 1..lanes {
+        let block = &memory.blocks[memory.index(lane, lane_len - 1)];
+        for (word, other) in last.iter_mut().zip(block) {
+            *word ^= other;
+        }
+    }
+    Ok(long_hash(&to_bytes(&last), length))
+}
+
+struct Memory {
+    blocks: Vec<Block>,
+    lane_len: u32,
+    segment_len: u32,
+    lanes: u32,
+}
+
+struct Position {
+    pass: u32,
+    lane: u32,
+    slice: u32,
+    total_passes: u32,
+    variant: Variant,
+}
+
+impl Memory {
+    fn index(&self, lane: u32, column: u32) -> usize {
+        (lane * self.lane_len + column) as usize
+    }
+
+    fn fill_segment(&mut self, position: &Position) {
+        let Position {
+            pass, lane, slice, ..
+        } = *position;
+        let data_independent = match position.variant {
+            Variant::Argon2d => false,
+            Variant::Argon2i => true,
+            Variant::Argon2id => pass == 0 && slice < 2,
+        };
+        let mut input = [0u64; BLOCK_WORDS];
+        let mut addresses = [0u64; BLOCK_WORDS];
+        if data_independent {
+            input[..6].copy_from_slice(&[
+                pass as u64,
+                lane as u64,
+                slice as u64,
+                self.blocks.len() as u64,
+                position.total_passes as u64,
+                position.variant.code() as u64,
+            ]);
+        }
+        let first = if pass == 0 && slice == 0 { 2 } else { 0 };
+        if data_independent && first == 2 {
+            next_addresses(&mut input, &mut addresses);
+        }
+
+        for index in first..self.segment_len {
+            let column = slice * self.segment_len + index;
+            let previous = if column == 0 {
+                self.index(lane, self.lane_len - 1)
+            } else {
+                self.index(lane, column - 1)
+            };
+            let pseudo_random = if data_independent {
+                if (index as usize).is_multiple_of(BLOCK_WORDS) {
+                    next_addresses(&mut input, &mut addresses);
+                }
+                addresses[index as usize % BLOCK_WORDS]
+            } else {
+                self.blocks[previous][0]
+            };
+            let reference = self.reference(position, index, pseudo_random);
+            let mut block = compress(&self.blocks[previous], &self.blocks[reference]);
+            let current = self.index(lane, column);
+            if pass > 0 {
+                for (word, old) in block.iter_mut().zip(&self.blocks[current]) {
+                    *word ^= old;
+                }
+            }
+            self.blocks[current] = block;
+        }
+    }
+
+    // RFC 9106 section 3.4.1.
+    fn reference(&self, position: &Position, index: u32, pseudo_random: u64) -> usize {
+        let j1 = pseudo_random & 0xffff_ffff;
+        let j2 = pseudo_random >> 32;
+        let first_slice = position.pass == 0 && position.slice == 0;
+        let lane = if first_slice {
+            position.lane
+        } else {
+            (j2 % self.lanes as u64) as u32
+        };
+        let same_lane = lane == position.lane;
+        let finished = if position.pass == 0 {
+            position.slice * self.segment_len
+        } else {
+            self.lane_len - self.segment_len
+        };
+        let area = if same_lane {
+            finished + index - 1
+        } else if index == 0 {
+            finished - 1
+        } else {
+            finished
+        } as u64;
+        let x = (j1 * j1) >> 32;
+        let relative = area - 1 - ((area * x) >> 32);
+        let start = if position.pass == 0 || position.slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            (position.slice + 1) * self.segment_len
+        };
+        let column = (start as u64 + relative) % self.lane_len as u64;
+        self.index(lane, column as u32)
+    }
+}
+
+// The next block of pseudo-random reference positions for Argon2i.
+fn next_addresses(input: &mut Block, addresses: &mut Block) {
+    input[6] += 1;
+    *addresses = compress(&[0; BLOCK_WORDS], &compress(&[0; BLOCK_WORDS], input));
+}
+
+// G: the BLAKE2b round function applied to the rows and then the columns of
+// the 8x8 matrix of 16-byte registers in X xor Y.
+fn compress(x: &Block, y: &Block) -> Block {
+    let mut r = [0u64; BLOCK_WORDS];
+    for (word, (a, b)) in r.iter_mut().zip(x.iter().zip(y)) {
+        *word = a ^ b;
+    }
+    let mut z = r;
+    for row in 0..8 {
+        let indices: [usize; 16] = std::array::from_fn(|i| 16 * row + i);
+        permute(&mut z, &indices);
+    }
+    for column in 0..8 {
+        let indices: [usize; 16] = std::array::from_fn(|i| 2 * column + 16 * (i / 2) + i % 2);
+        permute(&mut z, &indices);
+    }
+    for (word, original) in z.iter_mut().zip(&r) {
+        *word ^= original;
+    }
+    z
+}
+
+fn permute(block: &mut Block, indices: &[usize; 16]) {
+    let mut v: [u64; 16] = std::array::from_fn(|i| block[indices[i]]);
+    mix(&mut v, 0, 4, 8, 12);
+    mix(&mut v, 1, 5, 9, 13);
+    mix(&mut v, 2, 6, 10, 14);
+    mix(&mut v, 3, 7, 11, 15);
+    mix(&mut v, 0, 5, 10, 15);
+    mix(&mut v, 1, 6, 11, 12);
+    mix(&mut v, 2, 7, 8, 13);
+    mix(&mut v, 3, 4, 9, 14);
+    for (i, &index) in indices.iter().enumerate() {
+        block[index] = v[i];
+    }
+}
+
+// BLAKE2b's G with the multiplications that make it BlaMka.
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+    let multiply = |x: u64, y: u64| ((x & 0xffff_ffff) * (y & 0xffff_ffff)).wrapping_mul(2);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(multiply(v[a], v[b]));
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]).wrapping_add(multiply(v[c], v[d]));
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(multiply(v[a], v[b]));
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]).wrapping_add(multiply(v[c], v[d]));
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+// H', the variable-length hash of RFC 9106 section 3.3.
+fn long_hash(input: &[u8], length: usize) -> Vec<u8> {
+    let prefixed = [&(length as u32).to_le_bytes()[..], input].concat();
+    if length <= 64 {
+        let mut hash = Blake2b::with_len(length);
+        hash.update(&prefixed);
+        return hash.finalize();
+    }
+    let mut output = Vec::with_capacity(length);
+    let mut v = Blake2b::digest(&prefixed);
+    loop {
+        output.extend_from_slice(&v[..32]);
+        if length - output.len() <= 64 {
+            break;
+        }
+        v = Blake2b::digest(&v);
+    }
+    let mut hash = Blake2b::with_len(length - output.len());
+    hash.update(&v);
+    output.extend(hash.finalize());
+    output
+}
+
+fn from_bytes(bytes: &[u8]) -> Block {
+    std::array::from_fn(|i| u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap()))
+}
+
+fn to_bytes(block: &Block) -> Vec<u8> {
+    block.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+
+    // RFC 9106 section 5
+    fn rfc9106(variant: Variant) -> String {
+        let params = Params {
+            variant,
+            memory: 32,
+            iterations: 3,
+            lanes: 4,
+        };
+        let tag = hash(&[1; 32], &[2; 16], &[3; 8], &[4; 12], &params, 32).unwrap();
+        to_hex(&tag)
+    }
+
+    #[test]
+    fn rfc9106_vectors() {
+        assert_eq!(
+            rfc9106(Variant::Argon2d),
+            "512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb"
+        );
+        assert_eq!(
+            rfc9106(Variant::Argon2i),
+            "c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8"
+        );
+        assert_eq!(
+            rfc9106(Variant::Argon2id),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"
+        );
+    }
+
+    // Checked against the Python cryptography package's Argon2id.
+    #[test]
+    fn long_output_and_one_lane() {
+        let params = Params {
+            variant: Variant::Argon2id,
+            memory: 64,
+            iterations: 2,
+            lanes: 1,
+        };
+        let output = derive(b"password", b"somesalt", &params, 100).unwrap();
+        assert_eq!(
+            to_hex(&output),
+            "7712f6cfaea89a90b11559e10e234f92f892db147d4c3b6e628a51836a20dcd0\
+             7537028d562157088d11c966eced97430f53e747196cd7d99ddfb21b159e05ae\
+             131bd627e4a4b3452d5800c3351986221ec89db7698fcf4f91a1f5f4b73ef5e6\
+             92c2fbc1"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        let params = Params {
+            variant: Variant::Argon2i,
+            memory: 16,
+            iterations: 1,
+            lanes: 4,
+        };
+        assert!(derive(b"pw", b"saltsalt", &params, 32).is_err());
+        let params = Params { lanes: 1, ..params };
+        assert!(derive(b"pw", b"short", &params, 32).is_err());
+        assert!(derive(b"pw", b"saltsalt", &params, 3).is_err());
+        assert!(derive(b"pw", b"saltsalt", &params, 4).is_ok());
+    }
+}
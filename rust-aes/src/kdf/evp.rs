@@ -0,0 +1,51 @@
+use crate::hash::Hash;
+
+// OpenSSL's EVP_BytesToKey: D_i = H^count(D_{i-1} || password || salt),
+// concatenated until there is enough for the key and then the IV. Only for
+// reading legacy files; it is far too cheap to resist guessing.
+pub fn bytes_to_key<H: Hash>(
+    password: &[u8],
+    salt: &[u8],
+    count: u32,
+    key_len: usize,
+    iv_len: usize,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut output = Vec::with_capacity(key_len + iv_len);
+    let mut previous = Vec::new();
+    while output.len() < key_len + iv_len {
+        let mut hash = H::new();
+        hash.update(&previous);
+        hash.update(password);
+        hash.update(salt);
+        previous = hash.finalize();
+        for _ in 1..count {
+            previous = H::digest(&previous);
+        }
+        output.extend_from_slice(&previous);
+    }
+    output.truncate(key_len + iv_len);
+    let iv = output.split_off(key_len);
+    (output, iv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::hash::{Md5, Sha256};
+
+    // Checked against `openssl enc -P -S 0102030405060708 -pass pass:password`.
+    #[test]
+    fn openssl_enc() {
+        let salt = [1, 2, 3, 4, 5, 6, 7, 8];
+        let (key, iv) = bytes_to_key::<Md5>(b"password", &salt, 1, 32, 16);
+        assert_eq!(
+            to_hex(&key),
+            "e7b0971e52ca5cc8d0539fb3412f6316f7ba2e6ee293d9f3457b99436b51ce02"
+        );
+        assert_eq!(to_hex(&iv), "8d450e2ed75a84a923d4eac9fe49226b");
+        let (key, iv) = bytes_to_key::<Sha256>(b"password", &salt, 1, 16, 16);
+        assert_eq!(to_hex(&key), "2435177f1410536baad2acc155c0f947");
+        assert_eq!(to_hex(&iv), "83d58384573cb0f72157443606285d3f");
+    }
+}
@@ -0,0 +1,118 @@
+use crate::hash::{Hash, Hmac};
+
+pub fn extract<H: Hash>(salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let salt = if salt.is_empty() {
+        vec![0; H::OUTPUT_SIZE]
+    } else {
+        salt.to_vec()
+    };
+    Hmac::<H>::mac(&salt, ikm)
+}
+
+pub fn expand<H: Hash>(prk: &[u8], info: &[u8], length: usize) -> Result<Vec<u8>, String> {
+    if length > 255 * H::OUTPUT_SIZE {
+        return Err(format!("HKDF output length too large: {}", length));
+    }
+    let mut okm = Vec::with_capacity(length);
+    let mut previous = Vec::new();
+    let mut counter = 1u8;
+    while okm.len() < length {
+        let mut hmac = Hmac::<H>::new(prk);
+        hmac.update(&previous);
+        hmac.update(info);
+        hmac.update(&[counter]);
+        previous = hmac.finalize();
+        okm.extend_from_slice(&previous);
+        counter = counter.wrapping_add(1);
+    }
+    okm.truncate(length);
+    Ok(okm)
+}
+
+pub fn derive<H: Hash>(
+    salt: &[u8],
+    ikm: &[u8],
+    info: &[u8],
+    length: usize,
+) -> Result<Vec<u8>, String> {
+    expand::<H>(&extract::<H>(salt, ikm), info, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+    use crate::hash::Sha256;
+
+    fn range(start: u8, end: u8) -> Vec<u8> {
+        (start..=end).collect()
+    }
+
+    #[test]
+    fn rfc5869_case1() {
+        let ikm = vec![0x0b; 22];
+        let salt = range(0x00, 0x0c);
+        let info = range(0xf0, 0xf9);
+        let prk = extract::<Sha256>(&salt, &ikm);
+        assert_eq!(
+            to_hex(&prk),
+            "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"
+        );
+        let okm = expand::<Sha256>(&prk, &info, 42).unwrap();
+        assert_eq!(
+            okm,
+            from_hex(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+                 34007208d5b887185865"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rfc5869_case2() {
+        let ikm = range(0x00, 0x4f);
+        let salt = range(0x60, 0xaf);
+        let info = range(0xb0, 0xff);
+        let prk = extract::<Sha256>(&salt, &ikm);
+        assert_eq!(
+            to_hex(&prk),
+            "06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244"
+        );
+        let okm = expand::<Sha256>(&prk, &info, 82).unwrap();
+        assert_eq!(
+            okm,
+            from_hex(
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c\
+                 59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71\
+                 cc30c58179ec3e87c14c01d5c1f3434f1d87"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rfc5869_case3() {
+        let ikm = vec![0x0b; 22];
+        let prk = extract::<Sha256>(&[], &ikm);
+        assert_eq!(
+            to_hex(&prk),
+            "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04"
+        );
+        let okm = derive::<Sha256>(&[], &ikm, &[], 42).unwrap();
+        assert_eq!(
+            okm,
+            from_hex(
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d\
+                 9d201395faa4b61a96c8"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn length_limit() {
+        assert!(expand::<Sha256>(&[0; 32], &[], 255 * 32).is_ok());
+        assert!(expand::<Sha256>(&[0; 32], &[], 255 * 32 + 1).is_err());
+    }
+}
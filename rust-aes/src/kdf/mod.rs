@@ -0,0 +1,4 @@
+pub mod argon2;
+pub mod evp;
+pub mod hkdf;
+pub mod pbkdf2;
@@ -0,0 +1,65 @@
+use crate::hash::{Hash, Hmac};
+
+// PBKDF2, RFC 8018 section 5.2
+pub fn derive<H: Hash>(password: &[u8], salt: &[u8], iterations: u32, length: usize) -> Vec<u8> {
+    let keyed = Hmac::<H>::new(password);
+    let mut output = Vec::with_capacity(length);
+    let mut block_index = 1u32;
+    while output.len() < length {
+        let mut hmac = keyed.clone();
+        hmac.update(salt);
+        hmac.update(&block_index.to_be_bytes());
+        let mut u = hmac.finalize();
+        let mut block = u.clone();
+        for _ in 1..iterations {
+            let mut hmac = keyed.clone();
+            hmac.update(&u);
+            u = hmac.finalize();
+            for (b, x) in block.iter_mut().zip(&u) {
+                *b ^= x;
+            }
+        }
+        output.extend_from_slice(&block);
+        block_index += 1;
+    }
+    output.truncate(length);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::hash::Sha256;
+
+    // RFC 7914 section 11
+    #[test]
+    fn rfc7914() {
+        assert_eq!(
+            to_hex(&derive::<Sha256>(b"passwd", b"salt", 1, 64)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            to_hex(&derive::<Sha256>(b"password", b"salt", 2, 32)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+        assert_eq!(
+            to_hex(&derive::<Sha256>(b"password", b"salt", 4096, 32)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+        assert_eq!(
+            to_hex(&derive::<Sha256>(
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                40
+            )),
+            "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"
+        );
+    }
+}
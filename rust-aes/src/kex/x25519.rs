@@ -0,0 +1,340 @@
+use crate::error::Error;
+use crate::rng::RngSource;
+
+pub const KEY_LEN: usize = 32;
+pub const BASE_POINT: [u8; KEY_LEN] = {
+    let mut point = [0; KEY_LEN];
+    point[0] = 9;
+    point
+};
+
+// X25519 (RFC 7748) on the Montgomery form of Curve25519. Field elements are
+// five 51-bit limbs, little-endian, reduced lazily modulo 2^255 - 19.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const MASK: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    // The top bit is ignored, as RFC 7748 requires for u-coordinates.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |offset: usize| {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().0;
+        // Subtract p once if h >= p: q is 1 exactly when h + 19 overflows
+        // 2^255.
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut bytes = [0; 32];
+        let mut accumulator: u128 = 0;
+        let mut bits = 0;
+        let mut index = 0;
+        for limb in h {
+            accumulator |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                bytes[index] = accumulator as u8;
+                accumulator >>= 8;
+                bits -= 8;
+                index += 1;
+            }
+        }
+        // The last 7 bits.
+        bytes[31] = accumulator as u8;
+        bytes
+    }
+
+    fn carry(self) -> Fe {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        h[1] += h[0] >> 51;
+        h[0] &= MASK;
+        Fe(h)
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        let mut h = self.0;
+        for (limb, other) in h.iter_mut().zip(other.0) {
+            *limb += other;
+        }
+        Fe(h).carry()
+    }
+
+    // Adds 4p first so no limb goes negative.
+    fn sub(self, other: Fe) -> Fe {
+        const FOUR_P: [u64; 5] = [
+            0x1f_ffff_ffff_ffb4,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+            0x1f_ffff_ffff_fffc,
+        ];
+        let mut h = self.0;
+        for i in 0..5 {
+            h[i] = h[i] + FOUR_P[i] - other.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        let a = self.0.map(|limb| limb as u128);
+        let b = other.0.map(|limb| limb as u128);
+        // Limbs that wrap past 2^255 come back multiplied by 19.
+        let b19 = b.map(|limb| limb * 19);
+        let t = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        reduce_wide(t)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn mul_small(self, factor: u64) -> Fe {
+        reduce_wide(self.0.map(|limb| limb as u128 * factor as u128))
+    }
+
+    // self^(p - 2), the inverse by Fermat's little theorem.
+    fn invert(self) -> Fe {
+        // p - 2 = 2^255 - 21: bits 254..5 are set, then 01011.
+        let mut result = Fe::ONE;
+        for bit in (0..255).rev() {
+            result = result.square();
+            if bit >= 5 || (0b01011 >> bit) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+}
+
+fn reduce_wide(t: [u128; 5]) -> Fe {
+    let mut h = [0u64; 5];
+    let mut carry = 0u128;
+    for i in 0..5 {
+        let value = t[i] + carry;
+        h[i] = value as u64 & MASK;
+        carry = value >> 51;
+    }
+    let wrapped = h[0] as u128 + carry * 19;
+    h[0] = wrapped as u64 & MASK;
+    h[1] += (wrapped >> 51) as u64;
+    Fe(h)
+}
+
+// Swaps a and b when `swap` is 1 without branching on it.
+fn conditional_swap(swap: u64, a: &mut Fe, b: &mut Fe) {
+    let mask = 0u64.wrapping_sub(swap);
+    for i in 0..5 {
+        let difference = mask & (a.0[i] ^ b.0[i]);
+        a.0[i] ^= difference;
+        b.0[i] ^= difference;
+    }
+}
+
+// The X25519 function: clamps `scalar` and multiplies the point with
+// u-coordinate `u` by it using the Montgomery ladder.
+pub fn x25519(scalar: &[u8; KEY_LEN], u: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = Fe::from_bytes(u);
+    let (mut x2, mut z2) = (Fe::ONE, Fe::ZERO);
+    let (mut x3, mut z3) = (x1, Fe::ONE);
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        conditional_swap(swap, &mut x2, &mut x3);
+        conditional_swap(swap, &mut z2, &mut z3);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    conditional_swap(swap, &mut x2, &mut x3);
+    conditional_swap(swap, &mut z2, &mut z3);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(pub [u8; KEY_LEN]);
+
+impl PublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(PublicKey(key_bytes(bytes)?))
+    }
+}
+
+#[derive(Clone)]
+pub struct SecretKey([u8; KEY_LEN]);
+
+impl SecretKey {
+    pub fn generate(rng: &mut dyn RngSource) -> Result<Self, Error> {
+        SecretKey::from_bytes(&rng.random_vec(KEY_LEN)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(SecretKey(key_bytes(bytes)?))
+    }
+
+    pub fn to_bytes(&self) -> [u8; KEY_LEN] {
+        self.0
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519(&self.0, &BASE_POINT))
+    }
+
+    // The shared secret with `public`. Low-order points give an all-zero
+    // secret, which is rejected so a peer cannot force a known key.
+    pub fn agree(&self, public: &PublicKey) -> Result<[u8; KEY_LEN], Error> {
+        let shared = x25519(&self.0, &public.0);
+        if shared.iter().fold(0, |acc, byte| acc | byte) == 0 {
+            return Err(Error::InvalidInput(
+                "X25519 public key has low order".to_string(),
+            ));
+        }
+        Ok(shared)
+    }
+}
+
+fn key_bytes(bytes: &[u8]) -> Result<[u8; KEY_LEN], Error> {
+    bytes.try_into().map_err(|_| {
+        Error::InvalidLength(format!(
+            "X25519 keys are {} bytes, got {}",
+            KEY_LEN,
+            bytes.len()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    fn bytes(hex: &str) -> [u8; KEY_LEN] {
+        from_hex(hex).unwrap().try_into().unwrap()
+    }
+
+    // RFC 7748 5.2
+    #[test]
+    fn rfc7748_vectors() {
+        let output = x25519(
+            &bytes("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+            &bytes("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c"),
+        );
+        assert_eq!(
+            to_hex(&output),
+            "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"
+        );
+        let output = x25519(
+            &bytes("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d"),
+            &bytes("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493"),
+        );
+        assert_eq!(
+            to_hex(&output),
+            "95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957"
+        );
+    }
+
+    // RFC 7748 5.2, iterated 1 and 1000 times
+    #[test]
+    fn rfc7748_iterated() {
+        let mut k = BASE_POINT;
+        let mut u = BASE_POINT;
+        for iteration in 1..=1000 {
+            let result = x25519(&k, &u);
+            u = k;
+            k = result;
+            if iteration == 1 {
+                assert_eq!(
+                    to_hex(&k),
+                    "422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079"
+                );
+            }
+        }
+        assert_eq!(
+            to_hex(&k),
+            "684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"
+        );
+    }
+
+    // RFC 7748 6.1
+    #[test]
+    fn diffie_hellman() {
+        let alice = SecretKey::from_bytes(
+            &from_hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a").unwrap(),
+        )
+        .unwrap();
+        let bob = SecretKey::from_bytes(
+            &from_hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            to_hex(&alice.public_key().0),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"
+        );
+        assert_eq!(
+            to_hex(&bob.public_key().0),
+            "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"
+        );
+        let shared = alice.agree(&bob.public_key()).unwrap();
+        assert_eq!(shared, bob.agree(&alice.public_key()).unwrap());
+        assert_eq!(
+            to_hex(&shared),
+            "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"
+        );
+        assert!(alice.agree(&PublicKey([0; KEY_LEN])).is_err());
+        assert!(PublicKey::from_bytes(&[0; 31]).is_err());
+    }
+}
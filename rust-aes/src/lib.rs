@@ -2,6 +2,21 @@ extern crate cfg_if;
 extern crate wasm_bindgen;
 
 pub mod aes;
+pub mod armor;
+pub mod container;
+pub mod encoding;
+pub mod error;
+pub mod formats;
+pub mod fpe;
+pub mod hash;
+pub mod io;
+pub mod kdf;
+pub mod kex;
+pub mod modes;
+pub mod padding;
+pub mod parallel;
+pub mod protocols;
+pub mod rng;
 
 use aes::AesSize;
 use cfg_if::cfg_if;
@@ -17,25 +32,107 @@ cfg_if! {
 
 #[wasm_bindgen]
 pub fn encrypt(password: &str, plaintext: &str, size_string: &str) -> Vec<u8> {
-    let size = AesSize::parse(&size_string).expect("Invalid AES size");
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
     let cipher = aes::Key::from_password(password, size);
-    let ciphertext = aes::encrypt(&cipher, plaintext).expect("Failed to encrypt");
-    ciphertext
+    aes::encrypt_authenticated(&cipher, plaintext.as_bytes(), &mut rng::OsRng)
+        .map_err(|e| e.to_string())
+        .expect("Failed to encrypt")
 }
 
 #[wasm_bindgen]
 pub fn decrypt(password: &str, ciphertext: &[u8], size_string: &str) -> String {
-    let size = AesSize::parse(&size_string).expect("Invalid AES size");
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
+    let cipher = aes::Key::from_password(password, size);
+    aes::decrypt(&cipher, &binary(ciphertext))
+        .map_err(|e| e.to_string())
+        .expect("Failed to decrypt")
+}
+
+#[wasm_bindgen]
+pub fn encrypt_with_padding(
+    password: &str,
+    plaintext: &str,
+    size_string: &str,
+    padding_string: &str,
+) -> Vec<u8> {
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
+    let mut padding =
+        padding::parse(padding_string, Box::new(rng::OsRng)).expect("Invalid padding");
+    let cipher = aes::Key::from_password(password, size);
+    aes::encrypt_checked(&cipher, plaintext.as_bytes(), padding.as_mut())
+        .map_err(|e| e.to_string())
+        .expect("Failed to encrypt")
+}
+
+#[wasm_bindgen]
+pub fn decrypt_with_padding(
+    password: &str,
+    ciphertext: &[u8],
+    size_string: &str,
+    padding_string: &str,
+) -> String {
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
+    let padding =
+        padding::parse(padding_string, Box::new(rng::OsRng)).expect("Invalid padding");
+    let cipher = aes::Key::from_password(password, size);
+    let ciphertext = binary(ciphertext);
+    let plaintext = if aes::is_sealed(&ciphertext) {
+        aes::decrypt_authenticated(&cipher, &ciphertext)
+    } else {
+        aes::decrypt_with_padding(&cipher, &ciphertext, padding.as_ref())
+    };
+    let plaintext = plaintext
+        .map_err(|e| e.to_string())
+        .expect("Failed to decrypt");
+    String::from_utf8(plaintext).expect("Decrypted data is not valid UTF-8")
+}
+
+// For AES-192/256 data written by releases before the key schedule fix,
+// including the output of earlier `encrypt` calls.
+#[wasm_bindgen]
+pub fn decrypt_legacy_schedule(
+    password: &str,
+    ciphertext: &[u8],
+    size_string: &str,
+    padding_string: &str,
+) -> String {
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
+    let padding =
+        padding::parse(padding_string, Box::new(rng::OsRng)).expect("Invalid padding");
     let cipher = aes::Key::from_password(password, size);
-    let plaintext = aes::decrypt(&cipher, ciphertext)
+    let plaintext = aes::decrypt_legacy_schedule(&cipher, &binary(ciphertext), padding.as_ref())
         .map_err(|e| e.to_string())
         .expect("Failed to decrypt");
-    plaintext
+    String::from_utf8(plaintext).expect("Decrypted data is not valid UTF-8")
+}
+
+#[wasm_bindgen]
+pub fn armor(data: &[u8]) -> String {
+    armor::encode(armor::LABEL, data)
+}
+
+#[wasm_bindgen]
+pub fn dearmor(text: &str) -> Vec<u8> {
+    armor::decode(text)
+        .map_err(|e| e.to_string())
+        .expect("Invalid armor")
+}
+
+// The decrypt functions take the output of `armor` as well as raw bytes.
+fn binary(ciphertext: &[u8]) -> Vec<u8> {
+    if !armor::is_armored(ciphertext) {
+        return ciphertext.to_vec();
+    }
+    std::str::from_utf8(ciphertext)
+        .map_err(|_| error::Error::InvalidUtf8)
+        .and_then(armor::decode)
+        .map_err(|e| e.to_string())
+        .expect("Invalid armor")
 }
 
 #[wasm_bindgen]
 pub fn get_cipher(password: &str, size_string: &str) -> String {
-    let size = AesSize::parse(&size_string).expect("Invalid AES size");
+    let size = AesSize::parse(size_string).expect("Invalid AES size");
     let cipher = aes::Key::from_password(password, size);
     cipher.to_string()
 }
@@ -54,6 +151,74 @@ mod tests {
         assert_eq!(plaintext, decrypted);
     }
 
+    #[test]
+    fn test_padding() {
+        for padding in ["zero", "pkcs7", "ansix923", "iso10126", "iso7816"] {
+            let ciphertext = encrypt_with_padding("passwrd", "plaintext", "128", padding);
+            let decrypted = decrypt_with_padding("passwrd", &ciphertext, "128", padding);
+            assert_eq!(decrypted, "plaintext");
+        }
+        let legacy = encrypt("passwrd", "plaintext", "128");
+        assert_eq!(
+            decrypt_with_padding("passwrd", &legacy, "128", "zero"),
+            "plaintext"
+        );
+    }
+
+    #[test]
+    fn legacy_schedule() {
+        let key = aes::Key::from_password("passwrd", AesSize::S256);
+        let mut block = [0; 16];
+        block[..9].copy_from_slice(b"plaintext");
+        let ciphertext = aes::BlockCipher::with_legacy_schedule(&key).encrypt_block(&block);
+        assert_eq!(
+            decrypt_legacy_schedule("passwrd", &ciphertext, "256", "zero"),
+            "plaintext"
+        );
+    }
+
+    #[test]
+    fn armored() {
+        let ciphertext = encrypt("passwrd", "plaintext", "128");
+        let armored = armor(&ciphertext);
+        assert!(armored.starts_with("-----BEGIN AES MESSAGE-----\n"));
+        assert_eq!(decrypt("passwrd", &dearmor(&armored), "128"), "plaintext");
+        assert_eq!(decrypt("passwrd", armored.as_bytes(), "128"), "plaintext");
+
+        let ciphertext = encrypt_with_padding("passwrd", "plaintext", "128", "pkcs7");
+        let armored = armor(&ciphertext);
+        assert_eq!(
+            decrypt_with_padding("passwrd", armored.as_bytes(), "128", "pkcs7"),
+            "plaintext"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid armor")]
+    fn corrupted_armor() {
+        let armored = armor(&encrypt("passwrd", "plaintext", "128"));
+        let body = armored.find('\n').unwrap() + 1;
+        let flipped = if armored.as_bytes()[body] == b'A' { "B" } else { "A" };
+        let corrupted = [&armored[..body], flipped, &armored[body + 1..]].concat();
+        decrypt("passwrd", corrupted.as_bytes(), "128");
+    }
+
+    #[test]
+    #[should_panic(expected = "Authentication failed")]
+    fn tampered() {
+        let mut ciphertext = encrypt("passwrd", "plaintext", "128");
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+        decrypt("passwrd", &ciphertext, "128");
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong password or key")]
+    fn wrong_password() {
+        let ciphertext = encrypt("passwrd", "plaintext", "128");
+        decrypt("password", &ciphertext, "128");
+    }
+
     #[test]
     fn empty() {
         let password = "";
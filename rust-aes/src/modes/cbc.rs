@@ -0,0 +1,81 @@
+use super::xor_block;
+use crate::aes::BlockCipher;
+use crate::error::Error;
+
+pub fn encrypt(cipher: &BlockCipher, iv: &[u8; 16], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    check_length(plaintext.len())?;
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+    let mut previous = *iv;
+    for chunk in plaintext.chunks(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        previous = cipher.encrypt_block(&xor_block(&block, &previous));
+        ciphertext.extend_from_slice(&previous);
+    }
+    Ok(ciphertext)
+}
+
+pub fn decrypt(cipher: &BlockCipher, iv: &[u8; 16], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    check_length(ciphertext.len())?;
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let mut previous = *iv;
+    for chunk in ciphertext.chunks(16) {
+        let mut block = [0; 16];
+        block.copy_from_slice(chunk);
+        plaintext.extend_from_slice(&xor_block(&cipher.decrypt_block(&block), &previous));
+        previous = block;
+    }
+    Ok(plaintext)
+}
+
+fn check_length(length: usize) -> Result<(), Error> {
+    if !length.is_multiple_of(16) {
+        return Err(Error::InvalidLength(format!(
+            "CBC input must be a multiple of 16 bytes, got {}",
+            length
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::encoding::from_hex;
+
+    // NIST SP 800-38A F.2.1 / F.2.2
+    #[test]
+    fn sp800_38a_cbc_aes128() {
+        let key = Key::from_bytes(&from_hex("2b7e151628aed2a6abf7158809cf4f3c").unwrap());
+        let cipher = BlockCipher::new(&key);
+        let mut iv = [0; 16];
+        iv.copy_from_slice(&from_hex("000102030405060708090a0b0c0d0e0f").unwrap());
+        let plaintext = from_hex(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        )
+        .unwrap();
+        let expected = from_hex(
+            "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2\
+             73bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7",
+        )
+        .unwrap();
+        let ciphertext = encrypt(&cipher, &iv, &plaintext).unwrap();
+        assert_eq!(ciphertext, expected);
+        assert_eq!(decrypt(&cipher, &iv, &ciphertext).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn unaligned_input() {
+        let cipher = BlockCipher::new(&Key::from_bytes(&[0; 16]));
+        assert!(matches!(
+            encrypt(&cipher, &[0; 16], &[0; 15]),
+            Err(Error::InvalidLength(_))
+        ));
+        assert!(matches!(
+            decrypt(&cipher, &[0; 16], &[0; 17]),
+            Err(Error::InvalidLength(_))
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::constant_time_eq;
+use crate::modes::xor_block;
+
+// CCM, NIST SP 800-38C and RFC 3610. Sealed output is ciphertext || tag. The
+// nonce is 7 to 13 bytes; the rest of the counter block holds the length,
+// so shorter nonces allow longer messages.
+pub const MIN_NONCE_LEN: usize = 7;
+pub const MAX_NONCE_LEN: usize = 13;
+
+pub struct Ccm {
+    cipher: BlockCipher,
+    tag_len: usize,
+}
+
+impl Ccm {
+    // Tags are 4 to 16 bytes, in steps of two.
+    pub fn new(key: &Key, tag_len: usize) -> Result<Self, Error> {
+        if !(4..=16).contains(&tag_len) || !tag_len.is_multiple_of(2) {
+            return Err(Error::InvalidLength(format!(
+                "CCM tags are 4 to 16 bytes and even, got {}",
+                tag_len
+            )));
+        }
+        Ok(Ccm {
+            cipher: BlockCipher::new(key),
+            tag_len,
+        })
+    }
+
+    pub fn tag_len(&self) -> usize {
+        self.tag_len
+    }
+
+    pub fn seal(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        check_lengths(nonce, plaintext.len())?;
+        let tag = self.mac(nonce, aad, plaintext);
+        let mut sealed = self.ctr(nonce, plaintext);
+        sealed.extend(self.mask(nonce, &tag));
+        Ok(sealed)
+    }
+
+    pub fn open(&self, nonce: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < self.tag_len {
+            return Err(Error::InvalidLength(format!(
+                "sealed data must be at least {} bytes, got {}",
+                self.tag_len,
+                sealed.len()
+            )));
+        }
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - self.tag_len);
+        check_lengths(nonce, ciphertext.len())?;
+        let plaintext = self.ctr(nonce, ciphertext);
+        let expected = self.mask(nonce, &self.mac(nonce, aad, &plaintext));
+        if !constant_time_eq(&expected, tag) {
+            return Err(Error::Authentication);
+        }
+        Ok(plaintext)
+    }
+
+    // CBC-MAC over B0, the length-prefixed AAD and the plaintext, each
+    // zero-padded to a block boundary.
+    fn mac(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> [u8; 16] {
+        let width = 15 - nonce.len();
+        let mut b0 = [0; 16];
+        b0[0] = ((!aad.is_empty() as u8) << 6)
+            | (((self.tag_len - 2) / 2) as u8) << 3
+            | (width - 1) as u8;
+        b0[1..=nonce.len()].copy_from_slice(nonce);
+        b0[16 - width..].copy_from_slice(&(plaintext.len() as u128).to_be_bytes()[16 - width..]);
+        let mut mac = self.cipher.encrypt_block(&b0);
+
+        let mut header = Vec::new();
+        if !aad.is_empty() {
+            if aad.len() < 0xff00 {
+                header.extend_from_slice(&(aad.len() as u16).to_be_bytes());
+            } else if u32::try_from(aad.len()).is_ok() {
+                header.extend_from_slice(&[0xff, 0xfe]);
+                header.extend_from_slice(&(aad.len() as u32).to_be_bytes());
+            } else {
+                header.extend_from_slice(&[0xff, 0xff]);
+                header.extend_from_slice(&(aad.len() as u64).to_be_bytes());
+            }
+            header.extend_from_slice(aad);
+        }
+        for data in [&header[..], plaintext] {
+            for chunk in data.chunks(16) {
+                let mut block = [0; 16];
+                block[..chunk.len()].copy_from_slice(chunk);
+                mac = self.cipher.encrypt_block(&xor_block(&mac, &block));
+            }
+        }
+        mac
+    }
+
+    // Counter 0 masks the tag; the payload starts at counter 1.
+    fn ctr(&self, nonce: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len());
+        for (index, chunk) in data.chunks(16).enumerate() {
+            let keystream = self
+                .cipher
+                .encrypt_block(&counter_block(nonce, index as u64 + 1));
+            output.extend(chunk.iter().zip(keystream.iter()).map(|(a, b)| a ^ b));
+        }
+        output
+    }
+
+    fn mask(&self, nonce: &[u8], tag: &[u8; 16]) -> Vec<u8> {
+        let s0 = self.cipher.encrypt_block(&counter_block(nonce, 0));
+        tag.iter()
+            .zip(s0.iter())
+            .take(self.tag_len)
+            .map(|(a, b)| a ^ b)
+            .collect()
+    }
+}
+
+fn counter_block(nonce: &[u8], counter: u64) -> [u8; 16] {
+    let width = 15 - nonce.len();
+    let mut block = [0; 16];
+    block[0] = (width - 1) as u8;
+    block[1..=nonce.len()].copy_from_slice(nonce);
+    block[16 - width..].copy_from_slice(&(counter as u128).to_be_bytes()[16 - width..]);
+    block
+}
+
+fn check_lengths(nonce: &[u8], len: usize) -> Result<(), Error> {
+    if !(MIN_NONCE_LEN..=MAX_NONCE_LEN).contains(&nonce.len()) {
+        return Err(Error::InvalidLength(format!(
+            "CCM nonces are {} to {} bytes, got {}",
+            MIN_NONCE_LEN,
+            MAX_NONCE_LEN,
+            nonce.len()
+        )));
+    }
+    let width = 15 - nonce.len();
+    if width < 8 && len as u64 >= 1 << (8 * width) {
+        return Err(Error::InvalidLength(format!(
+            "a {}-byte CCM nonce limits messages to {} bytes",
+            nonce.len(),
+            (1u64 << (8 * width)) - 1
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    fn check(key: &str, tag_len: usize, nonce: &str, aad: &str, plaintext: &str, sealed: &str) {
+        let ccm = Ccm::new(&Key::from_bytes(&from_hex(key).unwrap()), tag_len).unwrap();
+        let nonce = from_hex(nonce).unwrap();
+        let aad = from_hex(aad).unwrap();
+        let plaintext = from_hex(plaintext).unwrap();
+        let result = ccm.seal(&nonce, &aad, &plaintext).unwrap();
+        assert_eq!(to_hex(&result), sealed);
+        assert_eq!(ccm.open(&nonce, &aad, &result).unwrap(), plaintext);
+    }
+
+    // RFC 3610 packet vector #1 and NIST SP 800-38C appendix C, example 1
+    #[test]
+    fn vectors() {
+        check(
+            "c0c1c2c3c4c5c6c7c8c9cacbcccdcecf",
+            8,
+            "00000003020100a0a1a2a3a4a5",
+            "0001020304050607",
+            "08090a0b0c0d0e0f101112131415161718191a1b1c1d1e",
+            "588c979a61c663d2f066d0c2c0f989806d5f6b61dac38417e8d12cfdf926e0",
+        );
+        check(
+            "404142434445464748494a4b4c4d4e4f",
+            4,
+            "10111213141516",
+            "0001020304050607",
+            "20212223",
+            "7162015b4dac255d",
+        );
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let ccm = Ccm::new(&Key::from_bytes(&[7; 16]), 16).unwrap();
+        let nonce = [9; 12];
+        let sealed = ccm.seal(&nonce, b"aad", b"attack at dawn").unwrap();
+        for index in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert_eq!(
+                ccm.open(&nonce, b"aad", &tampered),
+                Err(Error::Authentication)
+            );
+        }
+        assert_eq!(
+            ccm.open(&nonce, b"aae", &sealed),
+            Err(Error::Authentication)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_parameters() {
+        let key = Key::from_bytes(&[7; 16]);
+        assert!(Ccm::new(&key, 5).is_err());
+        assert!(Ccm::new(&key, 18).is_err());
+        let ccm = Ccm::new(&key, 16).unwrap();
+        assert!(ccm.seal(&[0; 6], b"", b"").is_err());
+        assert!(ccm.seal(&[0; 14], b"", b"").is_err());
+        assert!(ccm.seal(&[0; 13], b"", &[0; 0x10000]).is_err());
+        assert!(ccm.open(&[0; 12], b"", &[0; 15]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::aes::BlockCipher;
+
+pub fn apply(cipher: &BlockCipher, counter: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let mut counter = *counter;
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let keystream = cipher.encrypt_block(&counter);
+        output.extend(chunk.iter().zip(keystream.iter()).map(|(a, b)| a ^ b));
+        increment(&mut counter);
+    }
+    output
+}
+
+pub fn increment(counter: &mut [u8; 16]) {
+    for byte in counter.iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+// The counter block `blocks` positions after `counter`, for random access.
+pub fn advance(counter: &[u8; 16], blocks: u64) -> [u8; 16] {
+    u128::from_be_bytes(*counter)
+        .wrapping_add(blocks as u128)
+        .to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::encoding::from_hex;
+
+    // NIST SP 800-38A F.5.1 / F.5.2
+    #[test]
+    fn sp800_38a_ctr_aes128() {
+        let key = Key::from_bytes(&from_hex("2b7e151628aed2a6abf7158809cf4f3c").unwrap());
+        let cipher = BlockCipher::new(&key);
+        let mut counter = [0; 16];
+        counter.copy_from_slice(&from_hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap());
+        let plaintext = from_hex(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        )
+        .unwrap();
+        let expected = from_hex(
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff\
+             5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee",
+        )
+        .unwrap();
+        let ciphertext = apply(&cipher, &counter, &plaintext);
+        assert_eq!(ciphertext, expected);
+        assert_eq!(apply(&cipher, &counter, &ciphertext), plaintext);
+        assert_eq!(apply(&cipher, &counter, &plaintext[..21]), expected[..21]);
+    }
+
+    // NIST SP 800-38A F.5.5
+    #[test]
+    fn sp800_38a_ctr_aes256() {
+        let key = Key::from_bytes(
+            &from_hex("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4").unwrap(),
+        );
+        let cipher = BlockCipher::new(&key);
+        let mut counter = [0; 16];
+        counter.copy_from_slice(&from_hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap());
+        let plaintext = from_hex(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        )
+        .unwrap();
+        let expected = from_hex(
+            "601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5\
+             2b0930daa23de94ce87017ba2d84988ddfc9c58db67aada613c2dd08457941a6",
+        )
+        .unwrap();
+        assert_eq!(apply(&cipher, &counter, &plaintext), expected);
+    }
+
+    #[test]
+    fn increment_carries() {
+        let mut counter = [0xff; 16];
+        counter[0] = 0x00;
+        increment(&mut counter);
+        let mut expected = [0; 16];
+        expected[0] = 0x01;
+        assert_eq!(counter, expected);
+    }
+
+    #[test]
+    fn advance_matches_increment() {
+        let start = [0xfe; 16];
+        let mut counter = start;
+        for blocks in 0..600 {
+            assert_eq!(advance(&start, blocks), counter);
+            increment(&mut counter);
+        }
+    }
+}
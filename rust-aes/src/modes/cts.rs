@@ -0,0 +1,223 @@
+use super::{cbc, xor_block};
+use crate::aes::BlockCipher;
+use crate::error::Error;
+
+// Ciphertext stealing variants from the NIST SP 800-38A addendum. They only
+// differ in where the partial block ends up: CS1 keeps it second to last, CS3
+// always swaps the last two blocks and CS2 swaps them only when the input is
+// not block aligned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Cs1,
+    Cs2,
+    Cs3,
+}
+
+impl Variant {
+    fn swaps(&self, partial_len: usize) -> bool {
+        match self {
+            Variant::Cs1 => false,
+            Variant::Cs2 => partial_len != 16,
+            Variant::Cs3 => true,
+        }
+    }
+}
+
+pub fn encrypt(
+    cipher: &BlockCipher,
+    iv: &[u8; 16],
+    plaintext: &[u8],
+    variant: Variant,
+) -> Result<Vec<u8>, Error> {
+    check_length(plaintext.len())?;
+    if plaintext.len() == 16 {
+        return cbc::encrypt(cipher, iv, plaintext);
+    }
+    let partial_len = last_block_len(plaintext.len());
+    let mut padded = plaintext.to_vec();
+    padded.resize(plaintext.len() - partial_len + 16, 0);
+    let mut ciphertext = cbc::encrypt(cipher, iv, &padded)?;
+
+    // drop the padding bytes of C(n-1), leaving C1..C(n-2) || C(n-1)* || Cn
+    let penultimate = ciphertext.len() - 32;
+    ciphertext.drain(penultimate + partial_len..penultimate + 16);
+    if variant.swaps(partial_len) {
+        swap_last_blocks(&mut ciphertext, partial_len);
+    }
+    Ok(ciphertext)
+}
+
+pub fn decrypt(
+    cipher: &BlockCipher,
+    iv: &[u8; 16],
+    ciphertext: &[u8],
+    variant: Variant,
+) -> Result<Vec<u8>, Error> {
+    check_length(ciphertext.len())?;
+    if ciphertext.len() == 16 {
+        return cbc::decrypt(cipher, iv, ciphertext);
+    }
+    let partial_len = last_block_len(ciphertext.len());
+    let mut ciphertext = ciphertext.to_vec();
+    if variant.swaps(partial_len) {
+        // the inverse of swapping Cn in front of C(n-1)*
+        let tail = ciphertext.split_off(ciphertext.len() - 16 - partial_len);
+        let (last, partial) = tail.split_at(16);
+        ciphertext.extend_from_slice(partial);
+        ciphertext.extend_from_slice(last);
+    }
+
+    let penultimate = ciphertext.len() - 16 - partial_len;
+    let mut last = [0; 16];
+    last.copy_from_slice(&ciphertext[ciphertext.len() - 16..]);
+    // D(Cn) = (Pn* || 0) xor C(n-1), so its tail is the stolen part of C(n-1)
+    let decrypted_last = cipher.decrypt_block(&last);
+    let mut previous = [0; 16];
+    previous[..partial_len].copy_from_slice(&ciphertext[penultimate..penultimate + partial_len]);
+    previous[partial_len..].copy_from_slice(&decrypted_last[partial_len..]);
+
+    let chain_iv = if penultimate == 0 {
+        *iv
+    } else {
+        let mut block = [0; 16];
+        block.copy_from_slice(&ciphertext[penultimate - 16..penultimate]);
+        block
+    };
+    let mut plaintext = cbc::decrypt(cipher, iv, &ciphertext[..penultimate])?;
+    plaintext.extend_from_slice(&xor_block(&cipher.decrypt_block(&previous), &chain_iv));
+    plaintext.extend_from_slice(&xor_block(&decrypted_last, &previous)[..partial_len]);
+    Ok(plaintext)
+}
+
+fn check_length(length: usize) -> Result<(), Error> {
+    if length < 16 {
+        return Err(Error::InvalidLength(format!(
+            "ciphertext stealing needs at least one full block (16 bytes), got {}",
+            length
+        )));
+    }
+    Ok(())
+}
+
+fn last_block_len(length: usize) -> usize {
+    match length % 16 {
+        0 => 16,
+        partial => partial,
+    }
+}
+
+fn swap_last_blocks(ciphertext: &mut Vec<u8>, partial_len: usize) {
+    let tail = ciphertext.split_off(ciphertext.len() - partial_len - 16);
+    let (partial, last) = tail.split_at(partial_len);
+    ciphertext.extend_from_slice(last);
+    ciphertext.extend_from_slice(partial);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::encoding::from_hex;
+
+    const MESSAGE: &[u8] = b"I would like the General Gau's Chicken, please, and wonton soup.";
+
+    fn cipher() -> BlockCipher {
+        BlockCipher::new(&Key::from_bytes(b"chicken teriyaki"))
+    }
+
+    // RFC 3962 Appendix B, which uses the CS3 ordering with a zero IV
+    #[test]
+    fn rfc3962_cs3() {
+        let cases = [
+            (17, "c6353568f2bf8cb4d8a580362da7ff7f97"),
+            (
+                31,
+                "fc00783e0efdb2c1d445d4c8eff7ed2297687268d6ecccc0c07b25e25ecfe5",
+            ),
+            (
+                32,
+                "39312523a78662d5be7fcbcc98ebf5a897687268d6ecccc0c07b25e25ecfe584",
+            ),
+            (
+                47,
+                "97687268d6ecccc0c07b25e25ecfe584b3fffd940c16a18c1b5549d2f838029e\
+                 39312523a78662d5be7fcbcc98ebf5",
+            ),
+            (
+                48,
+                "97687268d6ecccc0c07b25e25ecfe5849dad8bbb96c4cdc03bc103e1a194bbd8\
+                 39312523a78662d5be7fcbcc98ebf5a8",
+            ),
+            (
+                64,
+                "97687268d6ecccc0c07b25e25ecfe58439312523a78662d5be7fcbcc98ebf5a8\
+                 4807efe836ee89a526730dbc2f7bc8409dad8bbb96c4cdc03bc103e1a194bbd8",
+            ),
+        ];
+        let cipher = cipher();
+        for (length, expected) in cases.iter() {
+            let plaintext = &MESSAGE[..*length];
+            let ciphertext = encrypt(&cipher, &[0; 16], plaintext, Variant::Cs3).unwrap();
+            assert_eq!(ciphertext, from_hex(expected).unwrap());
+            let decrypted = decrypt(&cipher, &[0; 16], &ciphertext, Variant::Cs3).unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn cs1_keeps_partial_block_second_to_last() {
+        let ciphertext = encrypt(&cipher(), &[0; 16], &MESSAGE[..31], Variant::Cs1).unwrap();
+        assert_eq!(
+            ciphertext,
+            from_hex("97687268d6ecccc0c07b25e25ecfe5fc00783e0efdb2c1d445d4c8eff7ed22").unwrap()
+        );
+    }
+
+    #[test]
+    fn cs2_only_swaps_partial_blocks() {
+        let cipher = cipher();
+        for length in [17, 31, 47] {
+            assert_eq!(
+                encrypt(&cipher, &[0; 16], &MESSAGE[..length], Variant::Cs2).unwrap(),
+                encrypt(&cipher, &[0; 16], &MESSAGE[..length], Variant::Cs3).unwrap()
+            );
+        }
+        for length in [32, 48, 64] {
+            let cs2 = encrypt(&cipher, &[0; 16], &MESSAGE[..length], Variant::Cs2).unwrap();
+            let cbc = cbc::encrypt(&cipher, &[0; 16], &MESSAGE[..length]).unwrap();
+            assert_eq!(cs2, cbc);
+        }
+    }
+
+    #[test]
+    fn length_preserving_round_trip() {
+        let cipher = cipher();
+        let iv = [0x5a; 16];
+        for variant in [Variant::Cs1, Variant::Cs2, Variant::Cs3] {
+            for length in 16..=MESSAGE.len() {
+                let plaintext = &MESSAGE[..length];
+                let ciphertext = encrypt(&cipher, &iv, plaintext, variant).unwrap();
+                assert_eq!(ciphertext.len(), length);
+                assert_eq!(
+                    decrypt(&cipher, &iv, &ciphertext, variant).unwrap(),
+                    plaintext
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn short_input() {
+        let cipher = cipher();
+        for variant in [Variant::Cs1, Variant::Cs2, Variant::Cs3] {
+            assert!(matches!(
+                encrypt(&cipher, &[0; 16], &MESSAGE[..15], variant),
+                Err(Error::InvalidLength(_))
+            ));
+            assert!(matches!(
+                decrypt(&cipher, &[0; 16], &[], variant),
+                Err(Error::InvalidLength(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::{cbc, ctr};
+use crate::aes::{AesSize, BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha256};
+use crate::padding::{Padding, Pkcs7Padding};
+use crate::rng::RngSource;
+
+const IV_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Cbc,
+    Ctr,
+}
+
+impl Mode {
+    fn label(&self) -> &'static [u8] {
+        match self {
+            Mode::Cbc => b"cbc",
+            Mode::Ctr => b"ctr",
+        }
+    }
+}
+
+// Sealed layout: IV || ciphertext || HMAC-SHA256(IV || AAD || ciphertext ||
+// len(AAD) || len(ciphertext)), lengths as big-endian 64-bit byte counts.
+pub struct EncryptThenMac {
+    mode: Mode,
+    cipher: BlockCipher,
+    mac_key: Vec<u8>,
+}
+
+impl EncryptThenMac {
+    pub fn new(master: &Key, mode: Mode) -> Self {
+        let encryption_key = master.derive(
+            &[b"aeslib etm encryption ", mode.label()].concat(),
+            master.size.clone(),
+        );
+        let mac_key = master.derive(&[b"aeslib etm mac ", mode.label()].concat(), AesSize::S256);
+        EncryptThenMac {
+            mode,
+            cipher: BlockCipher::new(&encryption_key),
+            mac_key: mac_key.to_bytes(),
+        }
+    }
+
+    pub fn seal(
+        &self,
+        rng: &mut dyn RngSource,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let iv = rng.random_block()?;
+        Ok(self.seal_with_iv(&iv, aad, plaintext))
+    }
+
+    pub fn seal_with_iv(&self, iv: &[u8; 16], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = match self.mode {
+            Mode::Cbc => {
+                let padded = Pkcs7Padding
+                    .pad(plaintext)
+                    .expect("PKCS#7 padding cannot fail");
+                cbc::encrypt(&self.cipher, iv, &padded).expect("padded plaintext is block aligned")
+            }
+            Mode::Ctr => ctr::apply(&self.cipher, iv, plaintext),
+        };
+        let tag = self.tag(iv, aad, &ciphertext);
+        [iv.as_slice(), &ciphertext, &tag].concat()
+    }
+
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < IV_LEN + TAG_LEN {
+            return Err(Error::InvalidLength(format!(
+                "sealed data must be at least {} bytes, got {}",
+                IV_LEN + TAG_LEN,
+                sealed.len()
+            )));
+        }
+        let (iv, rest) = sealed.split_at(IV_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let mut iv_block = [0; 16];
+        iv_block.copy_from_slice(iv);
+        if !constant_time_eq(&self.tag(&iv_block, aad, ciphertext), tag) {
+            return Err(Error::Authentication);
+        }
+        match self.mode {
+            Mode::Cbc => Pkcs7Padding.unpad(cbc::decrypt(&self.cipher, &iv_block, ciphertext)?),
+            Mode::Ctr => Ok(ctr::apply(&self.cipher, &iv_block, ciphertext)),
+        }
+    }
+
+    fn tag(&self, iv: &[u8; 16], aad: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+        let mut hmac = Hmac::<Sha256>::new(&self.mac_key);
+        hmac.update(iv);
+        hmac.update(aad);
+        hmac.update(ciphertext);
+        hmac.update(&(aad.len() as u64).to_be_bytes());
+        hmac.update(&(ciphertext.len() as u64).to_be_bytes());
+        hmac.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    fn master() -> Key {
+        Key::from_password("correct horse battery staple", AesSize::S128)
+    }
+
+    #[test]
+    fn round_trip() {
+        for mode in [Mode::Cbc, Mode::Ctr] {
+            let etm = EncryptThenMac::new(&master(), mode);
+            for length in [0, 1, 15, 16, 17, 64] {
+                let plaintext = vec![0x42; length];
+                let sealed = etm.seal_with_iv(&[7; 16], b"header", &plaintext);
+                assert_eq!(etm.open(b"header", &sealed).unwrap(), plaintext);
+            }
+        }
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        for mode in [Mode::Cbc, Mode::Ctr] {
+            let etm = EncryptThenMac::new(&master(), mode);
+            let sealed = etm.seal_with_iv(&[1; 16], b"aad", b"attack at dawn");
+            for index in 0..sealed.len() {
+                let mut tampered = sealed.clone();
+                tampered[index] ^= 0x01;
+                assert_eq!(etm.open(b"aad", &tampered), Err(Error::Authentication));
+            }
+            assert_eq!(etm.open(b"other", &sealed), Err(Error::Authentication));
+            assert_eq!(
+                etm.open(b"aad", &sealed[..sealed.len() - 1]),
+                Err(Error::Authentication)
+            );
+            assert!(matches!(
+                etm.open(b"aad", &sealed[..IV_LEN + TAG_LEN - 1]),
+                Err(Error::InvalidLength(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn wrong_key_is_detected() {
+        let etm = EncryptThenMac::new(&master(), Mode::Cbc);
+        let sealed = etm.seal_with_iv(&[3; 16], b"", b"secret");
+        let wrong = EncryptThenMac::new(&Key::from_password("wrong", AesSize::S128), Mode::Cbc);
+        assert_eq!(wrong.open(b"", &sealed), Err(Error::Authentication));
+        let other_mode = EncryptThenMac::new(&master(), Mode::Ctr);
+        assert_eq!(other_mode.open(b"", &sealed), Err(Error::Authentication));
+    }
+
+    #[test]
+    fn ciphertext_lengths() {
+        let plaintext = [0; 20];
+        let cbc = EncryptThenMac::new(&master(), Mode::Cbc).seal_with_iv(&[0; 16], b"", &plaintext);
+        let ctr = EncryptThenMac::new(&master(), Mode::Ctr).seal_with_iv(&[0; 16], b"", &plaintext);
+        assert_eq!(cbc.len(), IV_LEN + 32 + TAG_LEN);
+        assert_eq!(ctr.len(), IV_LEN + 20 + TAG_LEN);
+    }
+
+    #[test]
+    fn random_iv_is_reproducible_with_seed() {
+        let etm = EncryptThenMac::new(&master(), Mode::Ctr);
+        let a = etm.seal(&mut SeededRng::from_u64(7), b"", b"data").unwrap();
+        let b = etm.seal(&mut SeededRng::from_u64(7), b"", b"data").unwrap();
+        let c = etm.seal(&mut SeededRng::from_u64(8), b"", b"data").unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a[..IV_LEN], c[..IV_LEN]);
+        assert_eq!(etm.open(b"", &c).unwrap(), b"data");
+    }
+}
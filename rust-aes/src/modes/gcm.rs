@@ -0,0 +1,215 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::constant_time_eq;
+
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+// GCM, NIST SP 800-38D. Sealed output is ciphertext || tag.
+pub struct Gcm {
+    cipher: BlockCipher,
+    h: u128,
+}
+
+impl Gcm {
+    pub fn new(key: &Key) -> Self {
+        let cipher = BlockCipher::new(key);
+        let h = u128::from_be_bytes(cipher.encrypt_block(&[0; 16]));
+        Gcm { cipher, h }
+    }
+
+    pub fn seal(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let j0 = self.j0(nonce)?;
+        let mut sealed = self.ctr(&j0, plaintext);
+        let tag = self.tag(&j0, aad, &sealed);
+        sealed.extend_from_slice(&tag);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, nonce: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < TAG_LEN {
+            return Err(Error::InvalidLength(format!(
+                "sealed data must be at least {} bytes, got {}",
+                TAG_LEN,
+                sealed.len()
+            )));
+        }
+        let j0 = self.j0(nonce)?;
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        if !constant_time_eq(&self.tag(&j0, aad, ciphertext), tag) {
+            return Err(Error::Authentication);
+        }
+        Ok(self.ctr(&j0, ciphertext))
+    }
+
+    fn j0(&self, nonce: &[u8]) -> Result<[u8; 16], Error> {
+        if nonce.is_empty() {
+            return Err(Error::InvalidLength(
+                "GCM nonce must not be empty".to_string(),
+            ));
+        }
+        let mut j0 = [0; 16];
+        if nonce.len() == NONCE_LEN {
+            j0[..NONCE_LEN].copy_from_slice(nonce);
+            j0[15] = 1;
+        } else {
+            j0 = self.ghash(&[], nonce).to_be_bytes();
+        }
+        Ok(j0)
+    }
+
+    // GCTR with inc32: only the low 32 bits of the counter block wrap.
+    fn ctr(&self, j0: &[u8; 16], data: &[u8]) -> Vec<u8> {
+        let mut counter = *j0;
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data.chunks(16) {
+            inc32(&mut counter);
+            let keystream = self.cipher.encrypt_block(&counter);
+            output.extend(chunk.iter().zip(keystream.iter()).map(|(a, b)| a ^ b));
+        }
+        output
+    }
+
+    fn tag(&self, j0: &[u8; 16], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+        let s = self.ghash(aad, ciphertext);
+        let mask = u128::from_be_bytes(self.cipher.encrypt_block(j0));
+        (s ^ mask).to_be_bytes()
+    }
+
+    fn ghash(&self, aad: &[u8], ciphertext: &[u8]) -> u128 {
+        let mut y = 0;
+        for data in [aad, ciphertext] {
+            for chunk in data.chunks(16) {
+                let mut block = [0; 16];
+                block[..chunk.len()].copy_from_slice(chunk);
+                y = gf_mul(y ^ u128::from_be_bytes(block), self.h);
+            }
+        }
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        gf_mul(y ^ lengths, self.h)
+    }
+}
+
+fn inc32(counter: &mut [u8; 16]) {
+    let low = u32::from_be_bytes([counter[12], counter[13], counter[14], counter[15]]);
+    counter[12..].copy_from_slice(&low.wrapping_add(1).to_be_bytes());
+}
+
+// Multiplication in GF(2^128) with GCM's reflected bit order (SP 800-38D 6.3).
+fn gf_mul(x: u128, y: u128) -> u128 {
+    const R: u128 = 0xe1 << 120;
+    let mut z = 0;
+    let mut v = y;
+    for i in 0..128 {
+        if (x >> (127 - i)) & 1 == 1 {
+            z ^= v;
+        }
+        v = if v & 1 == 1 { (v >> 1) ^ R } else { v >> 1 };
+    }
+    z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    const KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const PLAINTEXT: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255";
+    const AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+
+    fn check(key: &str, nonce: &str, aad: &str, plaintext: &str, sealed: &str) {
+        let gcm = Gcm::new(&Key::from_bytes(&from_hex(key).unwrap()));
+        let nonce = from_hex(nonce).unwrap();
+        let aad = from_hex(aad).unwrap();
+        let plaintext = from_hex(plaintext).unwrap();
+        let result = gcm.seal(&nonce, &aad, &plaintext).unwrap();
+        assert_eq!(to_hex(&result), sealed);
+        assert_eq!(gcm.open(&nonce, &aad, &result).unwrap(), plaintext);
+    }
+
+    // McGrew & Viega, "The Galois/Counter Mode of Operation", test cases 1-4 and 6
+    #[test]
+    fn test_cases() {
+        let zero = "00000000000000000000000000000000";
+        check(
+            zero,
+            "000000000000000000000000",
+            "",
+            "",
+            "58e2fccefa7e3061367f1d57a4e7455a",
+        );
+        check(
+            zero,
+            "000000000000000000000000",
+            "",
+            zero,
+            "0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf",
+        );
+        check(
+            KEY,
+            "cafebabefacedbaddecaf888",
+            "",
+            PLAINTEXT,
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985\
+             4d5c2af327cd64a62cf35abd2ba6fab4",
+        );
+        check(
+            KEY,
+            "cafebabefacedbaddecaf888",
+            AAD,
+            &PLAINTEXT[..120],
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091\
+             5bc94fbc3221a5db94fae95ae7121a47",
+        );
+        check(
+            KEY,
+            "9313225df88406e555909c5aff5269aa6a7a9538534f7da1e4c303d2a318a728\
+             c3c0c95156809539fcf0e2429a6b525416aedbf5a0de6a57a637b39b",
+            AAD,
+            &PLAINTEXT[..120],
+            "8ce24998625615b603a033aca13fb894be9112a5c3a211a8ba262a3cca7e2ca7\
+             01e4a9a4fba43c90ccdcb281d48c7c6fd62875d2aca417034c34aee5\
+             619cc5aefffe0bfa462af43c1699d050",
+        );
+    }
+
+    // Test case 15, the AES-256 counterpart of test case 3
+    #[test]
+    fn test_case_15() {
+        check(
+            &KEY.repeat(2),
+            "cafebabefacedbaddecaf888",
+            "",
+            PLAINTEXT,
+            "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+             8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662898015ad\
+             b094dac5d93471bdec1a502270e3cc6c",
+        );
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let gcm = Gcm::new(&Key::from_bytes(&from_hex(KEY).unwrap()));
+        let nonce = [9; NONCE_LEN];
+        let sealed = gcm.seal(&nonce, b"aad", b"attack at dawn").unwrap();
+        for index in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 0x80;
+            assert_eq!(
+                gcm.open(&nonce, b"aad", &tampered),
+                Err(Error::Authentication)
+            );
+        }
+        assert_eq!(gcm.open(&nonce, b"", &sealed), Err(Error::Authentication));
+        assert_eq!(
+            gcm.open(&[8; NONCE_LEN], b"aad", &sealed),
+            Err(Error::Authentication)
+        );
+        assert!(gcm.open(&nonce, b"aad", &sealed[..TAG_LEN - 1]).is_err());
+        assert!(gcm.seal(&[], b"", b"").is_err());
+    }
+}
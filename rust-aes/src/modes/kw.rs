@@ -0,0 +1,148 @@
+use crate::aes::BlockCipher;
+use crate::error::Error;
+use crate::hash::constant_time_eq;
+
+const IV: [u8; 8] = [0xa6; 8];
+
+// AES Key Wrap, RFC 3394. Wraps keys of at least 16 bytes in 8-byte steps
+// and adds 8 bytes that act as an integrity check on unwrap.
+pub fn wrap(kek: &BlockCipher, key: &[u8]) -> Result<Vec<u8>, Error> {
+    check_len(key.len(), "key")?;
+    let mut a = IV;
+    let mut r: Vec<[u8; 8]> = key.chunks(8).map(to_half).collect();
+    let n = r.len() as u64;
+    for j in 0..6 {
+        for (i, half) in r.iter_mut().enumerate() {
+            let b = kek.encrypt_block(&join(&a, half));
+            let t = n * j + i as u64 + 1;
+            a = (u64::from_be_bytes(to_half(&b[..8])) ^ t).to_be_bytes();
+            *half = to_half(&b[8..]);
+        }
+    }
+    let mut wrapped = a.to_vec();
+    for half in r {
+        wrapped.extend_from_slice(&half);
+    }
+    Ok(wrapped)
+}
+
+pub fn unwrap(kek: &BlockCipher, wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+    if wrapped.len() < 8 {
+        return Err(Error::InvalidLength("wrapped key is too short".to_string()));
+    }
+    check_len(wrapped.len() - 8, "wrapped key")?;
+    let mut a = to_half(&wrapped[..8]);
+    let mut r: Vec<[u8; 8]> = wrapped[8..].chunks(8).map(to_half).collect();
+    let n = r.len() as u64;
+    for j in (0..6).rev() {
+        for (i, half) in r.iter_mut().enumerate().rev() {
+            let t = n * j + i as u64 + 1;
+            let masked = (u64::from_be_bytes(a) ^ t).to_be_bytes();
+            let b = kek.decrypt_block(&join(&masked, half));
+            a = to_half(&b[..8]);
+            *half = to_half(&b[8..]);
+        }
+    }
+    if !constant_time_eq(&a, &IV) {
+        return Err(Error::Authentication);
+    }
+    Ok(r.concat())
+}
+
+fn check_len(len: usize, what: &str) -> Result<(), Error> {
+    if len < 16 || !len.is_multiple_of(8) {
+        return Err(Error::InvalidLength(format!(
+            "{} must be a multiple of 8 bytes and at least 16, got {}",
+            what, len
+        )));
+    }
+    Ok(())
+}
+
+fn to_half(bytes: &[u8]) -> [u8; 8] {
+    let mut half = [0; 8];
+    half.copy_from_slice(bytes);
+    half
+}
+
+fn join(a: &[u8; 8], b: &[u8; 8]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(a);
+    block[8..].copy_from_slice(b);
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::encoding::{from_hex, to_hex};
+
+    fn kek() -> BlockCipher {
+        BlockCipher::new(&Key::from_bytes(
+            &from_hex("000102030405060708090a0b0c0d0e0f").unwrap(),
+        ))
+    }
+
+    // RFC 3394 sections 4.1-4.6: every KEK size with every key size up to it
+    #[test]
+    fn rfc3394() {
+        let kek_bytes: Vec<u8> = (0..32).collect();
+        let key_bytes =
+            from_hex("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f").unwrap();
+        let vectors = [
+            (16, 16, "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"),
+            (24, 16, "96778b25ae6ca435f92b5b97c050aed2468ab8a17ad84e5d"),
+            (32, 16, "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7"),
+            (
+                24,
+                24,
+                "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2",
+            ),
+            (
+                32,
+                24,
+                "a8f9bc1612c68b3ff6e6f4fbe30e71e4769c8b80a32cb8958cd5d17d6b254da1",
+            ),
+            (
+                32,
+                32,
+                "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21",
+            ),
+        ];
+        for (kek_len, key_len, expected) in vectors {
+            let kek = BlockCipher::new(&Key::from_bytes(&kek_bytes[..kek_len]));
+            let key = &key_bytes[..key_len];
+            let wrapped = wrap(&kek, key).unwrap();
+            assert_eq!(to_hex(&wrapped), expected);
+            assert_eq!(unwrap(&kek, &wrapped).unwrap(), key);
+        }
+    }
+
+    // A 256-bit key under a 128-bit KEK, which the RFC does not cover
+    #[test]
+    fn longer_key_than_kek() {
+        let key =
+            from_hex("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f").unwrap();
+        let wrapped = wrap(&kek(), &key).unwrap();
+        assert_eq!(
+            to_hex(&wrapped),
+            "11826840774d993ff9c2fa02cca3cea0e93b1e1cf96361f93ea6dc2f345194e7b30f964c79f9e61d"
+        );
+        assert_eq!(unwrap(&kek(), &wrapped).unwrap(), key);
+    }
+
+    #[test]
+    fn integrity() {
+        let wrapped = wrap(&kek(), &[7; 24]).unwrap();
+        for index in 0..wrapped.len() {
+            let mut tampered = wrapped.clone();
+            tampered[index] ^= 0x80;
+            assert_eq!(unwrap(&kek(), &tampered), Err(Error::Authentication));
+        }
+        let other = BlockCipher::new(&Key::from_bytes(&[1; 16]));
+        assert_eq!(unwrap(&other, &wrapped), Err(Error::Authentication));
+        assert!(wrap(&kek(), &[0; 12]).is_err());
+        assert!(unwrap(&kek(), &wrapped[..20]).is_err());
+    }
+}
@@ -0,0 +1,17 @@
+pub mod cbc;
+pub mod ccm;
+pub mod ctr;
+pub mod cts;
+pub mod etm;
+pub mod gcm;
+pub mod kw;
+pub mod stream;
+pub mod xts;
+
+pub fn xor_block(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut out = [0; 16];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = a[i] ^ b[i];
+    }
+    out
+}
@@ -0,0 +1,100 @@
+use super::gcm::{Gcm, NONCE_LEN, TAG_LEN};
+use crate::aes::Key;
+use crate::error::Error;
+
+pub const NONCE_PREFIX_LEN: usize = 7;
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+pub const SEALED_SEGMENT_SIZE: usize = SEGMENT_SIZE + TAG_LEN;
+
+// STREAM (Hoang, Reyhanitabar, Rogaway, Vizár 2015) over AES-GCM. Segment i
+// uses the nonce prefix || i as u32 big-endian || last flag, so dropping,
+// reordering or appending segments fails authentication. Every segment but
+// the last holds exactly SEGMENT_SIZE bytes of plaintext. crate::io's
+// EncryptWriter::stream and DecryptReader::stream read and write the format.
+pub struct Stream {
+    gcm: Gcm,
+    prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl Stream {
+    pub fn new(key: &Key, prefix: &[u8]) -> Result<Self, Error> {
+        if prefix.len() != NONCE_PREFIX_LEN {
+            return Err(Error::InvalidLength(format!(
+                "STREAM nonce prefix must be {} bytes, got {}",
+                NONCE_PREFIX_LEN,
+                prefix.len()
+            )));
+        }
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(prefix);
+        Ok(Stream {
+            gcm: Gcm::new(key),
+            prefix: nonce_prefix,
+        })
+    }
+
+    pub fn seal_segment(&self, index: u32, last: bool, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        self.gcm
+            .seal(&self.nonce(index, last), aad, plaintext)
+            .expect("STREAM nonces are never empty")
+    }
+
+    pub fn open_segment(
+        &self,
+        index: u32,
+        last: bool,
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.gcm.open(&self.nonce(index, last), aad, sealed)
+    }
+
+    fn nonce(&self, index: u32, last: bool) -> [u8; NONCE_LEN] {
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+        nonce[NONCE_LEN - 1] = last as u8;
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> Stream {
+        Stream::new(&Key::from_bytes(&[0x42; 16]), &[7; NONCE_PREFIX_LEN]).unwrap()
+    }
+
+    #[test]
+    fn nonce_layout() {
+        assert_eq!(
+            stream().nonce(0x01020304, true),
+            [7, 7, 7, 7, 7, 7, 7, 1, 2, 3, 4, 1]
+        );
+        assert!(Stream::new(&Key::from_bytes(&[0; 16]), &[0; 12]).is_err());
+    }
+
+    #[test]
+    fn segments_are_bound_to_position() {
+        let stream = stream();
+        let sealed = stream.seal_segment(2, false, b"aad", b"segment");
+        assert_eq!(sealed.len(), 7 + TAG_LEN);
+        assert_eq!(
+            stream.open_segment(2, false, b"aad", &sealed).unwrap(),
+            b"segment"
+        );
+        assert_eq!(
+            stream.open_segment(3, false, b"aad", &sealed),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            stream.open_segment(2, true, b"aad", &sealed),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            stream.open_segment(2, false, b"", &sealed),
+            Err(Error::Authentication)
+        );
+    }
+}
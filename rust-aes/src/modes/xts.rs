@@ -0,0 +1,267 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+
+// XTS-AES, IEEE 1619-2007. The key is the data key followed by the tweak key
+// and sectors are numbered as 128-bit little-endian tweaks.
+pub struct Xts {
+    data: BlockCipher,
+    tweak: BlockCipher,
+}
+
+impl Xts {
+    pub fn new(key: &[u8]) -> Result<Self, Error> {
+        if key.len() != 32 && key.len() != 64 {
+            return Err(Error::InvalidLength(format!(
+                "XTS keys are 32 or 64 bytes, got {}",
+                key.len()
+            )));
+        }
+        let (data, tweak) = key.split_at(key.len() / 2);
+        Ok(Xts {
+            data: BlockCipher::new(&Key::from_bytes(data)),
+            tweak: BlockCipher::new(&Key::from_bytes(tweak)),
+        })
+    }
+
+    pub fn encrypt_sector(&self, sector: u128, data: &[u8]) -> Result<Vec<u8>, Error> {
+        check_sector(data)?;
+        let mut tweak = self.initial_tweak(sector);
+        let tail = data.len() % 16;
+        let full = data.len() - tail;
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data[..full].chunks(16) {
+            output.extend_from_slice(&self.encrypt_block(chunk, tweak));
+            tweak = mul_alpha(tweak);
+        }
+        if tail != 0 {
+            // Ciphertext stealing: the short block borrows the end of the
+            // previous ciphertext block and the two trade places.
+            let previous = output.split_off(full - 16);
+            let mut block = [0; 16];
+            block[..tail].copy_from_slice(&data[full..]);
+            block[tail..].copy_from_slice(&previous[tail..]);
+            output.extend_from_slice(&self.encrypt_block(&block, tweak));
+            output.extend_from_slice(&previous[..tail]);
+        }
+        Ok(output)
+    }
+
+    pub fn decrypt_sector(&self, sector: u128, data: &[u8]) -> Result<Vec<u8>, Error> {
+        check_sector(data)?;
+        let mut tweak = self.initial_tweak(sector);
+        let tail = data.len() % 16;
+        let full = if tail == 0 {
+            data.len()
+        } else {
+            data.len() - tail - 16
+        };
+        let mut output = Vec::with_capacity(data.len());
+        for chunk in data[..full].chunks(16) {
+            output.extend_from_slice(&self.decrypt_block(chunk, tweak));
+            tweak = mul_alpha(tweak);
+        }
+        if tail != 0 {
+            let stolen = self.decrypt_block(&data[full..full + 16], mul_alpha(tweak));
+            let mut block = [0; 16];
+            block[..tail].copy_from_slice(&data[full + 16..]);
+            block[tail..].copy_from_slice(&stolen[tail..]);
+            output.extend_from_slice(&self.decrypt_block(&block, tweak));
+            output.extend_from_slice(&stolen[..tail]);
+        }
+        Ok(output)
+    }
+
+    // Consecutive sectors of `sector_size` bytes starting at `first_sector`;
+    // only the last may be shorter.
+    pub fn encrypt(
+        &self,
+        first_sector: u128,
+        sector_size: usize,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.sectors(first_sector, sector_size, data, Xts::encrypt_sector)
+    }
+
+    pub fn decrypt(
+        &self,
+        first_sector: u128,
+        sector_size: usize,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.sectors(first_sector, sector_size, data, Xts::decrypt_sector)
+    }
+
+    fn sectors<F>(
+        &self,
+        first_sector: u128,
+        sector_size: usize,
+        data: &[u8],
+        apply: F,
+    ) -> Result<Vec<u8>, Error>
+    where
+        F: Fn(&Xts, u128, &[u8]) -> Result<Vec<u8>, Error>,
+    {
+        if sector_size < 16 {
+            return Err(Error::InvalidLength(
+                "XTS sectors are at least 16 bytes".to_string(),
+            ));
+        }
+        let mut output = Vec::with_capacity(data.len());
+        for (i, sector) in data.chunks(sector_size).enumerate() {
+            output.extend(apply(self, first_sector.wrapping_add(i as u128), sector)?);
+        }
+        Ok(output)
+    }
+
+    fn initial_tweak(&self, sector: u128) -> u128 {
+        u128::from_le_bytes(self.tweak.encrypt_block(&sector.to_le_bytes()))
+    }
+
+    fn encrypt_block(&self, block: &[u8], tweak: u128) -> [u8; 16] {
+        let mut input = [0; 16];
+        input.copy_from_slice(block);
+        let masked = (u128::from_le_bytes(input) ^ tweak).to_le_bytes();
+        (u128::from_le_bytes(self.data.encrypt_block(&masked)) ^ tweak).to_le_bytes()
+    }
+
+    fn decrypt_block(&self, block: &[u8], tweak: u128) -> [u8; 16] {
+        let mut input = [0; 16];
+        input.copy_from_slice(block);
+        let masked = (u128::from_le_bytes(input) ^ tweak).to_le_bytes();
+        (u128::from_le_bytes(self.data.decrypt_block(&masked)) ^ tweak).to_le_bytes()
+    }
+}
+
+fn check_sector(data: &[u8]) -> Result<(), Error> {
+    if data.len() < 16 {
+        return Err(Error::InvalidLength(format!(
+            "XTS needs at least 16 bytes per sector, got {}",
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+// Multiplication by the primitive element of GF(2^128), little-endian.
+fn mul_alpha(tweak: u128) -> u128 {
+    let carry = tweak >> 127;
+    (tweak << 1) ^ (carry * 0x87)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    fn check(key: &str, sector: u128, plaintext: &[u8], ciphertext: &str) {
+        let xts = Xts::new(&from_hex(key).unwrap()).unwrap();
+        let encrypted = xts.encrypt_sector(sector, plaintext).unwrap();
+        assert_eq!(to_hex(&encrypted), ciphertext);
+        assert_eq!(xts.decrypt_sector(sector, &encrypted).unwrap(), plaintext);
+    }
+
+    // IEEE 1619-2007 Annex B, XTS-AES-128 vectors 2, 4, 5, 15 and 17
+    #[test]
+    fn ieee1619() {
+        check(
+            "1111111111111111111111111111111122222222222222222222222222222222",
+            0x3333333333,
+            &[0x44; 32],
+            "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0",
+        );
+        let key = "2718281828459045235360287471352631415926535897932384626433832795";
+        let plaintext: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let xts = Xts::new(&from_hex(key).unwrap()).unwrap();
+        let vector4 = xts.encrypt_sector(0, &plaintext).unwrap();
+        assert_eq!(
+            to_hex(&vector4[..32]),
+            "27a7479befa1d476489f308cd4cfa6e2a96e4bbe3208ff25287dd3819616e89c"
+        );
+        assert_eq!(
+            to_hex(&vector4[480..]),
+            "eb4a427d1923ce3ff262735779a418f20a282df920147beabe421ee5319d0568"
+        );
+        let vector5 = xts.encrypt_sector(1, &plaintext).unwrap();
+        assert_eq!(
+            to_hex(&vector5[..32]),
+            "bbf9d6a74a7465fee20f42adf9a623fc954f3b55587e8e429eec6f71e738a390"
+        );
+        assert_eq!(xts.decrypt_sector(1, &vector5).unwrap(), plaintext);
+
+        let stealing = "fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0";
+        let plaintext: Vec<u8> = (0..21).collect();
+        check(
+            stealing,
+            0x123456789a,
+            &plaintext[..17],
+            "6c1625db4671522d3d7599601de7ca09ed",
+        );
+        check(
+            stealing,
+            0x123456789a,
+            &plaintext,
+            "2cd47e780de4b008d8fde727c1c325f4edbf9dace4",
+        );
+    }
+
+    // IEEE 1619-2007 Annex B, XTS-AES-256 vectors 10-14
+    #[test]
+    fn ieee1619_aes256() {
+        let key = "27182818284590452353602874713526624977572470936999595749669676\
+                   27314159265358979323846264338327950288419716939937510582097494\
+                   4592";
+        let xts = Xts::new(&from_hex(key).unwrap()).unwrap();
+        let plaintext: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let vectors = [
+            (
+                0xff,
+                "1c3b3a102f770386e4836c99e370cf9bea00803f5e482357a4ae12d414a3e63b",
+                "773dad38014bd2092fa755c824bb5e54c4f36ffda9fcea70b9c6e693e148c151",
+            ),
+            (
+                0xffff,
+                "77a31251618a15e6b92d1d66dffe7b50b50bad552305ba0217a610688eff7e11",
+                "54fbff89eeaeea791f4a7ab1f9868294a4f9e27b42af8100cb9d59cef9645803",
+            ),
+            (
+                0xffffff,
+                "e387aaa58ba483afa7e8eb469778317ecf4cf573aa9d4eac23f2cdf914e4e200",
+                "ed242c8900dd1fa965bc950dfce09b132263a1eef52dd6888c309f5a7d712826",
+            ),
+            (
+                0xffffffff,
+                "bf53d2dade78e822a4d949a9bc6766b01b06a8ef70d26748c6a7fc36d80ae4c5",
+                "f6516139420fcff3b7361d86322c4bd84c82f335abb152c4a93411373aaa8220",
+            ),
+            (
+                0xffffffffff,
+                "64497e5a831e4a932c09be3e5393376daa599548b816031d224bbf50a818ed23",
+                "6975be5e0b4efce51cd3e70c25a1fbbbd609d273ad5b0d59631c531f6a0a57b9",
+            ),
+        ];
+        for (sector, head, tail) in vectors {
+            let encrypted = xts.encrypt_sector(sector, &plaintext).unwrap();
+            assert_eq!(to_hex(&encrypted[..32]), head);
+            assert_eq!(to_hex(&encrypted[480..]), tail);
+            assert_eq!(xts.decrypt_sector(sector, &encrypted).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn sectors() {
+        let xts = Xts::new(&[5; 32]).unwrap();
+        let data: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+        let encrypted = xts.encrypt(7, 512, &data).unwrap();
+        assert_eq!(
+            &encrypted[..512],
+            xts.encrypt_sector(7, &data[..512]).unwrap()
+        );
+        assert_eq!(
+            &encrypted[512..],
+            xts.encrypt_sector(8, &data[512..]).unwrap()
+        );
+        assert_eq!(xts.decrypt(7, 512, &encrypted).unwrap(), data);
+        assert!(xts.encrypt_sector(0, &[0; 15]).is_err());
+        assert!(Xts::new(&[0; 16]).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::error::Error;
+use crate::rng::{OsRng, RngSource};
+
+const BLOCK_SIZE: usize = 16;
+
+pub trait Padding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error>;
+    fn unpad(&self, data: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+// `rng` is only used by ISO 10126, which pads with random bytes.
+pub fn parse(name: &str, rng: Box<dyn RngSource>) -> Result<Box<dyn Padding>, String> {
+    match name {
+        "zero" => Ok(Box::new(ZeroPadding)),
+        "pkcs7" => Ok(Box::new(Pkcs7Padding)),
+        "ansix923" => Ok(Box::new(AnsiX923Padding)),
+        "iso10126" => Ok(Box::new(Iso10126Padding::new(rng))),
+        "iso7816" => Ok(Box::new(Iso7816Padding)),
+        _ => Err(format!("Invalid padding: {}", name)),
+    }
+}
+
+// Pads with zero bytes up to the block size and adds nothing to aligned
+// input. This is what aes::encrypt has always done, so data that ends in a
+// zero byte cannot be padded unambiguously.
+pub struct ZeroPadding;
+
+impl Padding for ZeroPadding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if data.last() == Some(&0) {
+            return Err(Error::InvalidPadding);
+        }
+        let mut padded = data.to_vec();
+        padded.resize(data.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        Ok(padded)
+    }
+
+    fn unpad(&self, mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        check_aligned(&data)?;
+        let data_len = data
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |i| i + 1);
+        if data.len() - data_len >= BLOCK_SIZE {
+            return Err(Error::InvalidPadding);
+        }
+        data.truncate(data_len);
+        Ok(data)
+    }
+}
+
+pub struct Pkcs7Padding;
+
+impl Padding for Pkcs7Padding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let pad_len = pad_len(data);
+        let mut padded = data.to_vec();
+        padded.resize(data.len() + pad_len, pad_len as u8);
+        Ok(padded)
+    }
+
+    fn unpad(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        strip_counted(data, |pad_len, filler| {
+            filler.iter().all(|byte| *byte as usize == pad_len)
+        })
+    }
+}
+
+pub struct AnsiX923Padding;
+
+impl Padding for AnsiX923Padding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let pad_len = pad_len(data);
+        let mut padded = data.to_vec();
+        padded.resize(data.len() + pad_len - 1, 0);
+        padded.push(pad_len as u8);
+        Ok(padded)
+    }
+
+    fn unpad(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        strip_counted(data, |_, filler| filler.iter().all(|byte| *byte == 0))
+    }
+}
+
+pub struct Iso10126Padding {
+    rng: Box<dyn RngSource>,
+}
+
+impl Iso10126Padding {
+    pub fn new(rng: Box<dyn RngSource>) -> Self {
+        Iso10126Padding { rng }
+    }
+}
+
+impl Default for Iso10126Padding {
+    fn default() -> Self {
+        Iso10126Padding::new(Box::new(OsRng))
+    }
+}
+
+impl Padding for Iso10126Padding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let pad_len = pad_len(data);
+        let mut padded = data.to_vec();
+        padded.extend_from_slice(&self.rng.random_vec(pad_len - 1)?);
+        padded.push(pad_len as u8);
+        Ok(padded)
+    }
+
+    fn unpad(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        strip_counted(data, |_, _| true)
+    }
+}
+
+pub struct Iso7816Padding;
+
+impl Padding for Iso7816Padding {
+    fn pad(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let pad_len = pad_len(data);
+        let mut padded = data.to_vec();
+        padded.push(0x80);
+        padded.resize(data.len() + pad_len, 0);
+        Ok(padded)
+    }
+
+    fn unpad(&self, mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        check_aligned(&data)?;
+        let marker = data
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .ok_or(Error::InvalidPadding)?;
+        if data[marker] != 0x80 || data.len() - marker > BLOCK_SIZE {
+            return Err(Error::InvalidPadding);
+        }
+        data.truncate(marker);
+        Ok(data)
+    }
+}
+
+fn pad_len(data: &[u8]) -> usize {
+    BLOCK_SIZE - data.len() % BLOCK_SIZE
+}
+
+fn check_aligned(data: &[u8]) -> Result<(), Error> {
+    if !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(Error::InvalidLength(format!(
+            "padded data must be a multiple of {} bytes, got {}",
+            BLOCK_SIZE,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+// Shared unpadding for the schemes whose last byte holds the padding length.
+fn strip_counted(
+    mut data: Vec<u8>,
+    filler_is_valid: impl Fn(usize, &[u8]) -> bool,
+) -> Result<Vec<u8>, Error> {
+    check_aligned(&data)?;
+    let pad_len = *data.last().ok_or(Error::InvalidPadding)? as usize;
+    if pad_len == 0 || pad_len > BLOCK_SIZE {
+        return Err(Error::InvalidPadding);
+    }
+    let data_len = data.len() - pad_len;
+    if !filler_is_valid(pad_len, &data[data_len..data.len() - 1]) {
+        return Err(Error::InvalidPadding);
+    }
+    data.truncate(data_len);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::from_hex;
+    use crate::rng::SeededRng;
+
+    fn schemes() -> Vec<Box<dyn Padding>> {
+        vec![
+            Box::new(Pkcs7Padding),
+            Box::new(AnsiX923Padding),
+            Box::new(Iso10126Padding::new(Box::new(SeededRng::from_u64(1)))),
+            Box::new(Iso7816Padding),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for mut scheme in schemes() {
+            for length in 0..40 {
+                let data = vec![0xab; length];
+                let padded = scheme.pad(&data).unwrap();
+                assert_eq!(padded.len(), (length / 16 + 1) * 16);
+                assert_eq!(scheme.unpad(padded).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn known_paddings() {
+        let data = [0xdd; 13];
+        assert_eq!(
+            Pkcs7Padding.pad(&data).unwrap()[13..],
+            from_hex("030303").unwrap()
+        );
+        assert_eq!(
+            AnsiX923Padding.pad(&data).unwrap()[13..],
+            from_hex("000003").unwrap()
+        );
+        assert_eq!(
+            Iso7816Padding.pad(&data).unwrap()[13..],
+            from_hex("800000").unwrap()
+        );
+        assert_eq!(ZeroPadding.pad(&data).unwrap()[13..], [0, 0, 0]);
+        let iso10126 = Iso10126Padding::default().pad(&data).unwrap();
+        assert_eq!(iso10126.len(), 16);
+        assert_eq!(iso10126[15], 3);
+    }
+
+    #[test]
+    fn parse_uses_the_given_rng() {
+        let pad = || {
+            let rng = Box::new(SeededRng::from_u64(7));
+            parse("iso10126", rng).unwrap().pad(&[0xdd; 3]).unwrap()
+        };
+        let mut expected = vec![0xdd; 3];
+        expected.extend(SeededRng::from_u64(7).random_vec(12).unwrap());
+        expected.push(13);
+        assert_eq!(pad(), expected);
+    }
+
+    #[test]
+    fn strict_unpad() {
+        let block = |tail: &str| {
+            let mut block = vec![0xdd; 16 - tail.len() / 2];
+            block.extend(from_hex(tail).unwrap());
+            block
+        };
+        assert!(Pkcs7Padding.unpad(block("030203")).is_err());
+        assert!(Pkcs7Padding.unpad(block("00")).is_err());
+        assert!(Pkcs7Padding.unpad(block("11")).is_err());
+        assert!(AnsiX923Padding.unpad(block("000103")).is_err());
+        assert!(AnsiX923Padding.unpad(block("030303")).is_err());
+        assert!(Iso10126Padding::default().unpad(block("ff")).is_err());
+        assert!(Iso7816Padding.unpad(block("800001")).is_err());
+        assert!(Iso7816Padding.unpad(vec![0; 16]).is_err());
+        assert!(ZeroPadding.unpad(vec![0; 16]).is_err());
+        for scheme in schemes() {
+            assert!(scheme.unpad(Vec::new()).is_err());
+            assert!(matches!(
+                scheme.unpad(vec![1; 17]),
+                Err(Error::InvalidLength(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn zero_padding() {
+        let mut zero = ZeroPadding;
+        assert_eq!(zero.pad(b"").unwrap(), b"");
+        assert_eq!(zero.pad(&[1; 16]).unwrap(), vec![1; 16]);
+        let padded = zero.pad(b"abc").unwrap();
+        assert_eq!(zero.unpad(padded).unwrap(), b"abc");
+        let padded = zero.pad(b"a\0b").unwrap();
+        assert_eq!(zero.unpad(padded).unwrap(), b"a\0b");
+        assert_eq!(zero.pad(b"abc\0"), Err(Error::InvalidPadding));
+    }
+
+    #[test]
+    fn parse_names() {
+        for name in ["zero", "pkcs7", "ansix923", "iso10126", "iso7816"] {
+            assert!(parse(name, Box::new(OsRng)).is_ok());
+        }
+        assert!(parse("none", Box::new(OsRng)).is_err());
+    }
+}
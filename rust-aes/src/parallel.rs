@@ -0,0 +1,256 @@
+use std::convert::Infallible;
+use std::thread;
+
+use crate::aes::BlockCipher;
+use crate::error::Error;
+use crate::modes::ctr;
+use crate::modes::stream::{Stream, SEALED_SEGMENT_SIZE, SEGMENT_SIZE};
+use crate::modes::xts::Xts;
+
+// Input the streaming adapters collect per worker before fanning out.
+pub const BATCH_SIZE: usize = 64 * 1024;
+
+// Below this a worker costs more than it saves.
+const MIN_RUN: usize = 4096;
+
+// Splits `data` into contiguous runs of whole `unit`-sized pieces, one run
+// per worker, and concatenates the results in order, so the output never
+// depends on the thread count. `f` gets the index of the run's first unit.
+fn map_runs<E, F>(data: &[u8], unit: usize, threads: usize, f: F) -> Result<Vec<u8>, E>
+where
+    E: Send,
+    F: Fn(usize, &[u8]) -> Result<Vec<u8>, E> + Sync,
+{
+    let units = data.len().div_ceil(unit).max(1);
+    let workers = threads.min(units).min(data.len() / MIN_RUN).max(1);
+    if workers == 1 {
+        return f(0, data);
+    }
+    let per_worker = units.div_ceil(workers);
+    let f = &f;
+    let results: Vec<Result<Vec<u8>, E>> = thread::scope(|scope| {
+        let handles: Vec<_> = data
+            .chunks(per_worker * unit)
+            .enumerate()
+            .map(|(run, piece)| scope.spawn(move || f(run * per_worker, piece)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    });
+    let mut output = Vec::with_capacity(data.len());
+    for result in results {
+        output.extend(result?);
+    }
+    Ok(output)
+}
+
+pub fn ctr(cipher: &BlockCipher, counter: &[u8; 16], data: &[u8], threads: usize) -> Vec<u8> {
+    let result = map_runs(data, 16, threads, |first, piece| {
+        let start = ctr::advance(counter, first as u64);
+        Ok::<_, Infallible>(ctr::apply(cipher, &start, piece))
+    });
+    result.unwrap_or_else(|never| match never {})
+}
+
+pub fn ecb_encrypt(cipher: &BlockCipher, data: &[u8], threads: usize) -> Result<Vec<u8>, Error> {
+    ecb(data, threads, |block| cipher.encrypt_block(block))
+}
+
+pub fn ecb_decrypt(cipher: &BlockCipher, data: &[u8], threads: usize) -> Result<Vec<u8>, Error> {
+    ecb(data, threads, |block| cipher.decrypt_block(block))
+}
+
+fn ecb<F>(data: &[u8], threads: usize, apply: F) -> Result<Vec<u8>, Error>
+where
+    F: Fn(&[u8; 16]) -> [u8; 16] + Sync,
+{
+    if !data.len().is_multiple_of(16) {
+        return Err(Error::InvalidLength(
+            "ECB input is not a whole number of blocks".to_string(),
+        ));
+    }
+    map_runs(data, 16, threads, |_, piece| {
+        let mut output = Vec::with_capacity(piece.len());
+        for chunk in piece.chunks(16) {
+            let mut block = [0; 16];
+            block.copy_from_slice(chunk);
+            output.extend_from_slice(&apply(&block));
+        }
+        Ok(output)
+    })
+}
+
+pub fn xts_encrypt(
+    xts: &Xts,
+    first_sector: u128,
+    sector_size: usize,
+    data: &[u8],
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    check_sector_size(sector_size)?;
+    map_runs(data, sector_size, threads, |first, piece| {
+        xts.encrypt(first_sector.wrapping_add(first as u128), sector_size, piece)
+    })
+}
+
+pub fn xts_decrypt(
+    xts: &Xts,
+    first_sector: u128,
+    sector_size: usize,
+    data: &[u8],
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    check_sector_size(sector_size)?;
+    map_runs(data, sector_size, threads, |first, piece| {
+        xts.decrypt(first_sector.wrapping_add(first as u128), sector_size, piece)
+    })
+}
+
+fn check_sector_size(sector_size: usize) -> Result<(), Error> {
+    if sector_size < 16 {
+        return Err(Error::InvalidLength(
+            "XTS sectors are at least 16 bytes".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Seals consecutive STREAM segments starting at `first_index`. With `last`
+// the final segment, which may be short or empty, carries the last flag;
+// otherwise `data` must be a whole number of segments.
+pub fn seal_segments(
+    stream: &Stream,
+    first_index: u32,
+    aad: &[u8],
+    data: &[u8],
+    last: bool,
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    if !last && !data.len().is_multiple_of(SEGMENT_SIZE) {
+        return Err(Error::InvalidLength(
+            "only the last STREAM segment may be short".to_string(),
+        ));
+    }
+    if data.is_empty() {
+        return Ok(if last {
+            stream.seal_segment(first_index, true, aad, data)
+        } else {
+            Vec::new()
+        });
+    }
+    let count = data.len().div_ceil(SEGMENT_SIZE);
+    map_runs(data, SEGMENT_SIZE, threads, |first, piece| {
+        let mut output = Vec::with_capacity(piece.len() + count * 16);
+        for (offset, segment) in piece.chunks(SEGMENT_SIZE).enumerate() {
+            let position = first + offset;
+            let index = segment_index(first_index, position)?;
+            let is_last = last && position == count - 1;
+            output.extend(stream.seal_segment(index, is_last, aad, segment));
+        }
+        Ok(output)
+    })
+}
+
+// The inverse of seal_segments over sealed segments of SEALED_SEGMENT_SIZE.
+pub fn open_segments(
+    stream: &Stream,
+    first_index: u32,
+    aad: &[u8],
+    sealed: &[u8],
+    last: bool,
+    threads: usize,
+) -> Result<Vec<u8>, Error> {
+    if sealed.is_empty() {
+        return if last {
+            stream.open_segment(first_index, true, aad, sealed)
+        } else {
+            Ok(Vec::new())
+        };
+    }
+    let count = sealed.len().div_ceil(SEALED_SEGMENT_SIZE);
+    map_runs(sealed, SEALED_SEGMENT_SIZE, threads, |first, piece| {
+        let mut output = Vec::with_capacity(piece.len());
+        for (offset, segment) in piece.chunks(SEALED_SEGMENT_SIZE).enumerate() {
+            let position = first + offset;
+            let index = segment_index(first_index, position)?;
+            let is_last = last && position == count - 1;
+            output.extend(stream.open_segment(index, is_last, aad, segment)?);
+        }
+        Ok(output)
+    })
+}
+
+fn segment_index(first_index: u32, position: usize) -> Result<u32, Error> {
+    u32::try_from(position)
+        .ok()
+        .and_then(|position| first_index.checked_add(position))
+        .ok_or_else(|| Error::InvalidLength("too many STREAM segments".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::Key;
+    use crate::modes::stream::NONCE_PREFIX_LEN;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn ctr_and_ecb_match_sequential() {
+        let cipher = BlockCipher::new(&Key::from_bytes(&[9; 16]));
+        let counter = [0xff; 16];
+        for len in [0, 15, 16, 4096 * 3 + 5, 40_000] {
+            let data = data(len);
+            let sequential = ctr::apply(&cipher, &counter, &data);
+            for threads in [1, 2, 4, 7] {
+                assert_eq!(ctr(&cipher, &counter, &data, threads), sequential);
+            }
+        }
+
+        let blocks = data(40_000 / 16 * 16);
+        let sequential = ecb_encrypt(&cipher, &blocks, 1).unwrap();
+        let threaded = ecb_encrypt(&cipher, &blocks, 4).unwrap();
+        assert_eq!(threaded, sequential);
+        assert_eq!(ecb_decrypt(&cipher, &threaded, 3).unwrap(), blocks);
+        assert!(ecb_encrypt(&cipher, &blocks[..17], 2).is_err());
+    }
+
+    #[test]
+    fn xts_matches_sequential() {
+        let xts = Xts::new(&[3; 64]).unwrap();
+        let data = data(512 * 20 + 100);
+        let sequential = xts.encrypt(1 << 70, 512, &data).unwrap();
+        let threaded = xts_encrypt(&xts, 1 << 70, 512, &data, 4).unwrap();
+        assert_eq!(threaded, sequential);
+        assert_eq!(xts_decrypt(&xts, 1 << 70, 512, &threaded, 3).unwrap(), data);
+    }
+
+    #[test]
+    fn segments_match_sequential() {
+        let stream = Stream::new(&Key::from_bytes(&[1; 16]), &[2; NONCE_PREFIX_LEN]).unwrap();
+        let data = data(SEGMENT_SIZE * 3 + 10);
+        let mut sequential = Vec::new();
+        for (index, segment) in data.chunks(SEGMENT_SIZE).enumerate() {
+            sequential.extend(stream.seal_segment(5 + index as u32, index == 3, b"aad", segment));
+        }
+        let sealed = seal_segments(&stream, 5, b"aad", &data, true, 4).unwrap();
+        assert_eq!(sealed, sequential);
+        assert_eq!(
+            open_segments(&stream, 5, b"aad", &sealed, true, 2).unwrap(),
+            data
+        );
+        assert_eq!(
+            open_segments(&stream, 5, b"aad", &sealed, false, 2),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            seal_segments(&stream, 0, b"", b"", true, 4).unwrap(),
+            stream.seal_segment(0, true, b"", b"")
+        );
+        assert!(seal_segments(&stream, 0, b"", &data, false, 4).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+// Packet and record protection for network protocols.
+pub mod quic;
+pub mod srtp;
+pub mod tls;
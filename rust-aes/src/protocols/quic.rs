@@ -0,0 +1,566 @@
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Sha256};
+use crate::kdf::hkdf;
+use crate::modes::gcm::{Gcm, TAG_LEN};
+use crate::protocols::tls::expand_label;
+
+// QUIC version 1 packet protection with AES-128-GCM, RFC 9001 section 5.
+// The payload is sealed with the unprotected header as associated data and
+// the IV XORed with the packet number as nonce. Header protection then
+// encrypts a 16-byte ciphertext sample with single-block AES under the "hp"
+// key and XORs the result over the low bits of the first byte and over the
+// packet number bytes.
+pub const VERSION: u32 = 1;
+pub const INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+pub const IV_LEN: usize = 12;
+pub const SAMPLE_LEN: usize = 16;
+pub const MAX_CID_LEN: usize = 20;
+// Fixed key and nonce for Retry integrity tags, section 5.8.
+const RETRY_KEY: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+const RETRY_NONCE: [u8; IV_LEN] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+const KEY_LEN: usize = 16;
+// The sample is taken as if the packet number were four bytes long.
+const MAX_PN_LEN: usize = 4;
+const LONG_HEADER: u8 = 0x80;
+const MAX_PACKET_NUMBER: u64 = (1 << 62) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+// The secret protecting Initial packets sent by `side`, derived from the
+// Destination Connection ID of the client's first Initial.
+pub fn initial_secret(dcid: &[u8], side: Side) -> Result<Vec<u8>, Error> {
+    let initial = hkdf::extract::<Sha256>(&INITIAL_SALT, dcid);
+    let label = match side {
+        Side::Client => "client in",
+        Side::Server => "server in",
+    };
+    expand_label(&initial, label, &[], 32)
+}
+
+// The secret for the next key phase, section 6.1. Header protection keeps
+// the original "hp" key.
+pub fn next_secret(secret: &[u8]) -> Result<Vec<u8>, Error> {
+    expand_label(secret, "quic ku", &[], 32)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keys {
+    pub key: Vec<u8>,
+    pub iv: [u8; IV_LEN],
+    pub hp: Vec<u8>,
+}
+
+impl Keys {
+    pub fn derive(secret: &[u8]) -> Result<Keys, Error> {
+        let mut iv = [0; IV_LEN];
+        iv.copy_from_slice(&expand_label(secret, "quic iv", &[], IV_LEN)?);
+        Ok(Keys {
+            key: expand_label(secret, "quic key", &[], KEY_LEN)?,
+            iv,
+            hp: expand_label(secret, "quic hp", &[], KEY_LEN)?,
+        })
+    }
+
+    pub fn initial(dcid: &[u8], side: Side) -> Result<Keys, Error> {
+        Keys::derive(&initial_secret(dcid, side)?)
+    }
+}
+
+pub struct HeaderProtection {
+    cipher: BlockCipher,
+}
+
+impl HeaderProtection {
+    pub fn new(hp: &[u8]) -> Result<HeaderProtection, Error> {
+        if hp.len() != KEY_LEN {
+            return Err(Error::InvalidLength(format!(
+                "QUIC header protection keys are {} bytes, got {}",
+                KEY_LEN,
+                hp.len()
+            )));
+        }
+        Ok(HeaderProtection {
+            cipher: BlockCipher::new(&Key::from_bytes(hp)),
+        })
+    }
+
+    // The first five bytes of AES-ECB(hp, sample).
+    pub fn mask(&self, sample: &[u8; SAMPLE_LEN]) -> [u8; 5] {
+        let block = self.cipher.encrypt_block(sample);
+        let mut mask = [0; 5];
+        mask.copy_from_slice(&block[..5]);
+        mask
+    }
+
+    // Protects a sealed packet whose header is still in the clear.
+    fn apply(&self, packet: &mut [u8], pn_offset: usize, pn_len: usize) {
+        let mut sample = [0; SAMPLE_LEN];
+        sample.copy_from_slice(&packet[pn_offset + MAX_PN_LEN..][..SAMPLE_LEN]);
+        let mask = self.mask(&sample);
+        packet[0] ^= mask[0] & first_byte_bits(packet[0]);
+        for (byte, mask) in packet[pn_offset..pn_offset + pn_len]
+            .iter_mut()
+            .zip(&mask[1..])
+        {
+            *byte ^= mask;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    // The unprotected header, ending with the truncated packet number.
+    pub header: Vec<u8>,
+    pub packet_number: u64,
+    pub payload: Vec<u8>,
+}
+
+pub struct PacketProtection {
+    aead: Gcm,
+    iv: [u8; IV_LEN],
+    header: HeaderProtection,
+}
+
+impl PacketProtection {
+    pub fn new(keys: &Keys) -> Result<PacketProtection, Error> {
+        if keys.key.len() != KEY_LEN {
+            return Err(Error::InvalidLength(format!(
+                "QUIC packet keys are {} bytes, got {}",
+                KEY_LEN,
+                keys.key.len()
+            )));
+        }
+        Ok(PacketProtection {
+            aead: Gcm::new(&Key::from_bytes(&keys.key)),
+            iv: keys.iv,
+            header: HeaderProtection::new(&keys.hp)?,
+        })
+    }
+
+    // `header` is the unprotected header, including any Length field and the
+    // truncated packet number whose length the first byte gives.
+    // `packet_number` is the full number.
+    pub fn seal(
+        &self,
+        header: &[u8],
+        packet_number: u64,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let pn_len = match header.first() {
+            Some(first) => (first & 0x03) as usize + 1,
+            None => return Err(invalid("empty header")),
+        };
+        if header.len() < 1 + pn_len || packet_number > MAX_PACKET_NUMBER {
+            return Err(invalid("header too short or packet number too large"));
+        }
+        let pn_offset = header.len() - pn_len;
+        if payload.len() + TAG_LEN + pn_len < MAX_PN_LEN + SAMPLE_LEN {
+            return Err(invalid("payload too short to sample; add PADDING"));
+        }
+        let mut packet = header.to_vec();
+        packet.extend(
+            self.aead
+                .seal(&self.nonce(packet_number), header, payload)?,
+        );
+        self.header.apply(&mut packet, pn_offset, pn_len);
+        Ok(packet)
+    }
+
+    // Opens the packet at the start of `data` and returns it with the number
+    // of bytes it took up, since long header packets can be coalesced.
+    // `dcid_len` is the length of this endpoint's connection IDs, needed for
+    // short headers, and `largest_pn` the largest packet number received so
+    // far in this packet number space.
+    pub fn open(
+        &self,
+        data: &[u8],
+        dcid_len: usize,
+        largest_pn: Option<u64>,
+    ) -> Result<(Packet, usize), Error> {
+        let (pn_offset, end) = layout(data, dcid_len)?;
+        if end < pn_offset + MAX_PN_LEN + SAMPLE_LEN {
+            return Err(invalid("packet too short to sample"));
+        }
+        let mut packet = data[..end].to_vec();
+        let mut sample = [0; SAMPLE_LEN];
+        sample.copy_from_slice(&packet[pn_offset + MAX_PN_LEN..][..SAMPLE_LEN]);
+        let mask = self.header.mask(&sample);
+        packet[0] ^= mask[0] & first_byte_bits(packet[0]);
+        let pn_len = (packet[0] & 0x03) as usize + 1;
+        let mut truncated = 0;
+        for (byte, mask) in packet[pn_offset..pn_offset + pn_len]
+            .iter_mut()
+            .zip(&mask[1..])
+        {
+            *byte ^= mask;
+            truncated = (truncated << 8) | *byte as u64;
+        }
+        let packet_number = decode_packet_number(largest_pn, truncated, pn_len * 8);
+        let (header, sealed) = packet.split_at(pn_offset + pn_len);
+        let payload = self.aead.open(&self.nonce(packet_number), header, sealed)?;
+        let packet = Packet {
+            header: header.to_vec(),
+            packet_number,
+            payload,
+        };
+        Ok((packet, end))
+    }
+
+    fn nonce(&self, packet_number: u64) -> [u8; IV_LEN] {
+        let mut nonce = self.iv;
+        for (byte, pn) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *byte ^= pn;
+        }
+        nonce
+    }
+}
+
+// Recovers a full packet number from its truncated encoding, RFC 9000
+// appendix A.3: the candidate closest to the next expected number.
+pub fn decode_packet_number(largest_pn: Option<u64>, truncated: u64, bits: usize) -> u64 {
+    let expected = largest_pn.map_or(0, |largest| largest + 1);
+    let window = 1u64 << bits;
+    let half = window / 2;
+    let candidate = (expected & !(window - 1)) | truncated;
+    if candidate + half <= expected && candidate < (1 << 62) - window {
+        candidate + window
+    } else if candidate > expected + half && candidate >= window {
+        candidate - window
+    } else {
+        candidate
+    }
+}
+
+// The tag ending a Retry packet, computed over the client's original
+// Destination Connection ID and the rest of the Retry packet.
+pub fn retry_integrity_tag(original_dcid: &[u8], retry: &[u8]) -> Result<[u8; TAG_LEN], Error> {
+    if original_dcid.len() > MAX_CID_LEN {
+        return Err(invalid("connection ID too long"));
+    }
+    let mut pseudo = vec![original_dcid.len() as u8];
+    pseudo.extend_from_slice(original_dcid);
+    pseudo.extend_from_slice(retry);
+    let sealed = Gcm::new(&Key::from_bytes(&RETRY_KEY)).seal(&RETRY_NONCE, &pseudo, &[])?;
+    let mut tag = [0; TAG_LEN];
+    tag.copy_from_slice(&sealed);
+    Ok(tag)
+}
+
+pub fn verify_retry(original_dcid: &[u8], packet: &[u8]) -> Result<(), Error> {
+    if packet.len() < TAG_LEN {
+        return Err(invalid("Retry packet too short"));
+    }
+    let (retry, tag) = packet.split_at(packet.len() - TAG_LEN);
+    if !constant_time_eq(&retry_integrity_tag(original_dcid, retry)?, tag) {
+        return Err(Error::Authentication);
+    }
+    Ok(())
+}
+
+// Long headers protect four bits of the first byte, short headers five.
+fn first_byte_bits(first: u8) -> u8 {
+    if first & LONG_HEADER != 0 {
+        0x0f
+    } else {
+        0x1f
+    }
+}
+
+// Where the packet number starts and where the packet ends.
+fn layout(data: &[u8], dcid_len: usize) -> Result<(usize, usize), Error> {
+    let first = *data.first().ok_or_else(|| invalid("empty packet"))?;
+    if first & LONG_HEADER == 0 {
+        return Ok((1 + dcid_len, data.len()));
+    }
+    let mut reader = Reader { data, position: 1 };
+    let version = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported version {:#x}", version)));
+    }
+    for _ in 0..2 {
+        let len = reader.take(1)?[0] as usize;
+        if len > MAX_CID_LEN {
+            return Err(invalid("connection ID too long"));
+        }
+        reader.take(len)?;
+    }
+    match (first >> 4) & 0x03 {
+        // Initial packets carry a token.
+        0 => {
+            let len = reader.varint()?;
+            reader.take(usize::try_from(len).map_err(|_| invalid("token too long"))?)?;
+        }
+        1 | 2 => {}
+        _ => return Err(invalid("Retry packets are not protected")),
+    }
+    let len = reader.varint()?;
+    let pn_offset = reader.position;
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(|len| pn_offset.checked_add(len))
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| invalid("packet length past the end of the data"))?;
+    Ok((pn_offset, end))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.position..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| invalid("truncated header"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    // RFC 9000 section 16: the top two bits give the length.
+    fn varint(&mut self) -> Result<u64, Error> {
+        let first = self.take(1)?[0];
+        let len = 1 << (first >> 6);
+        let mut value = (first & 0x3f) as u64;
+        for &byte in self.take(len - 1)? {
+            value = (value << 8) | byte as u64;
+        }
+        Ok(value)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("QUIC: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    // RFC 9001 appendix A
+    const DCID: &str = "8394c8f03e515708";
+    const SERVER_PAYLOAD: &str = "02000000000600405a020000560303eefce7f7b37ba1d1632e96677825ddf739\
+                                  88cfc79825df566dc5430b9a045a1200130100002e00330024001d00209d3c94\
+                                  0d89690b84d08a60993c144eca684d1081287c834d5311bcf32bb9da1a002b00\
+                                  020304";
+    const SERVER_PACKET: &str = "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a\
+                                 5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3\
+                                 dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84\
+                                 022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4\
+                                 2158407dd074ee";
+    // The CRYPTO frame of A.2, padded to 1162 bytes.
+    const CLIENT_CRYPTO: &str = "060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e868\
+                                 04fe3a47f06a2b69484c00000413011302010000c000000010000e00000b6578\
+                                 616d706c652e636f6dff01000100000a00080006001d00170018001000070005\
+                                 04616c706e000500050100000000003300260024001d00209370b2c9caa47fba\
+                                 baf4559fedba753de171fa71f50f1ce15d43e994ec74d748002b000302030400\
+                                 0d0010000e0403050306030203080408050806002d00020101001c0002400100\
+                                 3900320408ffffffffffffffff05048000ffff07048000ffff08011001048000\
+                                 75300901100f088394c8f03e51570806048000ffff";
+    const CLIENT_PACKET: &str = "c000000001088394c8f03e5157080000449e7b9aec34d1b1c98dd7689fb8ec11\
+                                 d242b123dc9bd8bab936b47d92ec356c0bab7df5976d27cd449f63300099f399\
+                                 1c260ec4c60d17b31f8429157bb35a1282a643a8d2262cad67500cadb8e7378c\
+                                 8eb7539ec4d4905fed1bee1fc8aafba17c750e2c7ace01e6005f80fcb7df6212\
+                                 30c83711b39343fa028cea7f7fb5ff89eac2308249a02252155e2347b63d58c5\
+                                 457afd84d05dfffdb20392844ae812154682e9cf012f9021a6f0be17ddd0c208\
+                                 4dce25ff9b06cde535d0f920a2db1bf362c23e596d11a4f5a6cf3948838a3aec\
+                                 4e15daf8500a6ef69ec4e3feb6b1d98e610ac8b7ec3faf6ad760b7bad1db4ba3\
+                                 485e8a94dc250ae3fdb41ed15fb6a8e5eba0fc3dd60bc8e30c5c4287e53805db\
+                                 059ae0648db2f64264ed5e39be2e20d82df566da8dd5998ccabdae053060ae6c\
+                                 7b4378e846d29f37ed7b4ea9ec5d82e7961b7f25a9323851f681d582363aa5f8\
+                                 9937f5a67258bf63ad6f1a0b1d96dbd4faddfcefc5266ba6611722395c906556\
+                                 be52afe3f565636ad1b17d508b73d8743eeb524be22b3dcbc2c7468d54119c74\
+                                 68449a13d8e3b95811a198f3491de3e7fe942b330407abf82a4ed7c1b311663a\
+                                 c69890f4157015853d91e923037c227a33cdd5ec281ca3f79c44546b9d90ca00\
+                                 f064c99e3dd97911d39fe9c5d0b23a229a234cb36186c4819e8b9c5927726632\
+                                 291d6a418211cc2962e20fe47feb3edf330f2c603a9d48c0fcb5699dbfe58964\
+                                 25c5bac4aee82e57a85aaf4e2513e4f05796b07ba2ee47d80506f8d2c25e50fd\
+                                 14de71e6c418559302f939b0e1abd576f279c4b2e0feb85c1f28ff18f58891ff\
+                                 ef132eef2fa09346aee33c28eb130ff28f5b766953334113211996d20011a198\
+                                 e3fc433f9f2541010ae17c1bf202580f6047472fb36857fe843b19f5984009dd\
+                                 c324044e847a4f4a0ab34f719595de37252d6235365e9b84392b061085349d73\
+                                 203a4a13e96f5432ec0fd4a1ee65accdd5e3904df54c1da510b0ff20dcc0c77f\
+                                 cb2c0e0eb605cb0504db87632cf3d8b4dae6e705769d1de354270123cb11450e\
+                                 fc60ac47683d7b8d0f811365565fd98c4c8eb936bcab8d069fc33bd801b03ade\
+                                 a2e1fbc5aa463d08ca19896d2bf59a071b851e6c239052172f296bfb5e724047\
+                                 90a2181014f3b94a4e97d117b438130368cc39dbb2d198065ae3986547926cd2\
+                                 162f40a29f0c3c8745c0f50fba3852e566d44575c29d39a03f0cda721984b6f4\
+                                 40591f355e12d439ff150aab7613499dbd49adabc8676eef023b15b65bfc5ca0\
+                                 6948109f23f350db82123535eb8a7433bdabcb909271a6ecbcb58b936a88cd4e\
+                                 8f2e6ff5800175f113253d8fa9ca8885c2f552e657dc603f252e1a8e308f76f0\
+                                 be79e2fb8f5d5fbbe2e30ecadd220723c8c0aea8078cdfcb3868263ff8f09400\
+                                 54da48781893a7e49ad5aff4af300cd804a6b6279ab3ff3afb64491c85194aab\
+                                 760d58a606654f9f4400e8b38591356fbf6425aca26dc85244259ff2b19c41b9\
+                                 f96f3ca9ec1dde434da7d2d392b905ddf3d1f9af93d1af5950bd493f5aa731b4\
+                                 056df31bd267b6b90a079831aaf579be0a39013137aac6d404f518cfd4684064\
+                                 7e78bfe706ca4cf5e9c5453e9f7cfd2b8b4c8d169a44e55c88d4a9a7f9474241\
+                                 e221af44860018ab0856972e194cd934";
+
+    fn hex(text: &str) -> Vec<u8> {
+        from_hex(text).unwrap()
+    }
+
+    #[test]
+    fn initial_keys() {
+        let dcid = hex(DCID);
+        assert_eq!(
+            to_hex(&initial_secret(&dcid, Side::Client).unwrap()),
+            "c00cf151ca5be075ed0ebfb5c80323c42d6b7db67881289af4008f1f6c357aea"
+        );
+        assert_eq!(
+            to_hex(&initial_secret(&dcid, Side::Server).unwrap()),
+            "3c199828fd139efd216c155ad844cc81fb82fa8d7446fa7d78be803acdda951b"
+        );
+        let client = Keys::initial(&dcid, Side::Client).unwrap();
+        assert_eq!(to_hex(&client.key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(to_hex(&client.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(to_hex(&client.hp), "9f50449e04a0e810283a1e9933adedd2");
+        let server = Keys::initial(&dcid, Side::Server).unwrap();
+        assert_eq!(to_hex(&server.key), "cf3a5331653c364c88f0f379b6067e37");
+        assert_eq!(to_hex(&server.iv), "0ac1493ca1905853b0bba03e");
+        assert_eq!(to_hex(&server.hp), "c206b8d9b9f0f37644430b490eeaa314");
+    }
+
+    // The client Initial's sample and mask, A.2
+    #[test]
+    fn header_protection_mask() {
+        let keys = Keys::initial(&hex(DCID), Side::Client).unwrap();
+        let mut sample = [0; SAMPLE_LEN];
+        sample.copy_from_slice(&hex("d1b1c98dd7689fb8ec11d242b123dc9b"));
+        let mask = HeaderProtection::new(&keys.hp).unwrap().mask(&sample);
+        assert_eq!(to_hex(&mask), "437b9aec36");
+    }
+
+    // The server Initial, A.3
+    #[test]
+    fn server_initial() {
+        let keys = Keys::initial(&hex(DCID), Side::Server).unwrap();
+        let protection = PacketProtection::new(&keys).unwrap();
+        let header = hex("c1000000010008f067a5502a4262b50040750001");
+        let packet = protection.seal(&header, 1, &hex(SERVER_PAYLOAD)).unwrap();
+        assert_eq!(to_hex(&packet), SERVER_PACKET);
+
+        let coalesced = [&packet[..], &[0; 7]].concat();
+        let (opened, len) = protection.open(&coalesced, 0, None).unwrap();
+        assert_eq!(len, packet.len());
+        assert_eq!(
+            opened,
+            Packet {
+                header,
+                packet_number: 1,
+                payload: hex(SERVER_PAYLOAD),
+            }
+        );
+
+        let mut tampered = packet.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            protection.open(&tampered, 0, None),
+            Err(Error::Authentication)
+        );
+        let client = PacketProtection::new(&Keys::initial(&hex(DCID), Side::Client).unwrap());
+        assert!(client.unwrap().open(&packet, 0, None).is_err());
+    }
+
+    #[test]
+    fn client_initial() {
+        let keys = Keys::initial(&hex(DCID), Side::Client).unwrap();
+        let protection = PacketProtection::new(&keys).unwrap();
+        // A 4-byte packet number and 1182 bytes after the Length.
+        let header = hex("c300000001088394c8f03e5157080000449e00000002");
+        let mut payload = hex(CLIENT_CRYPTO);
+        payload.resize(1162, 0);
+        let packet = protection.seal(&header, 2, &payload).unwrap();
+        assert_eq!(to_hex(&packet), CLIENT_PACKET);
+        let (opened, len) = protection.open(&packet, 0, Some(1)).unwrap();
+        assert_eq!(len, 1200);
+        assert_eq!(opened.packet_number, 2);
+        assert_eq!(opened.header, header);
+        assert_eq!(opened.payload, payload);
+    }
+
+    #[test]
+    fn short_header() {
+        let protection = PacketProtection::new(&Keys::derive(&[5; 32]).unwrap()).unwrap();
+        // Fixed bit, key phase 0, 2-byte packet number 0x9b32, 8-byte CID.
+        let header = hex("41aaaaaaaaaaaaaaaa9b32");
+        let packet = protection.seal(&header, 0xa82f_9b32, b"ping").unwrap();
+        let (opened, len) = protection.open(&packet, 8, Some(0xa82f_30ea)).unwrap();
+        assert_eq!(len, packet.len());
+        assert_eq!(opened.packet_number, 0xa82f_9b32);
+        assert_eq!(opened.payload, b"ping");
+        assert!(protection.seal(&header, 1, b"").is_err());
+    }
+
+    // RFC 9000 appendix A.3
+    #[test]
+    fn packet_numbers() {
+        assert_eq!(
+            decode_packet_number(Some(0xa82f_30ea), 0x9b32, 16),
+            0xa82f_9b32
+        );
+        assert_eq!(decode_packet_number(None, 0, 8), 0);
+        assert_eq!(decode_packet_number(Some(0xff), 0x01, 8), 0x101);
+        assert_eq!(decode_packet_number(Some(0x101), 0xff, 8), 0xff);
+    }
+
+    // A.4 and A.5
+    #[test]
+    fn retry_and_key_update() {
+        let retry = hex("ff000000010008f067a5502a4262b5746f6b656e04a265ba2eff4d829058fb3f0f2496ba");
+        assert_eq!(verify_retry(&hex(DCID), &retry), Ok(()));
+        assert_eq!(
+            verify_retry(&hex("8394c8f03e515709"), &retry),
+            Err(Error::Authentication)
+        );
+        assert_eq!(
+            to_hex(
+                &next_secret(&hex(
+                    "9ac312a7f877468ebe69422748ad00a15443f18203a07d6060f688f30f21632b"
+                ))
+                .unwrap()
+            ),
+            "1223504755036d556342ee9361d253421a826c9ecdf3c7148684b36b714881f9"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let protection = PacketProtection::new(&Keys::derive(&[5; 32]).unwrap()).unwrap();
+        let packet = hex(SERVER_PACKET);
+        let mut version = packet.clone();
+        version[4] = 2;
+        let mut retry = packet.clone();
+        retry[0] |= 0x30;
+        let mut length = packet.clone();
+        length[17] = 0x76;
+        for data in [
+            Vec::new(),
+            version,
+            retry,
+            length,
+            packet[..30].to_vec(),
+            hex("40aaaa"),
+        ] {
+            assert!(matches!(
+                protection.open(&data, 8, None),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+}
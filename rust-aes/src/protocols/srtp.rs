@@ -0,0 +1,734 @@
+use std::collections::HashMap;
+
+use crate::aes::{BlockCipher, Key};
+use crate::error::Error;
+use crate::hash::{constant_time_eq, Hmac, Sha1};
+use crate::modes::ctr;
+
+// SRTP and SRTCP, RFC 3711. Session keys, salts and HMAC-SHA1 keys come
+// from the master key and salt through the AES-CM key derivation function.
+// RTP payloads are encrypted after the header, and the authentication tag
+// covers the packet plus the 32-bit rollover counter (ROC) that extends the
+// 16-bit sequence number to a 48-bit index. SRTCP encrypts everything after
+// the first eight bytes and appends an E flag with a 31-bit SRTCP index.
+pub const SALT_LEN: usize = 14;
+pub const AUTH_KEY_LEN: usize = 20;
+pub const REPLAY_WINDOW: u64 = 64;
+pub const MAX_SRTCP_INDEX: u32 = (1 << 31) - 1;
+const RTP_VERSION: u8 = 2;
+const RTP_HEADER_LEN: usize = 12;
+const RTCP_HEADER_LEN: usize = 8;
+const SRTCP_TRAILER_LEN: usize = 4;
+const ENCRYPTED_FLAG: u32 = 1 << 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    // AES in counter mode with a 16-bit block counter.
+    AesCm,
+    AesF8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub encryption: Encryption,
+    // Truncated HMAC-SHA1 tag lengths.
+    pub rtp_tag_len: usize,
+    pub rtcp_tag_len: usize,
+}
+
+impl Policy {
+    // The SDES crypto suites of RFC 4568; SRTCP always uses 80-bit tags.
+    pub const AES_CM_128_HMAC_SHA1_80: Policy = Policy {
+        encryption: Encryption::AesCm,
+        rtp_tag_len: 10,
+        rtcp_tag_len: 10,
+    };
+    pub const AES_CM_128_HMAC_SHA1_32: Policy = Policy {
+        encryption: Encryption::AesCm,
+        rtp_tag_len: 4,
+        rtcp_tag_len: 10,
+    };
+    pub const F8_128_HMAC_SHA1_80: Policy = Policy {
+        encryption: Encryption::AesF8,
+        rtp_tag_len: 10,
+        rtcp_tag_len: 10,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    RtpEncryption,
+    RtpAuthentication,
+    RtpSalt,
+    RtcpEncryption,
+    RtcpAuthentication,
+    RtcpSalt,
+}
+
+impl Label {
+    fn code(&self) -> u8 {
+        match self {
+            Label::RtpEncryption => 0,
+            Label::RtpAuthentication => 1,
+            Label::RtpSalt => 2,
+            Label::RtcpEncryption => 3,
+            Label::RtcpAuthentication => 4,
+            Label::RtcpSalt => 5,
+        }
+    }
+}
+
+// The key derivation function of section 4.3: AES-CM keystream under the
+// master key, starting from the master salt XORed with the label and
+// `index / key_derivation_rate` (zero when the rate is zero).
+pub fn derive_key(
+    master_key: &[u8],
+    master_salt: &[u8],
+    label: Label,
+    index: u64,
+    key_derivation_rate: u64,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    check_master(master_key, master_salt)?;
+    let r = index.checked_div(key_derivation_rate).unwrap_or(0);
+    let key_id = ((label.code() as u128) << 48) | (r as u128 & 0xffff_ffff_ffff);
+    let x = salt_value(master_salt) ^ key_id;
+    let cipher = BlockCipher::new(&Key::from_bytes(master_key));
+    Ok(ctr::apply(
+        &cipher,
+        &(x << 16).to_be_bytes(),
+        &vec![0; length],
+    ))
+}
+
+// AES-f8 keystream XORed over `data`, section 4.1.2. `iv_cipher` is keyed
+// with the session key XORed with the salt padded out with 0x55 bytes.
+fn f8(cipher: &BlockCipher, iv_cipher: &BlockCipher, iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let iv = u128::from_be_bytes(iv_cipher.encrypt_block(iv));
+    let mut stream = 0;
+    let mut output = Vec::with_capacity(data.len());
+    for (j, chunk) in data.chunks(16).enumerate() {
+        stream =
+            u128::from_be_bytes(cipher.encrypt_block(&(iv ^ j as u128 ^ stream).to_be_bytes()));
+        output.extend(chunk.iter().zip(stream.to_be_bytes()).map(|(a, b)| a ^ b));
+    }
+    output
+}
+
+struct SessionKeys {
+    cipher: BlockCipher,
+    // Only for AES-f8.
+    iv_cipher: Option<BlockCipher>,
+    salt: u128,
+    auth_key: Vec<u8>,
+}
+
+impl SessionKeys {
+    fn derive(
+        master_key: &[u8],
+        master_salt: &[u8],
+        labels: [Label; 3],
+        encryption: Encryption,
+    ) -> Result<SessionKeys, Error> {
+        let derive = |label, length| derive_key(master_key, master_salt, label, 0, 0, length);
+        let key = derive(labels[0], master_key.len())?;
+        let salt = derive(labels[2], SALT_LEN)?;
+        let iv_cipher = match encryption {
+            Encryption::AesCm => None,
+            Encryption::AesF8 => {
+                let mut masked = salt.clone();
+                masked.resize(key.len(), 0x55);
+                for (byte, k) in masked.iter_mut().zip(&key) {
+                    *byte ^= k;
+                }
+                Some(BlockCipher::new(&Key::from_bytes(&masked)))
+            }
+        };
+        Ok(SessionKeys {
+            cipher: BlockCipher::new(&Key::from_bytes(&key)),
+            iv_cipher,
+            salt: salt_value(&salt),
+            auth_key: derive(labels[1], AUTH_KEY_LEN)?,
+        })
+    }
+
+    // For AES-CM the IV is the salt, SSRC and index laid over each other;
+    // AES-f8 passes its own IV.
+    fn encrypt(&self, ssrc: u32, index: u64, f8_iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+        match &self.iv_cipher {
+            None => {
+                let iv = (self.salt << 16) ^ ((ssrc as u128) << 64) ^ ((index as u128) << 16);
+                ctr::apply(&self.cipher, &iv.to_be_bytes(), data)
+            }
+            Some(iv_cipher) => f8(&self.cipher, iv_cipher, f8_iv, data),
+        }
+    }
+
+    fn tag(&self, parts: &[&[u8]], tag_len: usize) -> Vec<u8> {
+        let mut hmac = Hmac::<Sha1>::new(&self.auth_key);
+        for part in parts {
+            hmac.update(part);
+        }
+        let mut tag = hmac.finalize();
+        tag.truncate(tag_len);
+        tag
+    }
+}
+
+// Packet indices already seen, relative to the highest one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+    highest: Option<u64>,
+    seen: u64,
+}
+
+impl ReplayWindow {
+    pub fn check(&self, index: u64) -> Result<(), Error> {
+        match self.highest {
+            Some(highest) if index <= highest => {
+                let age = highest - index;
+                if age >= REPLAY_WINDOW || self.seen & (1 << age) != 0 {
+                    return Err(Error::Replay);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn accept(&mut self, index: u64) {
+        match self.highest {
+            Some(highest) if index <= highest => {
+                if highest - index < REPLAY_WINDOW {
+                    self.seen |= 1 << (highest - index);
+                }
+            }
+            Some(highest) => {
+                let shift = index - highest;
+                self.seen = if shift >= REPLAY_WINDOW {
+                    1
+                } else {
+                    (self.seen << shift) | 1
+                };
+                self.highest = Some(index);
+            }
+            None => {
+                self.seen = 1;
+                self.highest = Some(index);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct RtpStream {
+    roc: u32,
+    // The highest sequence number accepted so far.
+    sequence: Option<u16>,
+    window: ReplayWindow,
+}
+
+impl RtpStream {
+    // Section 3.3.1: the index closest to the highest one seen.
+    fn estimate(&self, sequence: u16) -> u64 {
+        let roc = self.roc as i64;
+        let Some(last) = self.sequence else {
+            return (self.roc as u64) << 16 | sequence as u64;
+        };
+        let v = if last < 0x8000 {
+            if sequence as i64 - last as i64 > 0x8000 {
+                roc - 1
+            } else {
+                roc
+            }
+        } else if (last as i64 - 0x8000) > sequence as i64 {
+            roc + 1
+        } else {
+            roc
+        };
+        // Before the first rollover there is no previous ROC to fall back to.
+        ((v.max(0) as u64) << 16) | sequence as u64
+    }
+
+    fn accept(&mut self, index: u64) {
+        let roc = (index >> 16) as u32;
+        let sequence = index as u16;
+        match self.sequence {
+            Some(last) if roc < self.roc || (roc == self.roc && sequence <= last) => {}
+            _ => {
+                self.roc = roc;
+                self.sequence = Some(sequence);
+            }
+        }
+        self.window.accept(index);
+    }
+}
+
+#[derive(Default)]
+struct RtcpStream {
+    next_index: u32,
+    window: ReplayWindow,
+}
+
+// One direction of an SRTP session, keyed by a master key and salt. Streams
+// are tracked per SSRC, each with its own ROC, SRTCP index and replay
+// window. Session keys are derived once, as with a key derivation rate of 0.
+pub struct Context {
+    policy: Policy,
+    rtp: SessionKeys,
+    rtcp: SessionKeys,
+    rtp_streams: HashMap<u32, RtpStream>,
+    rtcp_streams: HashMap<u32, RtcpStream>,
+}
+
+impl Context {
+    pub fn new(policy: Policy, master_key: &[u8], master_salt: &[u8]) -> Result<Context, Error> {
+        if !(4..=AUTH_KEY_LEN).contains(&policy.rtp_tag_len)
+            || !(4..=AUTH_KEY_LEN).contains(&policy.rtcp_tag_len)
+        {
+            return Err(invalid("tags are 4 to 20 bytes"));
+        }
+        let rtp = SessionKeys::derive(
+            master_key,
+            master_salt,
+            [
+                Label::RtpEncryption,
+                Label::RtpAuthentication,
+                Label::RtpSalt,
+            ],
+            policy.encryption,
+        )?;
+        let rtcp = SessionKeys::derive(
+            master_key,
+            master_salt,
+            [
+                Label::RtcpEncryption,
+                Label::RtcpAuthentication,
+                Label::RtcpSalt,
+            ],
+            policy.encryption,
+        )?;
+        Ok(Context {
+            policy,
+            rtp,
+            rtcp,
+            rtp_streams: HashMap::new(),
+            rtcp_streams: HashMap::new(),
+        })
+    }
+
+    // The ROC of a stream, which a receiver joining late must be told.
+    pub fn roc(&self, ssrc: u32) -> Option<u32> {
+        self.rtp_streams.get(&ssrc).map(|stream| stream.roc)
+    }
+
+    pub fn set_roc(&mut self, ssrc: u32, roc: u32) {
+        self.rtp_streams.entry(ssrc).or_default().roc = roc;
+    }
+
+    // Refuses to send an index twice, as that would reuse keystream.
+    pub fn protect_rtp(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let header_len = rtp_header_len(packet)?;
+        let (ssrc, sequence) = rtp_ids(packet);
+        let index = self.rtp_index(ssrc, sequence)?;
+        let (header, payload) = packet.split_at(header_len);
+        let mut output = header.to_vec();
+        output.extend(
+            self.rtp
+                .encrypt(ssrc, index, &rtp_f8_iv(header, index), payload),
+        );
+        let roc = ((index >> 16) as u32).to_be_bytes();
+        let tag = self.rtp.tag(&[&output, &roc], self.policy.rtp_tag_len);
+        output.extend(tag);
+        self.rtp_streams.entry(ssrc).or_default().accept(index);
+        Ok(output)
+    }
+
+    pub fn unprotect_rtp(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let tag_len = self.policy.rtp_tag_len;
+        if packet.len() < RTP_HEADER_LEN + tag_len {
+            return Err(invalid("packet too short"));
+        }
+        let (authenticated, tag) = packet.split_at(packet.len() - tag_len);
+        let header_len = rtp_header_len(authenticated)?;
+        let (ssrc, sequence) = rtp_ids(packet);
+        let index = self.rtp_index(ssrc, sequence)?;
+        let roc = ((index >> 16) as u32).to_be_bytes();
+        if !constant_time_eq(&self.rtp.tag(&[authenticated, &roc], tag_len), tag) {
+            return Err(Error::Authentication);
+        }
+        let (header, payload) = authenticated.split_at(header_len);
+        let mut output = header.to_vec();
+        output.extend(
+            self.rtp
+                .encrypt(ssrc, index, &rtp_f8_iv(header, index), payload),
+        );
+        self.rtp_streams.entry(ssrc).or_default().accept(index);
+        Ok(output)
+    }
+
+    pub fn protect_rtcp(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let ssrc = rtcp_ssrc(packet)?;
+        let stream = self.rtcp_streams.entry(ssrc).or_default();
+        let index = stream.next_index;
+        if index > MAX_SRTCP_INDEX {
+            return Err(invalid("SRTCP index exhausted; rekey the session"));
+        }
+        stream.next_index += 1;
+        let trailer = (ENCRYPTED_FLAG | index).to_be_bytes();
+        let (header, body) = packet.split_at(RTCP_HEADER_LEN);
+        let mut output = header.to_vec();
+        output.extend(
+            self.rtcp
+                .encrypt(ssrc, index as u64, &rtcp_f8_iv(header, trailer), body),
+        );
+        output.extend_from_slice(&trailer);
+        let tag = self.rtcp.tag(&[&output], self.policy.rtcp_tag_len);
+        output.extend(tag);
+        Ok(output)
+    }
+
+    pub fn unprotect_rtcp(&mut self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let tag_len = self.policy.rtcp_tag_len;
+        if packet.len() < RTCP_HEADER_LEN + SRTCP_TRAILER_LEN + tag_len {
+            return Err(invalid("packet too short"));
+        }
+        let ssrc = rtcp_ssrc(packet)?;
+        let (authenticated, tag) = packet.split_at(packet.len() - tag_len);
+        let (rest, trailer) = authenticated.split_at(authenticated.len() - SRTCP_TRAILER_LEN);
+        let trailer: [u8; SRTCP_TRAILER_LEN] = trailer.try_into().unwrap();
+        let word = u32::from_be_bytes(trailer);
+        let index = word & MAX_SRTCP_INDEX;
+        if let Some(stream) = self.rtcp_streams.get(&ssrc) {
+            stream.window.check(index as u64)?;
+        }
+        if !constant_time_eq(&self.rtcp.tag(&[authenticated], tag_len), tag) {
+            return Err(Error::Authentication);
+        }
+        let (header, body) = rest.split_at(RTCP_HEADER_LEN);
+        let mut output = header.to_vec();
+        if word & ENCRYPTED_FLAG != 0 {
+            output.extend(self.rtcp.encrypt(
+                ssrc,
+                index as u64,
+                &rtcp_f8_iv(header, trailer),
+                body,
+            ));
+        } else {
+            output.extend_from_slice(body);
+        }
+        self.rtcp_streams
+            .entry(ssrc)
+            .or_default()
+            .window
+            .accept(index as u64);
+        Ok(output)
+    }
+
+    // Streams are only recorded once a packet is accepted, so forged
+    // packets leave no state behind.
+    fn rtp_index(&self, ssrc: u32, sequence: u16) -> Result<u64, Error> {
+        match self.rtp_streams.get(&ssrc) {
+            Some(stream) => {
+                let index = stream.estimate(sequence);
+                stream.window.check(index)?;
+                Ok(index)
+            }
+            None => Ok(sequence as u64),
+        }
+    }
+}
+
+// The fixed header, CSRC list and any header extension.
+fn rtp_header_len(packet: &[u8]) -> Result<usize, Error> {
+    if packet.len() < RTP_HEADER_LEN || packet[0] >> 6 != RTP_VERSION {
+        return Err(invalid("not an RTP version 2 packet"));
+    }
+    let mut len = RTP_HEADER_LEN + 4 * (packet[0] & 0x0f) as usize;
+    if packet[0] & 0x10 != 0 {
+        let words = packet
+            .get(len + 2..len + 4)
+            .ok_or_else(|| invalid("truncated header extension"))?;
+        len += 4 + 4 * u16::from_be_bytes([words[0], words[1]]) as usize;
+    }
+    if len > packet.len() {
+        return Err(invalid("truncated RTP header"));
+    }
+    Ok(len)
+}
+
+fn rtp_ids(packet: &[u8]) -> (u32, u16) {
+    let ssrc = u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]);
+    (ssrc, u16::from_be_bytes([packet[2], packet[3]]))
+}
+
+fn rtcp_ssrc(packet: &[u8]) -> Result<u32, Error> {
+    if packet.len() < RTCP_HEADER_LEN || packet[0] >> 6 != RTP_VERSION {
+        return Err(invalid("not an RTCP version 2 packet"));
+    }
+    Ok(u32::from_be_bytes([
+        packet[4], packet[5], packet[6], packet[7],
+    ]))
+}
+
+// 0x00 | M, PT, sequence number, timestamp, SSRC | ROC
+fn rtp_f8_iv(header: &[u8], index: u64) -> [u8; 16] {
+    let mut iv = [0; 16];
+    iv[1..12].copy_from_slice(&header[1..RTP_HEADER_LEN]);
+    iv[12..].copy_from_slice(&((index >> 16) as u32).to_be_bytes());
+    iv
+}
+
+// 0x00000000 | E, SRTCP index | the first eight bytes of the packet
+fn rtcp_f8_iv(header: &[u8], trailer: [u8; SRTCP_TRAILER_LEN]) -> [u8; 16] {
+    let mut iv = [0; 16];
+    iv[4..8].copy_from_slice(&trailer);
+    iv[8..].copy_from_slice(&header[..RTCP_HEADER_LEN]);
+    iv
+}
+
+fn salt_value(salt: &[u8]) -> u128 {
+    salt.iter()
+        .fold(0u128, |value, &byte| (value << 8) | byte as u128)
+}
+
+fn check_master(master_key: &[u8], master_salt: &[u8]) -> Result<(), Error> {
+    if ![16, 24, 32].contains(&master_key.len()) || master_salt.len() != SALT_LEN {
+        return Err(Error::InvalidLength(format!(
+            "SRTP master keys are 16, 24 or 32 bytes and salts {}, got {} and {}",
+            SALT_LEN,
+            master_key.len(),
+            master_salt.len()
+        )));
+    }
+    Ok(())
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("SRTP: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+
+    // RFC 3711 appendix B.3
+    const MASTER_KEY: &str = "e1f97a0d3e018be0d64fa32c06de4139";
+    const MASTER_SALT: &str = "0ec675ad498afeebb6960b3aabe6";
+    const PAYLOAD: &[u8] = b"SRTP test payload, 37 bytes long....";
+
+    fn hex(text: &str) -> Vec<u8> {
+        from_hex(text).unwrap()
+    }
+
+    fn context(policy: Policy) -> Context {
+        Context::new(policy, &hex(MASTER_KEY), &hex(MASTER_SALT)).unwrap()
+    }
+
+    fn rtp(header: &str) -> Vec<u8> {
+        [&hex(header)[..], PAYLOAD].concat()
+    }
+
+    // B.2
+    #[test]
+    fn aes_cm_keystream() {
+        let cipher = BlockCipher::new(&Key::from_bytes(&hex("2b7e151628aed2a6abf7158809cf4f3c")));
+        let mut iv = [0; 16];
+        iv.copy_from_slice(&hex("f0f1f2f3f4f5f6f7f8f9fafbfcfd0000"));
+        assert_eq!(
+            to_hex(&ctr::apply(&cipher, &iv, &[0; 48])),
+            "e03ead0935c95e80e166b16dd92b4eb4d23513162b02d0f72a43a2fe4a5f97ab\
+             41e95b3bb0a2e8dd477901e4fca894c0"
+        );
+    }
+
+    // B.3
+    #[test]
+    fn key_derivation() {
+        let derive = |label, length| {
+            to_hex(&derive_key(&hex(MASTER_KEY), &hex(MASTER_SALT), label, 0, 0, length).unwrap())
+        };
+        assert_eq!(
+            derive(Label::RtpEncryption, 16),
+            "c61e7a93744f39ee10734afe3ff7a087"
+        );
+        assert_eq!(derive(Label::RtpSalt, 14), "30cbbc08863d8c85d49db34a9ae1");
+        assert_eq!(
+            derive(Label::RtpAuthentication, 20),
+            "cebe321f6ff7716b6fd4ab49af256a156d38baa4"
+        );
+        // With a rate the index is divided down before it reaches the salt.
+        assert_eq!(
+            derive_key(
+                &hex(MASTER_KEY),
+                &hex(MASTER_SALT),
+                Label::RtpEncryption,
+                0xffff,
+                0x10000,
+                16
+            )
+            .unwrap(),
+            hex("c61e7a93744f39ee10734afe3ff7a087")
+        );
+        assert!(derive_key(&[0; 16], &[0; 12], Label::RtpSalt, 0, 0, 14).is_err());
+    }
+
+    // B.1
+    #[test]
+    fn aes_f8() {
+        let key = hex("234829008467be186c3de14aae72d62c");
+        let mut masked = hex("32f2870d");
+        masked.resize(16, 0x55);
+        for (byte, k) in masked.iter_mut().zip(&key) {
+            *byte ^= k;
+        }
+        let mut iv = [0; 16];
+        iv.copy_from_slice(&hex("006e5cba50681de55c621599d462564a"));
+        let plaintext =
+            hex("70736575646f72616e646f6d6e65737320697320746865206e6578742062657374207468696e67");
+        let cipher = BlockCipher::new(&Key::from_bytes(&key));
+        let iv_cipher = BlockCipher::new(&Key::from_bytes(&masked));
+        assert_eq!(
+            to_hex(&f8(&cipher, &iv_cipher, &iv, &plaintext)),
+            "019ce7a26e7854014a6366aa95d4eefd1ad4172a14f9faf455b7f1d4b62bd08f562c0eef7c4802"
+        );
+        assert_eq!(
+            to_hex(&rtp_f8_iv(
+                &hex("806e5cba50681de55c621599"),
+                0xd462_564a << 16
+            )),
+            to_hex(&iv)
+        );
+    }
+
+    // Computed with Python's cryptography package and hmac
+    #[test]
+    fn srtp_packets() {
+        let mut sender = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        let mut receiver = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        let packet = rtp("800f1234decafbadcafebabe");
+        let protected = sender.protect_rtp(&packet).unwrap();
+        assert_eq!(
+            to_hex(&protected),
+            "800f1234decafbadcafebabeb6ac23b76c46b600532f09df465ae0c8fb3cb3d1\
+             826dc6f225c24414c300e3595acd49492131f552ebfe14f41b5e"
+        );
+        assert_eq!(receiver.unprotect_rtp(&protected).unwrap(), packet);
+        assert_eq!(receiver.unprotect_rtp(&protected), Err(Error::Replay));
+        assert_eq!(sender.protect_rtp(&packet), Err(Error::Replay));
+
+        let mut tampered = protected.clone();
+        tampered[RTP_HEADER_LEN] ^= 1;
+        let mut fresh = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        assert_eq!(fresh.unprotect_rtp(&tampered), Err(Error::Authentication));
+        // A failed packet leaves no trace in the replay window.
+        assert_eq!(fresh.unprotect_rtp(&protected).unwrap(), packet);
+    }
+
+    #[test]
+    fn rollover_counter() {
+        let mut receiver = context(Policy::AES_CM_128_HMAC_SHA1_32);
+        receiver.set_roc(0xcafe_babe, 1);
+        let protected = hex(
+            "800f0005decafbadcafebabe9582b4986c980a94b6f9efca91c0d2818ef70654\
+             3925565a221a0bdf78713606a2db63a7e1fbc047",
+        );
+        assert_eq!(
+            receiver.unprotect_rtp(&protected).unwrap(),
+            rtp("800f0005decafbadcafebabe")
+        );
+
+        // A sender crossing 0xffff moves to the next ROC.
+        let mut sender = context(Policy::AES_CM_128_HMAC_SHA1_32);
+        let mut receiver = context(Policy::AES_CM_128_HMAC_SHA1_32);
+        for sequence in [0xfffeu16, 0xffff, 0, 1] {
+            let mut packet = rtp("800f0000decafbadcafebabe");
+            packet[2..4].copy_from_slice(&sequence.to_be_bytes());
+            let protected = sender.protect_rtp(&packet).unwrap();
+            assert_eq!(protected.len(), packet.len() + 4);
+            assert_eq!(receiver.unprotect_rtp(&protected).unwrap(), packet);
+        }
+        assert_eq!(sender.roc(0xcafe_babe), Some(1));
+        assert_eq!(receiver.roc(0xcafe_babe), Some(1));
+    }
+
+    #[test]
+    fn srtcp_packets() {
+        let mut sender = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        let mut receiver = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        let packet = [&hex("81c80006cafebabe")[..], &(0..20).collect::<Vec<u8>>()].concat();
+        let protected = sender.protect_rtcp(&packet).unwrap();
+        assert_eq!(
+            to_hex(&protected),
+            "81c80006cafebabe1a368832a7c5c1d645bf2fab59121b9b3f27b0f180000000\
+             b618a9cec613288068dd"
+        );
+        assert_eq!(receiver.unprotect_rtcp(&protected).unwrap(), packet);
+        assert_eq!(receiver.unprotect_rtcp(&protected), Err(Error::Replay));
+
+        let second = sender.protect_rtcp(&packet).unwrap();
+        assert_eq!(
+            &second[second.len() - 14..second.len() - 10],
+            [0x80, 0, 0, 1]
+        );
+        let mut tampered = second.clone();
+        tampered[10] ^= 1;
+        assert_eq!(
+            receiver.unprotect_rtcp(&tampered),
+            Err(Error::Authentication)
+        );
+        assert_eq!(receiver.unprotect_rtcp(&second).unwrap(), packet);
+    }
+
+    #[test]
+    fn f8_round_trip() {
+        let mut sender = context(Policy::F8_128_HMAC_SHA1_80);
+        let mut receiver = context(Policy::F8_128_HMAC_SHA1_80);
+        // One CSRC and a one-word header extension.
+        let packet = rtp("910f00070000000100000002000000aabede000100000000");
+        let protected = sender.protect_rtp(&packet).unwrap();
+        assert_eq!(protected[..24], packet[..24]);
+        assert_ne!(protected[24..24 + PAYLOAD.len()], *PAYLOAD);
+        assert_eq!(receiver.unprotect_rtp(&protected).unwrap(), packet);
+
+        let rtcp = [&hex("80c9000100000002")[..], b"report"].concat();
+        let protected = sender.protect_rtcp(&rtcp).unwrap();
+        assert_eq!(receiver.unprotect_rtcp(&protected).unwrap(), rtcp);
+    }
+
+    #[test]
+    fn replay_window() {
+        let mut window = ReplayWindow::default();
+        for index in [100, 98, 163, 200] {
+            assert_eq!(window.check(index), Ok(()));
+            window.accept(index);
+        }
+        assert_eq!(window.check(200), Err(Error::Replay));
+        assert_eq!(window.check(163), Err(Error::Replay));
+        assert_eq!(window.check(137), Ok(()));
+        assert_eq!(window.check(136), Err(Error::Replay));
+        assert_eq!(window.check(201), Ok(()));
+        window.accept(1);
+        assert_eq!(window.check(137), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let mut context = context(Policy::AES_CM_128_HMAC_SHA1_80);
+        let mut version = rtp("800f1234decafbadcafebabe");
+        version[0] = 0x40;
+        let mut extension = rtp("900f1234decafbadcafebabe");
+        extension[14..16].copy_from_slice(&[0xff, 0xff]);
+        for packet in [version, extension, hex("800f1234")] {
+            assert!(matches!(
+                context.protect_rtp(&packet),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        assert!(context
+            .unprotect_rtcp(&hex("81c80006cafebabe80000000"))
+            .is_err());
+        assert!(Context::new(Policy::AES_CM_128_HMAC_SHA1_80, &[0; 15], &[0; 14]).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::aes::Key;
+use crate::error::Error;
+use crate::hash::Sha256;
+use crate::kdf::hkdf;
+use crate::modes::ccm::Ccm;
+use crate::modes::gcm::Gcm;
+
+// TLS 1.3 record protection, RFC 8446 section 5.2. A protected record is
+//   23 | 0x0303 | length (u16) | AEAD(content | real type | zero padding)
+// with the five header bytes as associated data and the per-record nonce
+// being the write IV XORed with the 64-bit record sequence number. Only the
+// SHA-256 suites are provided; TLS_AES_256_GCM_SHA384 would need SHA-384.
+pub const HEADER_LEN: usize = 5;
+pub const IV_LEN: usize = 12;
+pub const MAX_PLAINTEXT_LEN: usize = 1 << 14;
+// Content type, padding and tag may add up to 256 bytes.
+pub const MAX_CIPHERTEXT_LEN: usize = MAX_PLAINTEXT_LEN + 256;
+const LEGACY_VERSION: [u8; 2] = [3, 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128GcmSha256,
+    Aes128CcmSha256,
+    // CCM with an 8-byte tag.
+    Aes128Ccm8Sha256,
+}
+
+impl CipherSuite {
+    pub fn id(&self) -> u16 {
+        match self {
+            CipherSuite::Aes128GcmSha256 => 0x1301,
+            CipherSuite::Aes128CcmSha256 => 0x1304,
+            CipherSuite::Aes128Ccm8Sha256 => 0x1305,
+        }
+    }
+
+    pub fn key_len(&self) -> usize {
+        16
+    }
+
+    fn tag_len(&self) -> usize {
+        match self {
+            CipherSuite::Aes128Ccm8Sha256 => 8,
+            _ => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    ChangeCipherSpec,
+    Alert,
+    Handshake,
+    ApplicationData,
+}
+
+impl ContentType {
+    pub fn code(&self) -> u8 {
+        match self {
+            ContentType::ChangeCipherSpec => 20,
+            ContentType::Alert => 21,
+            ContentType::Handshake => 22,
+            ContentType::ApplicationData => 23,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Result<ContentType, Error> {
+        match code {
+            20 => Ok(ContentType::ChangeCipherSpec),
+            21 => Ok(ContentType::Alert),
+            22 => Ok(ContentType::Handshake),
+            23 => Ok(ContentType::ApplicationData),
+            _ => Err(invalid(&format!("unknown content type {}", code))),
+        }
+    }
+}
+
+// HKDF-Expand-Label, RFC 8446 section 7.1, over SHA-256. The label is given
+// without the "tls13 " prefix.
+pub fn expand_label(
+    secret: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    let full_label = [b"tls13 ", label.as_bytes()].concat();
+    if full_label.len() > 255 || context.len() > 255 || length > u16::MAX as usize {
+        return Err(invalid("HKDF label, context or length too long"));
+    }
+    let mut info = (length as u16).to_be_bytes().to_vec();
+    info.push(full_label.len() as u8);
+    info.extend_from_slice(&full_label);
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    hkdf::expand::<Sha256>(secret, &info, length).map_err(Error::InvalidInput)
+}
+
+// Derive-Secret, taking the transcript hash rather than the messages.
+pub fn derive_secret(secret: &[u8], label: &str, transcript_hash: &[u8]) -> Result<Vec<u8>, Error> {
+    expand_label(secret, label, transcript_hash, 32)
+}
+
+// The application traffic secret that follows a KeyUpdate.
+pub fn next_traffic_secret(secret: &[u8]) -> Result<Vec<u8>, Error> {
+    expand_label(secret, "traffic upd", &[], 32)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrafficKeys {
+    pub key: Vec<u8>,
+    pub iv: [u8; IV_LEN],
+}
+
+impl TrafficKeys {
+    pub fn derive(suite: CipherSuite, secret: &[u8]) -> Result<TrafficKeys, Error> {
+        let mut iv = [0; IV_LEN];
+        iv.copy_from_slice(&expand_label(secret, "iv", &[], IV_LEN)?);
+        Ok(TrafficKeys {
+            key: expand_label(secret, "key", &[], suite.key_len())?,
+            iv,
+        })
+    }
+}
+
+enum Aead {
+    Gcm(Gcm),
+    Ccm(Ccm),
+}
+
+// One direction of a connection: records must be sealed or opened in order,
+// as each one uses the next sequence number.
+pub struct RecordProtection {
+    aead: Aead,
+    iv: [u8; IV_LEN],
+    sequence: u64,
+}
+
+impl RecordProtection {
+    pub fn new(suite: CipherSuite, keys: &TrafficKeys) -> Result<RecordProtection, Error> {
+        if keys.key.len() != suite.key_len() {
+            return Err(Error::InvalidLength(format!(
+                "TLS: {:?} keys are {} bytes, got {}",
+                suite,
+                suite.key_len(),
+                keys.key.len()
+            )));
+        }
+        let key = Key::from_bytes(&keys.key);
+        let aead = match suite {
+            CipherSuite::Aes128GcmSha256 => Aead::Gcm(Gcm::new(&key)),
+            _ => Aead::Ccm(Ccm::new(&key, suite.tag_len())?),
+        };
+        Ok(RecordProtection {
+            aead,
+            iv: keys.iv,
+            sequence: 0,
+        })
+    }
+
+    pub fn from_secret(suite: CipherSuite, secret: &[u8]) -> Result<RecordProtection, Error> {
+        RecordProtection::new(suite, &TrafficKeys::derive(suite, secret)?)
+    }
+
+    // The sequence number of the next record.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    // Returns the whole record, header included. `padding` zero bytes are
+    // added inside the encryption to hide the content length.
+    pub fn seal(
+        &mut self,
+        content_type: ContentType,
+        content: &[u8],
+        padding: usize,
+    ) -> Result<Vec<u8>, Error> {
+        if content.len() + padding > MAX_PLAINTEXT_LEN {
+            return Err(Error::InvalidLength(format!(
+                "TLS: content and padding exceed {} bytes",
+                MAX_PLAINTEXT_LEN
+            )));
+        }
+        let mut inner = content.to_vec();
+        inner.push(content_type.code());
+        inner.resize(inner.len() + padding, 0);
+        let header = header(inner.len() + self.tag_len());
+        let nonce = self.next_nonce()?;
+        let sealed = match &self.aead {
+            Aead::Gcm(gcm) => gcm.seal(&nonce, &header, &inner)?,
+            Aead::Ccm(ccm) => ccm.seal(&nonce, &header, &inner)?,
+        };
+        Ok([&header[..], &sealed].concat())
+    }
+
+    // Takes exactly one record, as split off with `record_len`.
+    pub fn open(&mut self, record: &[u8]) -> Result<(ContentType, Vec<u8>), Error> {
+        if record_len(record) != Some(record.len()) {
+            return Err(invalid("expected exactly one record"));
+        }
+        if record[0] != ContentType::ApplicationData.code() || record[1..3] != LEGACY_VERSION {
+            return Err(invalid("not a protected record"));
+        }
+        let sealed = &record[HEADER_LEN..];
+        if sealed.len() > MAX_CIPHERTEXT_LEN {
+            return Err(invalid("record overflow"));
+        }
+        let nonce = self.nonce()?;
+        let mut inner = match &self.aead {
+            Aead::Gcm(gcm) => gcm.open(&nonce, &record[..HEADER_LEN], sealed)?,
+            Aead::Ccm(ccm) => ccm.open(&nonce, &record[..HEADER_LEN], sealed)?,
+        };
+        self.sequence += 1;
+        let end = inner
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .ok_or_else(|| invalid("record has no content type"))?;
+        let content_type = ContentType::from_code(inner[end])?;
+        inner.truncate(end);
+        if inner.len() > MAX_PLAINTEXT_LEN {
+            return Err(invalid("record overflow"));
+        }
+        Ok((content_type, inner))
+    }
+
+    fn tag_len(&self) -> usize {
+        match &self.aead {
+            Aead::Gcm(_) => 16,
+            Aead::Ccm(ccm) => ccm.tag_len(),
+        }
+    }
+
+    fn nonce(&self) -> Result<[u8; IV_LEN], Error> {
+        // The sequence number must not wrap; the connection needs new keys.
+        if self.sequence == u64::MAX {
+            return Err(invalid("record sequence number exhausted"));
+        }
+        let mut nonce = self.iv;
+        for (byte, sequence) in nonce[4..].iter_mut().zip(self.sequence.to_be_bytes()) {
+            *byte ^= sequence;
+        }
+        Ok(nonce)
+    }
+
+    fn next_nonce(&mut self) -> Result<[u8; IV_LEN], Error> {
+        let nonce = self.nonce()?;
+        self.sequence += 1;
+        Ok(nonce)
+    }
+}
+
+// The length of the first complete record in `data`, header included.
+pub fn record_len(data: &[u8]) -> Option<usize> {
+    let header = data.get(..HEADER_LEN)?;
+    let len = HEADER_LEN + u16::from_be_bytes([header[3], header[4]]) as usize;
+    (data.len() >= len).then_some(len)
+}
+
+fn header(len: usize) -> [u8; HEADER_LEN] {
+    let len = (len as u16).to_be_bytes();
+    [
+        ContentType::ApplicationData.code(),
+        LEGACY_VERSION[0],
+        LEGACY_VERSION[1],
+        len[0],
+        len[1],
+    ]
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidInput(format!("TLS: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_hex, to_hex};
+    use crate::hash::Hash;
+
+    // RFC 8448 section 3, "Simple 1-RTT Handshake"
+    const SHARED_SECRET: &str = "8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d";
+    const SERVER_HANDSHAKE_SECRET: &str =
+        "b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38";
+    const CLIENT_HANDSHAKE_SECRET: &str =
+        "b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21";
+    const SERVER_APPLICATION_SECRET: &str =
+        "a11af9f05531f856ad47116b45a950328204b4f44bfb6b3a4b4f1f3fcb631643";
+    const CLIENT_FINISHED: &str =
+        "14000020a8ec436d677634ae525ac1fcebe11a039ec17694fac6e98527b642f2edd5ce61";
+    const CLIENT_FINISHED_RECORD: &str =
+        "170303003575ec4dc238cce60b298044a71e219c56cc77b0517fe9b93c7a4bfc44d87f38f80338ac98fc\
+         46deb384bd1caeacab6867d726c40546";
+    const CLIENT_APPLICATION_RECORD: &str =
+        "1703030043a23f7054b62c94d0affafe8228ba55cbefacea42f914aa66bcab3f2b9819a8a5b46b395bd5\
+         4a9a20441e2b62974e1f5a6292a2977014bd1e3deae63aeebb21694915e4";
+    const SERVER_APPLICATION_RECORD: &str =
+        "17030300432e937e11ef4ac740e538ad36005fc4a46932fc3225d05f82aa1b36e30efaf97d90e6dffc60\
+         2dcb501a59a8fcc49c4bf2e5f0a21c0047c2abf332540dd032e167c2955d";
+
+    fn hex(text: &str) -> Vec<u8> {
+        from_hex(&text.replace(' ', "")).unwrap()
+    }
+
+    fn keys(key: &str, iv: &str) -> TrafficKeys {
+        let mut iv_bytes = [0; IV_LEN];
+        iv_bytes.copy_from_slice(&hex(iv));
+        TrafficKeys {
+            key: hex(key),
+            iv: iv_bytes,
+        }
+    }
+
+    #[test]
+    fn key_schedule() {
+        let early = hkdf::extract::<Sha256>(&[], &[0; 32]);
+        assert_eq!(
+            to_hex(&early),
+            "33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a"
+        );
+        let derived = derive_secret(&early, "derived", &Sha256::digest(b"")).unwrap();
+        assert_eq!(
+            to_hex(&derived),
+            "6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba"
+        );
+        assert_eq!(
+            to_hex(&hkdf::extract::<Sha256>(&derived, &hex(SHARED_SECRET))),
+            "1dc826e93606aa6fdc0aadc12f741b01046aa6b99f691ed221a9f0ca043fbeac"
+        );
+
+        let suite = CipherSuite::Aes128GcmSha256;
+        for (secret, expected) in [
+            (
+                SERVER_HANDSHAKE_SECRET,
+                keys(
+                    "3fce516009c21727d0f2e4e86ee403bc",
+                    "5d313eb2671276ee13000b30",
+                ),
+            ),
+            (
+                CLIENT_HANDSHAKE_SECRET,
+                keys(
+                    "dbfaa693d1762c5b666af5d950258d01",
+                    "5bd3c71b836e0b76bb73265f",
+                ),
+            ),
+            (
+                SERVER_APPLICATION_SECRET,
+                keys(
+                    "9f02283b6c9c07efc26bb9f2ac92e356",
+                    "cf782b88dd83549aadf1e984",
+                ),
+            ),
+        ] {
+            assert_eq!(TrafficKeys::derive(suite, &hex(secret)).unwrap(), expected);
+        }
+        assert_eq!(
+            to_hex(&next_traffic_secret(&hex(SERVER_APPLICATION_SECRET)).unwrap()),
+            "51921b8aa3001976eb401d0a4319a8516416a6c56001a357e5d162031e84f916"
+        );
+    }
+
+    #[test]
+    fn rfc8448_records() {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let mut client =
+            RecordProtection::from_secret(suite, &hex(CLIENT_HANDSHAKE_SECRET)).unwrap();
+        let record = client
+            .seal(ContentType::Handshake, &hex(CLIENT_FINISHED), 0)
+            .unwrap();
+        assert_eq!(to_hex(&record), CLIENT_FINISHED_RECORD);
+        assert_eq!(client.sequence(), 1);
+        let mut server =
+            RecordProtection::from_secret(suite, &hex(SERVER_HANDSHAKE_SECRET)).unwrap();
+        assert_eq!(server.open(&record), Err(Error::Authentication));
+        let mut server =
+            RecordProtection::from_secret(suite, &hex(CLIENT_HANDSHAKE_SECRET)).unwrap();
+        assert_eq!(
+            server.open(&record).unwrap(),
+            (ContentType::Handshake, hex(CLIENT_FINISHED))
+        );
+
+        // Both sides send 50 bytes of application data; the server's follows
+        // a NewSessionTicket, so it is its second record.
+        let data: Vec<u8> = (0..50).collect();
+        let mut client = RecordProtection::new(
+            suite,
+            &keys(
+                "17422dda596ed5d9acd890e3c63f5051",
+                "5b78923dee08579033e523d9",
+            ),
+        )
+        .unwrap();
+        let record = client.seal(ContentType::ApplicationData, &data, 0).unwrap();
+        assert_eq!(to_hex(&record), CLIENT_APPLICATION_RECORD);
+
+        let mut server =
+            RecordProtection::from_secret(suite, &hex(SERVER_APPLICATION_SECRET)).unwrap();
+        server.sequence = 1;
+        let record = hex(SERVER_APPLICATION_RECORD);
+        assert_eq!(
+            server.open(&record).unwrap(),
+            (ContentType::ApplicationData, data)
+        );
+        assert_eq!(server.open(&record), Err(Error::Authentication));
+    }
+
+    // Computed with Python's cryptography AESCCM
+    #[test]
+    fn ccm_records() {
+        let keys = TrafficKeys::derive(
+            CipherSuite::Aes128GcmSha256,
+            &hex(SERVER_APPLICATION_SECRET),
+        )
+        .unwrap();
+        let mut ccm = RecordProtection::new(CipherSuite::Aes128CcmSha256, &keys).unwrap();
+        assert_eq!(
+            to_hex(
+                &ccm.seal(ContentType::ApplicationData, b"hello, record layer", 4)
+                    .unwrap()
+            ),
+            "1703030028c6a36ee61c1ce549e6ca9b7ac21b6c7a3aa70ece23f08c17050b2f\
+             748f8ecbc349936433a2f8bf4b"
+        );
+        let mut ccm8 = RecordProtection::new(CipherSuite::Aes128Ccm8Sha256, &keys).unwrap();
+        ccm8.sequence = 5;
+        let record = ccm8.seal(ContentType::Alert, &[1, 0], 0).unwrap();
+        assert_eq!(to_hex(&record), "170303000b9455ace7c95cc24cdf5f10");
+
+        let mut reader = RecordProtection::new(CipherSuite::Aes128Ccm8Sha256, &keys).unwrap();
+        reader.sequence = 5;
+        assert_eq!(
+            reader.open(&record).unwrap(),
+            (ContentType::Alert, vec![1, 0])
+        );
+    }
+
+    #[test]
+    fn padding_and_framing() {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let secret = [3; 32];
+        let mut writer = RecordProtection::from_secret(suite, &secret).unwrap();
+        let mut reader = RecordProtection::from_secret(suite, &secret).unwrap();
+        let padded = writer.seal(ContentType::ApplicationData, b"", 100).unwrap();
+        assert_eq!(padded.len(), HEADER_LEN + 1 + 100 + 16);
+        let next = writer.seal(ContentType::Handshake, b"hi", 0).unwrap();
+
+        let stream = [&padded[..], &next, &next[..3]].concat();
+        let first = record_len(&stream).unwrap();
+        assert_eq!(
+            reader.open(&stream[..first]).unwrap(),
+            (ContentType::ApplicationData, Vec::new())
+        );
+        let second = record_len(&stream[first..]).unwrap();
+        assert_eq!(record_len(&stream[first + second..]), None);
+        assert_eq!(
+            reader.open(&stream[first..first + second]).unwrap(),
+            (ContentType::Handshake, b"hi".to_vec())
+        );
+
+        assert!(writer
+            .seal(ContentType::ApplicationData, &[0; MAX_PLAINTEXT_LEN], 1)
+            .is_err());
+        let mut bad_header = next.clone();
+        bad_header[0] = 22;
+        assert!(matches!(
+            reader.open(&bad_header),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            reader.open(&next[..next.len() - 1]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_inner_plaintext() {
+        let keys = TrafficKeys::derive(CipherSuite::Aes128GcmSha256, &[4; 32]).unwrap();
+        let gcm = Gcm::new(&Key::from_bytes(&keys.key));
+        let inner = [0; 8];
+        let header = header(inner.len() + 16);
+        let record = [&header[..], &gcm.seal(&keys.iv, &header, &inner).unwrap()].concat();
+        let mut reader = RecordProtection::new(CipherSuite::Aes128GcmSha256, &keys).unwrap();
+        assert!(matches!(reader.open(&record), Err(Error::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,7 @@
+pub use self::ctr_drbg::{CtrDrbg, EntropySource};
+pub use self::os::os_random;
+pub use self::source::{OsRng, RngSource, SeededRng};
+
+mod ctr_drbg;
+mod os;
+mod source;
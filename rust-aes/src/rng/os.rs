@@ -0,0 +1,73 @@
+use crate::error::Error;
+
+#[cfg(target_os = "linux")]
+pub fn os_random(buf: &mut [u8]) -> Result<(), Error> {
+    extern "C" {
+        fn getrandom(buf: *mut u8, buflen: usize, flags: u32) -> isize;
+    }
+    let mut filled = 0;
+    while filled < buf.len() {
+        let remaining = &mut buf[filled..];
+        let read = unsafe { getrandom(remaining.as_mut_ptr(), remaining.len(), 0) };
+        if read < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return dev_urandom(&mut buf[filled..]);
+        }
+        filled += read as usize;
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn os_random(buf: &mut [u8]) -> Result<(), Error> {
+    dev_urandom(buf)
+}
+
+#[cfg(unix)]
+fn dev_urandom(buf: &mut [u8]) -> Result<(), Error> {
+    use std::io::Read;
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(buf))
+        .map_err(|e| Error::Entropy(e.to_string()))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn os_random(buf: &mut [u8]) -> Result<(), Error> {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = crypto, js_name = getRandomValues)]
+        fn get_random_values(buf: &mut [u8]);
+    }
+    // getRandomValues accepts at most 65536 bytes per call
+    for chunk in buf.chunks_mut(65536) {
+        get_random_values(chunk);
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, target_arch = "wasm32")))]
+pub fn os_random(_buf: &mut [u8]) -> Result<(), Error> {
+    Err(Error::Entropy(
+        "no OS entropy source on this platform".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_buffer() {
+        let mut a = [0; 64];
+        let mut b = [0; 64];
+        os_random(&mut a).unwrap();
+        os_random(&mut b).unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, [0; 64]);
+    }
+}
@@ -0,0 +1,88 @@
+use super::{os_random, CtrDrbg};
+use crate::aes::AesSize;
+use crate::error::Error;
+use crate::hash::{Hash, Sha256};
+
+pub trait RngSource {
+    fn fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+
+    fn random_block(&mut self) -> Result<[u8; 16], Error> {
+        let mut block = [0; 16];
+        self.fill_bytes(&mut block)?;
+        Ok(block)
+    }
+
+    fn random_vec(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![0; len];
+        self.fill_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+pub struct OsRng;
+
+impl RngSource for OsRng {
+    fn fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        os_random(buf)
+    }
+}
+
+impl RngSource for CtrDrbg {
+    fn fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        CtrDrbg::fill_bytes(self, buf)
+    }
+}
+
+// Reproducible output for tests: a CTR_DRBG whose entropy input is a hash
+// chain over the seed instead of the OS source. Never use it for real data.
+pub struct SeededRng {
+    drbg: CtrDrbg,
+}
+
+impl SeededRng {
+    pub fn new(seed: &[u8]) -> Self {
+        let mut state = Sha256::digest(seed);
+        let entropy = Box::new(move |buf: &mut [u8]| {
+            for chunk in buf.chunks_mut(32) {
+                state = Sha256::digest(&state);
+                chunk.copy_from_slice(&state[..chunk.len()]);
+            }
+            Ok(())
+        });
+        let drbg = CtrDrbg::instantiate(AesSize::S128, entropy, &[], b"aeslib seeded rng")
+            .expect("seeded entropy source cannot fail");
+        SeededRng { drbg }
+    }
+
+    pub fn from_u64(seed: u64) -> Self {
+        SeededRng::new(&seed.to_be_bytes())
+    }
+}
+
+impl RngSource for SeededRng {
+    fn fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.drbg.fill_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_is_reproducible() {
+        let mut a = SeededRng::from_u64(42);
+        let mut b = SeededRng::from_u64(42);
+        let mut c = SeededRng::from_u64(43);
+        let first = a.random_vec(100).unwrap();
+        assert_eq!(first, b.random_vec(100).unwrap());
+        assert_ne!(first, c.random_vec(100).unwrap());
+        assert_ne!(first, a.random_vec(100).unwrap());
+    }
+
+    #[test]
+    fn os_rng() {
+        let mut rng = OsRng;
+        assert_ne!(rng.random_block().unwrap(), rng.random_block().unwrap());
+    }
+}